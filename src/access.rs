@@ -0,0 +1,213 @@
+//! Per-client access control.
+//!
+//! The config file's `access` section maps client identities to the caches
+//! and tools they may use:
+//!
+//! ```json
+//! {
+//!   "access": {
+//!     "clients": [
+//!       { "token": "ci-bot", "caches": ["docs"] },
+//!       { "certificate": "hr-gateway", "caches": ["hr"] },
+//!       { "uid": 1001, "caches": ["engineering", "docs"] },
+//!       { "client_name": "hr-assistant", "caches": ["hr"], "tools": ["context.resolve"] }
+//!     ],
+//!     "default": { "caches": ["docs"] }
+//!   }
+//! }
+//! ```
+//!
+//! A rule matches one identity: the name of the bearer token or the client
+//! certificate common name a TCP client authenticated with (see
+//! [`crate::auth`]), a Unix socket peer uid, or a stdio client's
+//! `clientInfo.name`. A client gets the union of every rule matching its
+//! identity, or `default` when none does; without `default`, unmatched
+//! clients may use nothing. `"*"` grants every cache, and omitting `tools`
+//! grants every tool. Tools that see the whole cache root (`context.doctor`)
+//! also need every cache. Without an `access` section, every client may use
+//! everything.
+
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+
+/// Cache pattern granting every cache.
+pub const ALL_CACHES: &str = "*";
+
+/// Tools that report on every cache under the root.
+pub const ROOT_TOOLS: &[&str] = &["context.doctor"];
+
+/// Who is on the other end of a session, as far as the transport can tell.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientIdentity {
+    /// How a TCP client authenticated.
+    pub principal: Option<Principal>,
+    /// Peer uid of a Unix socket connection.
+    pub uid: Option<u32>,
+    /// `clientInfo.name` from `initialize`. Only set on stdio, where the
+    /// client is the process that launched the server.
+    pub client_name: Option<String>,
+}
+
+/// An authenticated TCP client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principal {
+    /// Name of the bearer token the client presented.
+    Token(String),
+    /// Subject common name of the client's verified certificate.
+    Certificate(String),
+}
+
+/// The config file's `access` section.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessConfig {
+    #[serde(default)]
+    pub clients: Vec<AccessRule>,
+    /// Grant for clients no rule matches.
+    #[serde(default)]
+    pub default: Option<Grant>,
+}
+
+/// Caches and tools granted to the clients one identity matches.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessRule {
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub certificate: Option<String>,
+    #[serde(default)]
+    pub uid: Option<u32>,
+    #[serde(default)]
+    pub client_name: Option<String>,
+    pub caches: Vec<String>,
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+}
+
+impl AccessRule {
+    fn matches(&self, client: &ClientIdentity) -> bool {
+        match (&self.token, &self.certificate, &self.uid, &self.client_name) {
+            (Some(token), ..) => client.principal == Some(Principal::Token(token.clone())),
+            (_, Some(name), ..) => client.principal == Some(Principal::Certificate(name.clone())),
+            (_, _, Some(uid), _) => client.uid == Some(*uid),
+            (_, _, _, Some(name)) => client.client_name.as_ref() == Some(name),
+            _ => false,
+        }
+    }
+}
+
+/// Caches and tools, as written in the config.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Grant {
+    pub caches: Vec<String>,
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+}
+
+/// The server's access policy.
+#[derive(Debug, Clone, Default)]
+pub struct AccessPolicy {
+    config: Option<AccessConfig>,
+}
+
+impl AccessPolicy {
+    /// A policy enforcing `config`. Every rule must name exactly one
+    /// identity.
+    pub fn new(config: AccessConfig) -> Result<Self, String> {
+        for (i, rule) in config.clients.iter().enumerate() {
+            let matchers = [
+                rule.token.is_some(),
+                rule.certificate.is_some(),
+                rule.uid.is_some(),
+                rule.client_name.is_some(),
+            ];
+            if matchers.iter().filter(|m| **m).count() != 1 {
+                return Err(format!(
+                    "access rule {i} must match exactly one of token, certificate, uid, or client_name"
+                ));
+            }
+        }
+        Ok(Self {
+            config: Some(config),
+        })
+    }
+
+    /// What `client` may use.
+    pub fn permissions(&self, client: &ClientIdentity) -> Permissions {
+        let Some(config) = &self.config else {
+            return Permissions::all();
+        };
+
+        let matching: Vec<(&[String], Option<&[String]>)> = config
+            .clients
+            .iter()
+            .filter(|rule| rule.matches(client))
+            .map(|rule| (rule.caches.as_slice(), rule.tools.as_deref()))
+            .collect();
+        let grants = if matching.is_empty() {
+            config
+                .default
+                .iter()
+                .map(|grant| (grant.caches.as_slice(), grant.tools.as_deref()))
+                .collect()
+        } else {
+            matching
+        };
+
+        let mut permissions = Permissions::none();
+        for (caches, tools) in grants {
+            if caches.iter().any(|cache| cache == ALL_CACHES) {
+                permissions.caches = None;
+            } else if let Some(granted) = &mut permissions.caches {
+                granted.extend(caches.iter().cloned());
+            }
+            match tools {
+                None => permissions.tools = None,
+                Some(tools) => {
+                    if let Some(granted) = &mut permissions.tools {
+                        granted.extend(tools.iter().cloned());
+                    }
+                }
+            }
+        }
+        permissions
+    }
+}
+
+/// The caches and tools one client may use. `None` means all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permissions {
+    caches: Option<BTreeSet<String>>,
+    tools: Option<BTreeSet<String>>,
+}
+
+impl Permissions {
+    /// Every cache and tool.
+    pub fn all() -> Self {
+        Self {
+            caches: None,
+            tools: None,
+        }
+    }
+
+    /// No cache or tool.
+    pub fn none() -> Self {
+        Self {
+            caches: Some(BTreeSet::new()),
+            tools: Some(BTreeSet::new()),
+        }
+    }
+
+    pub fn allows_cache(&self, cache: &str) -> bool {
+        self.caches.as_ref().map_or(true, |caches| caches.contains(cache))
+    }
+
+    /// Whether `tool` may be called. Root-wide tools also need every cache.
+    pub fn allows_tool(&self, tool: &str) -> bool {
+        let granted = self.tools.as_ref().map_or(true, |tools| tools.contains(tool));
+        granted && (self.caches.is_none() || !ROOT_TOOLS.contains(&tool))
+    }
+}
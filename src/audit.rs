@@ -0,0 +1,417 @@
+//! Append-only audit log of tool calls.
+//!
+//! With an `audit` section in the config file, every `tools/call` a session
+//! makes is appended to a JSON Lines file:
+//!
+//! ```json
+//! {
+//!   "audit": { "path": "logs/audit.jsonl", "query": "hash", "max_bytes": 67108864, "keep": 10 }
+//! }
+//! ```
+//!
+//! An entry records when the call finished, the client (`clientInfo` from
+//! `initialize` and the identity the transport established), the tool, the
+//! cache (by the name it resolved to) and the `cache_version` the tool
+//! read, the query (its SHA-256 by default, or the full text with
+//! `"query": "full"`), the budget, the documents a `context.resolve`
+//! selected, the outcome code, and the duration.
+//!
+//! Entries are chained: `prev_hash` is the previous entry's `hash`, and
+//! `hash` is the SHA-256 of the entry serialized without `hash`, so editing,
+//! dropping, or reordering lines breaks the chain ([`verify`]). When the file
+//! would grow past `max_bytes` it is renamed to `<path>.1` (shifting older
+//! files up to `<path>.<keep>` and deleting the oldest) and a new file is
+//! started; the chain carries over.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{error, warn};
+
+use crate::access::{ClientIdentity, Principal};
+use crate::handlers::CallRecord;
+use crate::protocol::{ClientInfo, JsonRpcResponse, McpErrorResponse};
+
+/// Default size at which the log is rotated (64 MiB).
+pub const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Default number of rotated files kept.
+pub const DEFAULT_KEEP: usize = 10;
+
+/// The config file's `audit` section.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    /// Log file. Relative paths are resolved against the config file's
+    /// directory.
+    pub path: PathBuf,
+    #[serde(default)]
+    pub query: QueryRecord,
+    #[serde(default = "AuditConfig::default_max_bytes")]
+    pub max_bytes: u64,
+    #[serde(default = "AuditConfig::default_keep")]
+    pub keep: usize,
+}
+
+impl AuditConfig {
+    fn default_max_bytes() -> u64 {
+        DEFAULT_MAX_BYTES
+    }
+
+    fn default_keep() -> usize {
+        DEFAULT_KEEP
+    }
+}
+
+/// How queries are recorded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryRecord {
+    /// `query_sha256`: the SHA-256 of the query text.
+    #[default]
+    Hash,
+    /// `query`: the query text itself.
+    Full,
+}
+
+/// A shared handle to the audit log. Clones append to the same file.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    query: QueryRecord,
+    max_bytes: u64,
+    keep: usize,
+    writer: Arc<Mutex<Writer>>,
+}
+
+/// The open file and the end of the chain. The file is opened on the first
+/// entry, so loading a config does not create it.
+#[derive(Debug, Default)]
+struct Writer {
+    file: Option<File>,
+    size: u64,
+    last_hash: Option<String>,
+}
+
+/// One line of the log, before it is hashed.
+#[derive(Debug, Serialize)]
+struct Entry {
+    timestamp: String,
+    client: AuditClient,
+    tool: String,
+    cache: Option<String>,
+    /// For diff tools, the `base` cache compared against `cache`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_cache: Option<String>,
+    cache_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query_sha256: Option<String>,
+    budget: Option<serde_json::Value>,
+    documents: Option<Vec<DocumentRef>>,
+    outcome: String,
+    duration_ms: u64,
+    prev_hash: Option<String>,
+}
+
+/// The client as recorded in an entry.
+#[derive(Debug, Serialize)]
+struct AuditClient {
+    /// `clientInfo.name` from `initialize`, as the client sent it.
+    name: Option<String>,
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uid: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DocumentRef {
+    id: String,
+    version: String,
+}
+
+impl AuditLog {
+    /// A log as `config` describes it, resolving a relative path against
+    /// `base_dir`.
+    pub fn new(config: &AuditConfig, base_dir: &Path) -> Self {
+        Self {
+            path: base_dir.join(&config.path),
+            query: config.query,
+            max_bytes: config.max_bytes,
+            keep: config.keep,
+            writer: Arc::default(),
+        }
+    }
+
+    /// The log file and its rotated predecessors that exist, oldest first.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = (1..=self.keep)
+            .rev()
+            .map(|n| rotated(&self.path, n))
+            .filter(|path| path.exists())
+            .collect();
+        if self.path.exists() {
+            files.push(self.path.clone());
+        }
+        files
+    }
+
+    /// Append an entry for a `tools/call` dispatched as `call` describes
+    /// that got `response` after `duration`. The file is written on a
+    /// blocking thread. Failures to write are logged; the call itself is
+    /// unaffected.
+    pub async fn record_call(
+        &self,
+        client: &ClientIdentity,
+        client_info: Option<&ClientInfo>,
+        call: &CallRecord,
+        response: Option<&JsonRpcResponse>,
+        duration: Duration,
+    ) {
+        let params = call.params.as_ref();
+        let arguments = params.and_then(|params| params.arguments.as_ref());
+        let argument = |name: &str| arguments.and_then(|args| args.get(name));
+        let cache = argument("cache").or_else(|| argument("target")).and_then(|v| v.as_str());
+        let base_cache = argument("base").and_then(|v| v.as_str());
+        let query = argument("query").and_then(|v| v.as_str());
+
+        let (outcome, output) = outcome(response);
+        let tool = params.map_or("", |params| params.name.as_str());
+        let documents = output
+            .filter(|_| tool == "context.resolve")
+            .and_then(|output| serde_json::from_value(output["documents"].clone()).ok());
+
+        let (token, certificate) = match &client.principal {
+            Some(Principal::Token(name)) => (Some(name.clone()), None),
+            Some(Principal::Certificate(name)) => (None, Some(name.clone())),
+            None => (None, None),
+        };
+        let entry = Entry {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            client: AuditClient {
+                name: client_info.and_then(|info| info.name.clone()),
+                version: client_info.and_then(|info| info.version.clone()),
+                token,
+                certificate,
+                uid: client.uid,
+            },
+            tool: tool.to_string(),
+            cache: cache.map(str::to_string),
+            base_cache: base_cache.map(str::to_string),
+            cache_version: call.cache_version.clone().filter(|_| outcome == "ok"),
+            query: query.filter(|_| self.query == QueryRecord::Full).map(str::to_string),
+            query_sha256: query.filter(|_| self.query == QueryRecord::Hash).map(|q| sha256(q.as_bytes())),
+            budget: argument("budget").cloned(),
+            documents,
+            outcome,
+            duration_ms: duration.as_millis() as u64,
+            prev_hash: None,
+        };
+
+        let log = self.clone();
+        let span = tracing::Span::current();
+        let written = tokio::task::spawn_blocking(move || span.in_scope(|| log.append(entry))).await;
+        match written {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!(error = %e, "Cannot write audit log"),
+            Err(e) => error!(error = %e, "Audit log task failed"),
+        }
+    }
+
+    fn append(&self, mut entry: Entry) -> std::io::Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if writer.file.is_none() {
+            *writer = self.open()?;
+        }
+
+        entry.prev_hash = writer.last_hash.clone();
+        let mut value = serde_json::to_value(&entry)?;
+        let hash = sha256(serde_json::to_string(&value)?.as_bytes());
+        value["hash"] = serde_json::Value::String(hash.clone());
+        let mut line = serde_json::to_string(&value)?;
+        line.push('\n');
+
+        if writer.size > 0 && writer.size + line.len() as u64 > self.max_bytes {
+            writer.file = None;
+            self.rotate()?;
+            writer.file = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+            writer.size = 0;
+        }
+
+        let result = writer.file.as_mut().expect("audit log is open").write_all(line.as_bytes());
+        if let Err(e) = result {
+            // Reopen on the next entry rather than writing after a partial line.
+            writer.file = None;
+            return Err(e);
+        }
+        writer.size += line.len() as u64;
+        writer.last_hash = Some(hash);
+        Ok(())
+    }
+
+    /// Open the log for appending and pick up the chain from its last entry.
+    fn open(&self) -> std::io::Result<Writer> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let size = file.metadata()?.len();
+        let newest = self.files().into_iter().rev().find(|path| {
+            std::fs::metadata(path).is_ok_and(|meta| meta.len() > 0)
+        });
+        let last_hash = match newest {
+            Some(path) => last_hash(&path)?,
+            None => None,
+        };
+        Ok(Writer {
+            file: Some(file),
+            size,
+            last_hash,
+        })
+    }
+
+    /// Shift `<path>.N` to `<path>.N+1`, deleting the oldest, and move the
+    /// log to `<path>.1`.
+    fn rotate(&self) -> std::io::Result<()> {
+        if self.keep == 0 {
+            return std::fs::remove_file(&self.path);
+        }
+        let oldest = rotated(&self.path, self.keep);
+        if oldest.exists() {
+            std::fs::remove_file(&oldest)?;
+        }
+        for n in (1..self.keep).rev() {
+            let from = rotated(&self.path, n);
+            if from.exists() {
+                std::fs::rename(&from, rotated(&self.path, n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated(&self.path, 1))
+    }
+}
+
+/// Why a chain failed to verify.
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("cannot read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("{path}:{line}: {reason}")]
+    Broken {
+        path: String,
+        line: usize,
+        reason: String,
+    },
+}
+
+/// Check the hash chain across `files`, given oldest first, and return the
+/// number of entries. The first entry's `prev_hash` is taken as given, since
+/// its predecessor may have been rotated away.
+pub fn verify(files: &[PathBuf]) -> Result<usize, VerifyError> {
+    let mut previous: Option<Option<String>> = None;
+    let mut entries = 0;
+    for path in files {
+        let io_error = |source| VerifyError::Io {
+            path: path.display().to_string(),
+            source,
+        };
+        let reader = BufReader::new(File::open(path).map_err(io_error)?);
+        for (i, line) in reader.lines().enumerate() {
+            let line = line.map_err(io_error)?;
+            let broken = |reason: &str| VerifyError::Broken {
+                path: path.display().to_string(),
+                line: i + 1,
+                reason: reason.to_string(),
+            };
+            let mut value: serde_json::Value =
+                serde_json::from_str(&line).map_err(|_| broken("not a JSON object"))?;
+            let hash = match value.as_object_mut().and_then(|entry| entry.remove("hash")) {
+                Some(serde_json::Value::String(hash)) => hash,
+                _ => return Err(broken("missing hash")),
+            };
+            let serialized = serde_json::to_string(&value).map_err(|_| broken("not serializable"))?;
+            if sha256(serialized.as_bytes()) != hash {
+                return Err(broken("hash does not match the entry"));
+            }
+            let prev_hash = value["prev_hash"].as_str().map(str::to_string);
+            if previous.as_ref().is_some_and(|previous| *previous != prev_hash) {
+                return Err(broken("prev_hash does not match the previous entry"));
+            }
+            previous = Some(Some(hash));
+            entries += 1;
+        }
+    }
+    Ok(entries)
+}
+
+/// The outcome code of a `tools/call` response and, on success, the tool's
+/// JSON output.
+fn outcome(response: Option<&JsonRpcResponse>) -> (String, Option<serde_json::Value>) {
+    let Some(result) = response.and_then(|response| response.result.as_ref()) else {
+        return ("invalid_params".to_string(), None);
+    };
+    let text = result["content"][0]["text"].as_str().unwrap_or_default();
+    if result["isError"] == true {
+        let code = serde_json::from_str::<McpErrorResponse>(text)
+            .ok()
+            .and_then(|error| serde_json::to_value(error.error.code).ok())
+            .and_then(|code| code.as_str().map(str::to_string));
+        // Errors without a code: unknown tools, malformed arguments, and the
+        // like.
+        (code.unwrap_or_else(|| "error".to_string()), None)
+    } else {
+        ("ok".to_string(), serde_json::from_str(text).ok())
+    }
+}
+
+/// The `hash` of the last entry in `path`. The file is read backwards from
+/// the end, a growing window at a time, so a large log is not read in full.
+fn last_hash(path: &Path) -> std::io::Result<Option<String>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut window = 4096u64;
+    let last = loop {
+        let start = len.saturating_sub(window);
+        file.seek(SeekFrom::Start(start))?;
+        let mut tail = vec![0; (len - start) as usize];
+        file.read_exact(&mut tail)?;
+        let tail = String::from_utf8_lossy(&tail);
+        let lines: Vec<&str> = tail.lines().filter(|line| !line.trim().is_empty()).collect();
+        // Unless the window reaches the start of the file, its first line
+        // may be cut off.
+        if start == 0 || lines.len() > 1 {
+            break lines.last().map(|line| line.to_string());
+        }
+        window *= 2;
+    };
+    let hash = last
+        .and_then(|line| serde_json::from_str::<serde_json::Value>(&line).ok())
+        .and_then(|entry| entry["hash"].as_str().map(str::to_string));
+    if hash.is_none() {
+        warn!(path = %path.display(), "Audit log does not end with a hashed entry; starting a new chain");
+    }
+    Ok(hash)
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    let hex: String = Sha256::digest(bytes).iter().map(|b| format!("{b:02x}")).collect();
+    format!("sha256:{hex}")
+}
@@ -0,0 +1,226 @@
+//! Authentication for TCP sessions.
+//!
+//! Stdio sessions trust the process boundary and Unix socket peers are
+//! identified by uid, but a TCP peer can be anyone. The config file's
+//! `auth` section declares how TCP clients prove who they are:
+//!
+//! ```json
+//! {
+//!   "auth": {
+//!     "tokens": { "ci-bot": "sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08" },
+//!     "tls": { "cert": "certs/server.pem", "key": "certs/server.key", "client_ca": "certs/clients.pem" }
+//!   }
+//! }
+//! ```
+//!
+//! `tokens` maps a name to the SHA-256 of a bearer token; clients send the
+//! token as `"_meta": {"authorization": "Bearer <token>"}` in `initialize`.
+//! `tls` wraps TCP connections in TLS, and with `client_ca`, verifies
+//! client certificates against that bundle (mutual TLS). A verified
+//! certificate identifies the client by its subject common name; certificates
+//! are required unless tokens are declared too. Everything is read from
+//! local files at startup.
+//!
+//! Tokens without `tls` would cross the network in the clear, so they are
+//! only accepted on a loopback listen address.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::TlsAcceptor;
+
+use crate::access::Principal;
+
+/// The config file's `auth` section.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Token name to `sha256:<hex>` of the token.
+    #[serde(default)]
+    pub tokens: BTreeMap<String, String>,
+    #[serde(default)]
+    pub tls: Option<TlsFiles>,
+}
+
+/// PEM files for the TLS listener. Relative paths are resolved against the
+/// config file's directory.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// CA bundle client certificates must chain to.
+    #[serde(default)]
+    pub client_ca: Option<PathBuf>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("token {0:?} must be \"sha256:\" followed by 64 hex digits")]
+    InvalidTokenHash(String),
+    #[error("cannot read {kind} {path}: {source}")]
+    Pem {
+        kind: &'static str,
+        path: String,
+        source: rustls::pki_types::pem::Error,
+    },
+    #[error("no certificates in {0}")]
+    NoCertificates(String),
+    #[error("invalid TLS configuration: {0}")]
+    Tls(#[from] rustls::Error),
+    #[error("invalid client CA bundle: {0}")]
+    ClientCa(#[from] rustls::server::VerifierBuilderError),
+    #[error("auth.tokens require auth.tls to listen on {0}; only loopback addresses may take tokens without TLS")]
+    TokensWithoutTls(SocketAddr),
+}
+
+/// Verifies TCP clients.
+#[derive(Debug, Clone, Default)]
+pub struct Authenticator {
+    /// Token names and the SHA-256 of each token.
+    tokens: Vec<(String, [u8; 32])>,
+    tls: Option<Arc<rustls::ServerConfig>>,
+    /// Whether TLS verifies client certificates.
+    client_certificates: bool,
+}
+
+impl Authenticator {
+    /// Load the tokens and TLS files `config` declares, resolving relative
+    /// paths against `base_dir`.
+    pub fn load(config: &AuthConfig, base_dir: &Path) -> Result<Self, AuthError> {
+        let mut tokens = Vec::new();
+        for (name, hash) in &config.tokens {
+            let digest = parse_token_hash(hash).ok_or_else(|| AuthError::InvalidTokenHash(name.clone()))?;
+            tokens.push((name.clone(), digest));
+        }
+
+        let tls = match &config.tls {
+            Some(files) => Some(Arc::new(load_tls(files, base_dir, !tokens.is_empty())?)),
+            None => None,
+        };
+        Ok(Self {
+            tokens,
+            tls,
+            client_certificates: config.tls.as_ref().is_some_and(|tls| tls.client_ca.is_some()),
+        })
+    }
+
+    /// Whether TCP clients must authenticate. Without tokens or client
+    /// certificates, TCP sessions are anonymous.
+    pub fn is_required(&self) -> bool {
+        !self.tokens.is_empty() || self.client_certificates
+    }
+
+    /// Whether bearer tokens are accepted over plain TCP.
+    pub fn tokens_in_clear(&self) -> bool {
+        !self.tokens.is_empty() && self.tls.is_none()
+    }
+
+    /// Check that TCP clients on `addr` can authenticate safely: tokens
+    /// without TLS are only allowed on a loopback address.
+    pub fn check_listen_address(&self, addr: SocketAddr) -> Result<(), AuthError> {
+        if self.tokens_in_clear() && !addr.ip().is_loopback() {
+            return Err(AuthError::TokensWithoutTls(addr));
+        }
+        Ok(())
+    }
+
+    /// The name of the token an `initialize` request's `authorization`
+    /// value carries, if it is a known bearer token.
+    pub fn authenticate_token(&self, authorization: Option<&str>) -> Option<String> {
+        let token = authorization?.strip_prefix("Bearer ")?;
+        let digest: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        // Compare every entry without short-circuiting on the bytes.
+        let mut found = None;
+        for (name, expected) in &self.tokens {
+            let diff = expected.iter().zip(&digest).fold(0, |acc, (a, b)| acc | (a ^ b));
+            if diff == 0 {
+                found = Some(name.clone());
+            }
+        }
+        found
+    }
+
+    /// The TLS acceptor for TCP connections, if TLS is configured.
+    pub fn tls_acceptor(&self) -> Option<TlsAcceptor> {
+        self.tls.clone().map(TlsAcceptor::from)
+    }
+}
+
+/// The principal a verified client certificate chain names: the subject
+/// common name of the end-entity certificate.
+pub fn certificate_principal(chain: Option<&[CertificateDer<'_>]>) -> Option<Principal> {
+    let cert = chain?.first()?;
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let common_name = parsed.subject().iter_common_name().next()?.as_str().ok()?;
+    Some(Principal::Certificate(common_name.to_string()))
+}
+
+fn parse_token_hash(hash: &str) -> Option<[u8; 32]> {
+    let hex = hash.strip_prefix("sha256:")?;
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(digest)
+}
+
+fn load_tls(files: &TlsFiles, base_dir: &Path, tokens: bool) -> Result<rustls::ServerConfig, AuthError> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match &files.client_ca {
+        Some(client_ca) => {
+            let mut roots = rustls::RootCertStore::empty();
+            for cert in read_certs(&base_dir.join(client_ca), "client CA bundle")? {
+                roots.add(cert)?;
+            }
+            let verifier = rustls::server::WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            // Token holders may connect without a certificate.
+            let verifier = if tokens {
+                verifier.allow_unauthenticated().build()?
+            } else {
+                verifier.build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let cert_path = base_dir.join(&files.cert);
+    let key_path = base_dir.join(&files.key);
+    let certs = read_certs(&cert_path, "certificate")?;
+    let key = PrivateKeyDer::from_pem_file(&key_path).map_err(|source| AuthError::Pem {
+        kind: "private key",
+        path: key_path.display().to_string(),
+        source,
+    })?;
+    Ok(builder.with_single_cert(certs, key)?)
+}
+
+fn read_certs(path: &Path, kind: &'static str) -> Result<Vec<CertificateDer<'static>>, AuthError> {
+    let pem_error = |source| AuthError::Pem {
+        kind,
+        path: path.display().to_string(),
+        source,
+    };
+    let certs = CertificateDer::pem_file_iter(path)
+        .map_err(pem_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(pem_error)?;
+    if certs.is_empty() {
+        return Err(AuthError::NoCertificates(path.display().to_string()));
+    }
+    Ok(certs)
+}
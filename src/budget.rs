@@ -0,0 +1,49 @@
+//! Resolution of `budget` arguments into a concrete token count.
+//!
+//! Agents rarely know a safe absolute budget, so tools also accept a share
+//! of a model's context window or the name of a model profile from the
+//! config file. Every form resolves to a plain token count before selection
+//! runs; that count is what `selection.budget` reports.
+
+use std::collections::BTreeMap;
+
+use crate::protocol::{Budget, BudgetWindow, McpErrorCode, McpErrorResponse};
+
+/// Resolve `budget` to a token count, looking up profiles in `profiles`.
+///
+/// Negative counts, unknown profiles, and windows that leave no room (see
+/// [`window_tokens`]) are `invalid_budget`.
+pub fn resolve(
+    budget: &Budget,
+    profiles: &BTreeMap<String, BudgetWindow>,
+) -> Result<usize, McpErrorResponse> {
+    let tokens = match budget {
+        Budget::Tokens(tokens) => usize::try_from(*tokens).ok(),
+        Budget::Window(window) => window_tokens(window),
+        Budget::Profile(name) => profiles.get(name).and_then(window_tokens),
+        Budget::Invalid(_) => None,
+    };
+    tokens.ok_or_else(|| McpErrorResponse::canonical(McpErrorCode::InvalidBudget))
+}
+
+/// Tokens available under `window`: `floor((model_window - reserve) * fraction)`.
+///
+/// Returns `None` unless `model_window` is positive, `reserve` is
+/// non-negative and smaller than the window, and `fraction` is in `(0, 1]`.
+pub fn window_tokens(window: &BudgetWindow) -> Option<usize> {
+    let BudgetWindow {
+        model_window,
+        fraction,
+        reserve,
+    } = *window;
+
+    if model_window <= 0 || reserve < 0 || reserve >= model_window {
+        return None;
+    }
+    if !(fraction > 0.0 && fraction <= 1.0) {
+        return None;
+    }
+
+    let available = (model_window - reserve) as f64 * fraction;
+    Some(available.floor() as usize)
+}
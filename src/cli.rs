@@ -0,0 +1,421 @@
+//! Command-line interface for the `mcp-context-server` binary.
+//!
+//! With no subcommand the binary serves MCP over stdio, exactly as MCP hosts
+//! expect. The one-shot subcommands (`resolve`, `inspect`, `list`, `doctor`,
+//! `diff-caches`, `diff-resolve`, `build-cache`) run the same handlers as
+//! `tools/call` and print the tool's output, so cache problems can be
+//! debugged without hand-writing JSON-RPC.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::audit;
+use crate::config::{ServerConfig, DEFAULT_TOOL_TIMEOUT_SECS};
+use crate::handlers;
+use crate::handlers::doctor::{DoctorReport, Severity};
+use crate::logging::LogFormat;
+use crate::notifications::Progress;
+use crate::protocol::{
+    Budget, BuildCacheParams, DiffCachesParams, DiffResolveParams, InspectCacheParams, ResolveContextParams,
+    Scoring, Strategy, ToolResult,
+};
+use crate::transport::{self, Transport};
+
+#[derive(Debug, Parser)]
+#[command(name = "mcp-context-server", version, about = "Deterministic context tools over MCP")]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Server configuration flags. Each flag mirrors an environment variable;
+/// an explicit flag wins over the environment.
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+    /// Root directory containing context caches
+    #[arg(long, env = "CONTEXT_CACHE_ROOT", global = true)]
+    pub cache_root: Option<PathBuf>,
+
+    /// Maximum seconds per tool call
+    #[arg(long, env = "CONTEXT_TOOL_TIMEOUT_SECS", global = true, default_value_t = DEFAULT_TOOL_TIMEOUT_SECS)]
+    pub tool_timeout_secs: u64,
+
+    /// JSON config file declaring tokenizers, per-cache settings, and model profiles
+    #[arg(long = "config", env = "CONTEXT_CONFIG", global = true)]
+    pub config_file: Option<PathBuf>,
+
+    /// Log format on stderr; the level filter is read from CONTEXT_LOG
+    #[arg(long, env = "CONTEXT_LOG_FORMAT", global = true, value_enum, default_value_t = LogFormat::Pretty)]
+    pub log_format: LogFormat,
+}
+
+impl ConfigArgs {
+    /// Build a `ServerConfig` from the parsed flags.
+    pub fn to_config(&self) -> Result<ServerConfig, String> {
+        let cache_root = self.cache_root.clone().ok_or_else(|| {
+            "CONTEXT_CACHE_ROOT environment variable is not set (or pass --cache-root)".to_string()
+        })?;
+
+        let config = ServerConfig {
+            cache_root,
+            tool_timeout: Duration::from_secs(self.tool_timeout_secs),
+            ..ServerConfig::default()
+        };
+
+        match &self.config_file {
+            Some(path) => config.with_config_file(path),
+            None => Ok(config),
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the MCP server (the default when no subcommand is given)
+    Serve(ServeArgs),
+
+    /// Resolve context from a cache and print the selection
+    Resolve {
+        /// Cache directory name (relative to the cache root)
+        #[arg(long)]
+        cache: String,
+        /// Search query for context selection
+        #[arg(long)]
+        query: String,
+        /// Token budget: a count, a profile name, or a JSON window object
+        #[arg(long, allow_negative_numbers = true, value_parser = parse_budget)]
+        budget: Budget,
+        /// Explain rankings, term matches, and excluded documents
+        #[arg(long)]
+        explain: bool,
+        /// Return query-focused snippets instead of full content
+        #[arg(long)]
+        snippets: bool,
+        /// Suppress near-duplicates of higher-ranked documents
+        #[arg(long)]
+        dedup: bool,
+        /// Selection strategy: `score` (default) or `mmr`
+        #[arg(long)]
+        strategy: Option<Strategy>,
+        /// Scoring mode: `term_frequency` (default), `bm25`, or `hybrid` (with
+        /// the `hybrid` feature)
+        #[arg(long)]
+        scoring: Option<Scoring>,
+        /// Tokenizer to count tokens with (a name from the config file)
+        #[arg(long)]
+        tokenizer: Option<String>,
+    },
+
+    /// Inspect a cache and print its metadata and validity
+    Inspect {
+        /// Cache directory name (relative to the cache root)
+        #[arg(long)]
+        cache: String,
+    },
+
+    /// List caches under the cache root
+    List,
+
+    /// Diagnose caches under the cache root and suggest fixes
+    Doctor {
+        /// Print the report as JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Compare two caches and print added, removed, and changed documents
+    DiffCaches {
+        /// Cache to compare from (relative to the cache root)
+        #[arg(long)]
+        base: String,
+        /// Cache to compare to (relative to the cache root)
+        #[arg(long)]
+        target: String,
+    },
+
+    /// Resolve the same query against two caches and compare the selections
+    DiffResolve {
+        /// Cache to compare from (relative to the cache root)
+        #[arg(long)]
+        base: String,
+        /// Cache to compare to (relative to the cache root)
+        #[arg(long)]
+        target: String,
+        /// Search query for context selection
+        #[arg(long)]
+        query: String,
+        /// Token budget: a count, a profile name, or a JSON window object
+        #[arg(long, allow_negative_numbers = true, value_parser = parse_budget)]
+        budget: Budget,
+    },
+
+    /// Build or rebuild a cache from its configured source directory
+    BuildCache {
+        /// Cache directory name (relative to the cache root)
+        #[arg(long)]
+        cache: String,
+        /// Re-ingest only source files added or changed since the last build
+        #[arg(long)]
+        incremental: bool,
+    },
+
+    /// Validate the configuration and cache root, then exit
+    CheckConfig,
+
+    /// Verify the hash chain of the configured audit log and its rotated files
+    VerifyAudit,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Transport to serve MCP on
+    #[arg(long, value_enum, default_value_t = TransportKind::Stdio)]
+    pub transport: TransportKind,
+
+    /// Listen address: `host:port` for tcp, a socket path for unix
+    #[arg(long)]
+    pub listen: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TransportKind {
+    Stdio,
+    Tcp,
+    Unix,
+}
+
+impl ServeArgs {
+    /// Resolve the transport flags into a concrete `Transport`.
+    pub fn to_transport(&self) -> Result<Transport, String> {
+        match (self.transport, &self.listen) {
+            (TransportKind::Stdio, None) => Ok(Transport::Stdio),
+            (TransportKind::Stdio, Some(_)) => {
+                Err("--listen is not used with the stdio transport".to_string())
+            }
+            (TransportKind::Tcp, Some(addr)) => addr
+                .parse()
+                .map(Transport::Tcp)
+                .map_err(|_| format!("invalid tcp listen address: {addr}")),
+            #[cfg(unix)]
+            (TransportKind::Unix, Some(path)) => Ok(Transport::Unix(PathBuf::from(path))),
+            #[cfg(not(unix))]
+            (TransportKind::Unix, Some(_)) => {
+                Err("the unix transport is not supported on this platform".to_string())
+            }
+            (_, None) => Err("--listen is required for socket transports".to_string()),
+        }
+    }
+}
+
+/// Run the parsed command line and return the process exit code.
+pub async fn run(cli: Cli) -> i32 {
+    let config = match cli.config.to_config() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("mcp-context-server: configuration error: {e}");
+            return 1;
+        }
+    };
+
+    let transport = match &cli.command {
+        None => Transport::Stdio,
+        Some(Command::Serve(args)) => match args.to_transport() {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("mcp-context-server: {e}");
+                return 1;
+            }
+        },
+        Some(Command::CheckConfig) => {
+            return match config.validate() {
+                Ok(()) => {
+                    println!("configuration ok");
+                    0
+                }
+                Err(e) => {
+                    eprintln!("mcp-context-server: configuration error: {e}");
+                    1
+                }
+            };
+        }
+        Some(Command::VerifyAudit) => {
+            let Some(audit) = &config.audit else {
+                eprintln!("mcp-context-server: no audit log is configured");
+                return 1;
+            };
+            return match audit::verify(&audit.files()) {
+                Ok(entries) => {
+                    println!("audit log ok: {entries} entries");
+                    0
+                }
+                Err(e) => {
+                    eprintln!("mcp-context-server: audit log broken: {e}");
+                    1
+                }
+            };
+        }
+        Some(Command::Doctor { json }) => {
+            let report = handlers::doctor::diagnose(&config.cache_root);
+            if *json {
+                match serde_json::to_string(&report) {
+                    Ok(out) => println!("{out}"),
+                    Err(e) => {
+                        eprintln!("mcp-context-server: serialization failed: {e}");
+                        return 1;
+                    }
+                }
+            } else {
+                print!("{}", format_doctor_report(&report));
+            }
+            return if report.healthy { 0 } else { 1 };
+        }
+        Some(command) => {
+            return print_result(&one_shot(command, &config).await);
+        }
+    };
+
+    if let Transport::Tcp(addr) = &transport {
+        if let Err(e) = config.auth.check_listen_address(*addr) {
+            eprintln!("mcp-context-server: configuration error: {e}");
+            return 1;
+        }
+    }
+
+    match transport::serve(config, transport).await {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("mcp-context-server: fatal error: {e}");
+            1
+        }
+    }
+}
+
+/// Run a one-shot tool subcommand through the same handler as `tools/call`.
+///
+/// Subcommands that are not tool invocations get an error result.
+pub async fn one_shot(command: &Command, config: &ServerConfig) -> ToolResult {
+    match command {
+        Command::Resolve {
+            cache,
+            query,
+            budget,
+            explain,
+            snippets,
+            dedup,
+            strategy,
+            scoring,
+            tokenizer,
+        } => {
+            let params = ResolveContextParams {
+                cache: cache.clone(),
+                query: query.clone(),
+                budget: budget.clone(),
+                explain: *explain,
+                snippets: *snippets,
+                dedup: *dedup,
+                strategy: *strategy,
+                scoring: *scoring,
+                tokenizer: tokenizer.clone(),
+            };
+            handlers::resolve_context::handle(params, config).await
+        }
+        Command::Inspect { cache } => {
+            let params = InspectCacheParams { cache: cache.clone() };
+            handlers::inspect_cache::handle(params, config).await
+        }
+        Command::List => handlers::list_caches::handle(config).await,
+        Command::Doctor { .. } => handlers::doctor::handle(config).await,
+        Command::DiffCaches { base, target } => {
+            let params = DiffCachesParams {
+                base: base.clone(),
+                target: target.clone(),
+            };
+            handlers::diff_caches::handle(params, config).await
+        }
+        Command::DiffResolve {
+            base,
+            target,
+            query,
+            budget,
+        } => {
+            let params = DiffResolveParams {
+                base: base.clone(),
+                target: target.clone(),
+                query: query.clone(),
+                budget: budget.clone(),
+            };
+            handlers::diff_resolve::handle(params, config).await
+        }
+        Command::BuildCache { cache, incremental } => {
+            let params = BuildCacheParams {
+                cache: cache.clone(),
+                incremental: *incremental,
+            };
+            handlers::build_cache::handle(params, config, Progress::default()).await
+        }
+        Command::Serve(_) | Command::CheckConfig | Command::VerifyAudit => {
+            ToolResult::error("Not a tool subcommand")
+        }
+    }
+}
+
+/// Parse `--budget`: an integer token count, a JSON window object such as
+/// `{"model_window":200000,"fraction":0.25}`, or otherwise a profile name.
+///
+/// Values that parse but make no sense are left for the handler to reject
+/// as `invalid_budget`, exactly as over MCP.
+fn parse_budget(arg: &str) -> Result<Budget, String> {
+    if let Ok(tokens) = arg.parse::<i64>() {
+        return Ok(Budget::Tokens(tokens));
+    }
+    if arg.trim_start().starts_with('{') {
+        return serde_json::from_str(arg).map_err(|e| format!("invalid budget object: {e}"));
+    }
+    Ok(Budget::Profile(arg.to_string()))
+}
+
+/// Print a tool result: success text to stdout, errors to stderr with exit code 1.
+fn print_result(result: &ToolResult) -> i32 {
+    for block in &result.content {
+        let text = block.text.trim_end();
+        if result.is_error {
+            eprintln!("{text}");
+        } else {
+            println!("{text}");
+        }
+    }
+
+    if result.is_error {
+        1
+    } else {
+        0
+    }
+}
+
+/// Render a doctor report for humans: one block per finding, then a summary.
+pub fn format_doctor_report(report: &DoctorReport) -> String {
+    let mut out = String::new();
+
+    for finding in &report.findings {
+        let severity = match finding.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let location = finding.path.as_deref().or(finding.cache.as_deref()).unwrap_or(".");
+        out.push_str(&format!("{severity}[{}] {location}: {}\n", finding.code, finding.message));
+        out.push_str(&format!("  fix: {}\n", finding.fix));
+    }
+
+    let errors = report.findings.iter().filter(|f| f.severity == Severity::Error).count();
+    let warnings = report.findings.len() - errors;
+    out.push_str(&format!(
+        "{} caches checked: {errors} errors, {warnings} warnings\n",
+        report.caches_checked
+    ));
+    out
+}
@@ -1,14 +1,135 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
+use serde::Deserialize;
+
+use crate::access::{AccessConfig, AccessPolicy};
+use crate::audit::{AuditConfig, AuditLog};
+use crate::auth::{AuthConfig, Authenticator};
+use crate::budget;
+use crate::expansion::Dictionary;
+#[cfg(feature = "hybrid")]
+use crate::embedding::{EmbedderRegistry, EmbedderSpec};
+use crate::index;
+use crate::limits::{Limiter, LimitsConfig};
+use crate::protocol::BudgetWindow;
+use crate::redaction::{RedactStage, RedactionConfig, Redactor};
+use crate::response_limit::ResponseLimit;
+use crate::tokenizer::{TokenizerRegistry, TokenizerSpec};
+
 /// Default timeout for tool operations (30 seconds).
-const DEFAULT_TOOL_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_TOOL_TIMEOUT_SECS: u64 = 30;
 
-/// Server configuration loaded from environment variables.
+/// Server configuration loaded from environment variables and an optional
+/// JSON config file.
 #[derive(Debug, Clone)]
 pub struct ServerConfig {
     pub cache_root: PathBuf,
     pub tool_timeout: Duration,
+    /// Tokenizers declared in the config file.
+    pub tokenizers: TokenizerRegistry,
+    /// Per-cache settings from the config file, keyed by cache name.
+    pub caches: BTreeMap<String, CacheSettings>,
+    /// Named model profiles usable as a `budget`.
+    pub profiles: BTreeMap<String, BudgetWindow>,
+    /// Caches with at least this many documents are resolved through a
+    /// persistent inverted index.
+    pub index_min_documents: usize,
+    /// Query-expansion dictionaries from the config file, keyed by cache
+    /// name.
+    pub dictionaries: BTreeMap<String, Arc<Dictionary>>,
+    /// Secret patterns applied to caches with `redact` stages.
+    pub redactor: Arc<Redactor>,
+    /// Which clients may use which caches and tools.
+    pub access: AccessPolicy,
+    /// Bearer tokens and TLS for TCP sessions.
+    pub auth: Authenticator,
+    /// Where tool calls are recorded, if anywhere.
+    pub audit: Option<AuditLog>,
+    /// Rate limits and the cap on concurrent blocking work.
+    pub limits: Limiter,
+    /// Cap on the size of tool results, and what happens above it.
+    pub responses: Option<ResponseLimit>,
+    /// Query embedders declared in the config file.
+    #[cfg(feature = "hybrid")]
+    pub embedders: EmbedderRegistry,
+}
+
+/// Settings for one cache, from the config file's `caches` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheSettings {
+    /// Tokenizer used when a request does not name one.
+    #[serde(default)]
+    pub tokenizer: Option<String>,
+    /// Synonym and acronym dictionary applied before the cache's own.
+    #[serde(default)]
+    pub dictionary: Option<PathBuf>,
+    /// Directory `context.build_cache` builds the cache from. Relative paths
+    /// are resolved against the config file's directory when it is loaded.
+    #[serde(default)]
+    pub source: Option<PathBuf>,
+    /// Where secrets in the cache's content are redacted: `build`,
+    /// `output`, or both.
+    #[serde(default)]
+    pub redact: Vec<RedactStage>,
+    /// Embedder for `"scoring": "hybrid"` requests.
+    #[cfg(feature = "hybrid")]
+    #[serde(default)]
+    pub embedder: Option<String>,
+}
+
+/// On-disk layout of the JSON config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    tokenizers: BTreeMap<String, TokenizerSpec>,
+    #[serde(default)]
+    caches: BTreeMap<String, CacheSettings>,
+    #[serde(default)]
+    profiles: BTreeMap<String, BudgetWindow>,
+    #[serde(default)]
+    index_min_documents: Option<usize>,
+    #[serde(default)]
+    redaction: Option<RedactionConfig>,
+    #[serde(default)]
+    access: Option<AccessConfig>,
+    #[serde(default)]
+    auth: Option<AuthConfig>,
+    #[serde(default)]
+    audit: Option<AuditConfig>,
+    #[serde(default)]
+    limits: Option<LimitsConfig>,
+    #[serde(default)]
+    responses: Option<ResponseLimit>,
+    #[cfg(feature = "hybrid")]
+    #[serde(default)]
+    embedders: BTreeMap<String, EmbedderSpec>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            cache_root: PathBuf::new(),
+            tool_timeout: Duration::from_secs(DEFAULT_TOOL_TIMEOUT_SECS),
+            tokenizers: TokenizerRegistry::default(),
+            caches: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            index_min_documents: index::DEFAULT_MIN_DOCUMENTS,
+            dictionaries: BTreeMap::new(),
+            redactor: Arc::new(Redactor::default()),
+            access: AccessPolicy::default(),
+            auth: Authenticator::default(),
+            audit: None,
+            limits: Limiter::default(),
+            responses: None,
+            #[cfg(feature = "hybrid")]
+            embedders: EmbedderRegistry::default(),
+        }
+    }
 }
 
 impl ServerConfig {
@@ -16,6 +137,7 @@ impl ServerConfig {
     ///
     /// - `CONTEXT_CACHE_ROOT` (required) — root directory for caches
     /// - `CONTEXT_TOOL_TIMEOUT_SECS` (optional, default 30) — max seconds per tool call
+    /// - `CONTEXT_CONFIG` (optional) — path to a JSON config file
     pub fn from_env() -> Result<Self, String> {
         let cache_root = std::env::var("CONTEXT_CACHE_ROOT")
             .map(PathBuf::from)
@@ -28,9 +150,132 @@ impl ServerConfig {
             Err(_) => DEFAULT_TOOL_TIMEOUT_SECS,
         };
 
-        Ok(Self {
+        let config = Self {
             cache_root,
             tool_timeout: Duration::from_secs(tool_timeout_secs),
-        })
+            ..Self::default()
+        };
+
+        match std::env::var_os("CONTEXT_CONFIG") {
+            Some(path) => config.with_config_file(Path::new(&path)),
+            None => Ok(config),
+        }
+    }
+
+    /// Apply a JSON config file declaring tokenizers, per-cache settings,
+    /// model profiles, the inverted-index threshold, redaction patterns,
+    /// the access policy, TCP authentication, the audit log, rate limits,
+    /// the response size cap, and (with the `hybrid` feature) query
+    /// embedders.
+    ///
+    /// Every tokenizer, embedder, dictionary, and TLS file is loaded
+    /// immediately so a bad rank file, model, dictionary, or certificate
+    /// fails at startup rather than on the first request. Relative paths in
+    /// the file are resolved against the file's directory.
+    pub fn with_config_file(mut self, path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config file {}: {e}", path.display()))?;
+        let file: ConfigFile = serde_json::from_str(&text)
+            .map_err(|e| format!("invalid config file {}: {e}", path.display()))?;
+
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        self.tokenizers =
+            TokenizerRegistry::load(&file.tokenizers, base_dir).map_err(|e| e.to_string())?;
+
+        #[cfg(feature = "hybrid")]
+        {
+            self.embedders =
+                EmbedderRegistry::load(&file.embedders, base_dir).map_err(|e| e.to_string())?;
+        }
+
+        for (cache, settings) in &file.caches {
+            if let Some(name) = &settings.tokenizer {
+                if !self.tokenizers.contains(name) {
+                    return Err(format!("cache {cache:?} uses unknown tokenizer {name:?}"));
+                }
+            }
+            #[cfg(feature = "hybrid")]
+            if let Some(name) = &settings.embedder {
+                if !self.embedders.contains(name) {
+                    return Err(format!("cache {cache:?} uses unknown embedder {name:?}"));
+                }
+            }
+            if let Some(dictionary) = &settings.dictionary {
+                let dictionary = Dictionary::load(&base_dir.join(dictionary))
+                    .map_err(|e| format!("cache {cache:?}: {e}"))?;
+                self.dictionaries.insert(cache.clone(), Arc::new(dictionary));
+            }
+        }
+        self.caches = file.caches;
+        for settings in self.caches.values_mut() {
+            if let Some(source) = &mut settings.source {
+                *source = base_dir.join(&*source);
+            }
+        }
+
+        for (name, profile) in &file.profiles {
+            if budget::window_tokens(profile).is_none() {
+                return Err(format!("profile {name:?} does not describe a usable budget"));
+            }
+        }
+        self.profiles = file.profiles;
+
+        if let Some(min) = file.index_min_documents {
+            self.index_min_documents = min;
+        }
+
+        if let Some(redaction) = &file.redaction {
+            self.redactor = Arc::new(Redactor::new(redaction).map_err(|e| e.to_string())?);
+        }
+
+        if let Some(access) = file.access {
+            self.access = AccessPolicy::new(access)?;
+        }
+        if let Some(auth) = &file.auth {
+            self.auth = Authenticator::load(auth, base_dir).map_err(|e| e.to_string())?;
+        }
+        self.audit = file.audit.map(|audit| AuditLog::new(&audit, base_dir));
+        if let Some(limits) = &file.limits {
+            self.limits = Limiter::new(limits)?;
+        }
+        if let Some(responses) = &file.responses {
+            responses.validate()?;
+        }
+        self.responses = file.responses;
+
+        Ok(self)
+    }
+
+    /// The redactor for `cache` at `stage`, if the cache redacts there.
+    /// `cache` is the name the cache resolves to under the cache root, not
+    /// the spelling a request used.
+    pub fn redactor_for(&self, cache: &str, stage: RedactStage) -> Option<Arc<Redactor>> {
+        self.caches
+            .get(cache)
+            .filter(|settings| settings.redact.contains(&stage))
+            .map(|_| self.redactor.clone())
+    }
+
+    /// Check that the configuration is usable before serving.
+    ///
+    /// The cache root must be a readable directory and the tool timeout
+    /// must be non-zero.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.cache_root.is_dir() {
+            return Err(format!(
+                "cache root {} is not a directory",
+                self.cache_root.display()
+            ));
+        }
+
+        std::fs::read_dir(&self.cache_root).map_err(|e| {
+            format!("cache root {} is not readable: {e}", self.cache_root.display())
+        })?;
+
+        if self.tool_timeout.is_zero() {
+            return Err("tool timeout must be at least 1 second".to_string());
+        }
+
+        Ok(())
     }
 }
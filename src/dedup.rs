@@ -0,0 +1,124 @@
+//! Near-duplicate suppression for `context.resolve`.
+//!
+//! Caches built from several connectors often hold the same page twice. The
+//! dedup stage walks the ranking in order and suppresses any document that
+//! duplicates one already kept: either the same content hash (`version`) or
+//! word-shingle Jaccard similarity at or above [`SIMILARITY_THRESHOLD`].
+//! The higher-ranked document is always the representative, so the result
+//! depends only on the ranking and is deterministic.
+
+use serde::Serialize;
+
+use crate::selection::{self, Candidate};
+
+/// Words per shingle.
+pub const SHINGLE_SIZE: usize = 5;
+
+/// Minimum Jaccard similarity of shingle sets for two documents to count
+/// as duplicates.
+pub const SIMILARITY_THRESHOLD: f64 = 0.9;
+
+/// A document suppressed as a duplicate of a higher-ranked one.
+#[derive(Debug, Clone, Serialize)]
+pub struct Duplicate {
+    pub id: String,
+    pub version: String,
+    /// The kept representative.
+    pub duplicate_of: String,
+    pub match_kind: MatchKind,
+    /// Shingle similarity to the representative; 1.0 for identical hashes.
+    pub similarity: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchKind {
+    IdenticalHash,
+    Similar,
+}
+
+/// The documents of `ranked` that duplicate a higher-ranked one, in rank
+/// order. Every other document is kept.
+pub fn dedup<'a>(ranked: impl IntoIterator<Item = &'a Candidate>) -> Vec<Duplicate> {
+    let mut kept: Vec<(&Candidate, Vec<u64>)> = Vec::new();
+    let mut duplicates = Vec::new();
+
+    for candidate in ranked {
+        let signature = shingles(&candidate.content);
+        let duplicate = kept.iter().find_map(|(rep, rep_signature)| {
+            if rep.version == candidate.version {
+                return Some((rep, MatchKind::IdenticalHash, 1.0));
+            }
+            let similarity = jaccard(&signature, rep_signature);
+            (similarity >= SIMILARITY_THRESHOLD).then_some((rep, MatchKind::Similar, similarity))
+        });
+
+        match duplicate {
+            Some((rep, match_kind, similarity)) => duplicates.push(Duplicate {
+                id: candidate.id.clone(),
+                version: candidate.version.clone(),
+                duplicate_of: rep.id.clone(),
+                match_kind,
+                similarity,
+            }),
+            None => kept.push((candidate, signature)),
+        }
+    }
+
+    duplicates
+}
+
+/// Sorted, deduplicated hashes of the document's word shingles. Documents
+/// shorter than one shingle hash all their words as a single shingle;
+/// documents without words have no shingles and only match by hash.
+pub(crate) fn shingles(content: &str) -> Vec<u64> {
+    let words: Vec<String> = selection::words(content)
+        .into_iter()
+        .map(|w| w.text)
+        .collect();
+    if words.is_empty() {
+        return Vec::new();
+    }
+
+    let mut hashes: Vec<u64> = words
+        .windows(SHINGLE_SIZE.min(words.len()))
+        .map(shingle_hash)
+        .collect();
+    hashes.sort_unstable();
+    hashes.dedup();
+    hashes
+}
+
+/// 64-bit FNV-1a hash of a shingle's words, each followed by a `0xff`
+/// byte (which UTF-8 never contains). Unlike `DefaultHasher`, the hash is
+/// fixed across Rust releases and platforms, so similarities are too.
+fn shingle_hash(words: &[String]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    words
+        .iter()
+        .flat_map(|word| word.bytes().chain([0xff]))
+        .fold(OFFSET_BASIS, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(PRIME))
+}
+
+/// Jaccard similarity of two sorted hash sets. Empty sets are never similar.
+pub(crate) fn jaccard(a: &[u64], b: &[u64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+
+    shared as f64 / (a.len() + b.len() - shared) as f64
+}
@@ -0,0 +1,276 @@
+//! Query embedders for hybrid retrieval (`hybrid` feature).
+//!
+//! An embedder turns query text into a vector in the same space as the
+//! vectors precomputed in a cache (see [`crate::hybrid`]). Embedders are
+//! declared by name in the config file and always run locally: either an
+//! ONNX model on the CPU (`onnx` feature) or a local command that reads the
+//! text on stdin and prints a JSON array of numbers on stdout. The server
+//! never fetches models or calls a network service.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+/// An embedder declaration from the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case", deny_unknown_fields)]
+pub enum EmbedderSpec {
+    /// A local command. `command[0]` is resolved against the config file's
+    /// directory when it is a relative path with a directory component.
+    Command {
+        command: Vec<String>,
+        /// Name of the embedding model; must match the cache's embeddings.
+        model: String,
+    },
+    /// A sentence-embedding ONNX model and its `tokenizer.json`. Relative
+    /// paths are resolved against the config file's directory.
+    #[cfg(feature = "onnx")]
+    Onnx {
+        path: PathBuf,
+        tokenizer: PathBuf,
+        model: String,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmbeddingError {
+    #[error("embedder {0:?} has an empty command")]
+    EmptyCommand(String),
+    #[error("embedding command failed to run: {0}")]
+    Spawn(std::io::Error),
+    #[error("embedding command exited with {0}")]
+    CommandFailed(std::process::ExitStatus),
+    #[error("embedding command output is not a JSON array of numbers: {0}")]
+    InvalidOutput(serde_json::Error),
+    #[error("embedding is empty or not finite")]
+    InvalidVector,
+    #[cfg(feature = "onnx")]
+    #[error("cannot load ONNX embedder {name:?}: {message}")]
+    Load { name: String, message: String },
+    #[cfg(feature = "onnx")]
+    #[error("ONNX inference failed: {0}")]
+    Inference(String),
+}
+
+/// Produces query embeddings.
+pub enum Embedder {
+    Command {
+        program: PathBuf,
+        args: Vec<String>,
+        model: String,
+    },
+    #[cfg(feature = "onnx")]
+    Onnx(Box<onnx::OnnxEmbedder>),
+}
+
+impl Embedder {
+    /// Name of the model whose space this embedder produces vectors in.
+    pub fn model(&self) -> &str {
+        match self {
+            Self::Command { model, .. } => model,
+            #[cfg(feature = "onnx")]
+            Self::Onnx(onnx) => &onnx.model,
+        }
+    }
+
+    /// Embed `text`.
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let vector = match self {
+            Self::Command { program, args, .. } => run_command(program, args, text)?,
+            #[cfg(feature = "onnx")]
+            Self::Onnx(onnx) => onnx.embed(text)?,
+        };
+        if vector.is_empty() || vector.iter().any(|v| !v.is_finite()) {
+            return Err(EmbeddingError::InvalidVector);
+        }
+        Ok(vector)
+    }
+}
+
+fn run_command(program: &Path, args: &[String], text: &str) -> Result<Vec<f32>, EmbeddingError> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(EmbeddingError::Spawn)?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).map_err(EmbeddingError::Spawn)?;
+    }
+    let output = child.wait_with_output().map_err(EmbeddingError::Spawn)?;
+    if !output.status.success() {
+        return Err(EmbeddingError::CommandFailed(output.status));
+    }
+    serde_json::from_slice(&output.stdout).map_err(EmbeddingError::InvalidOutput)
+}
+
+/// Embedders declared in the config file, loaded once at startup.
+#[derive(Clone, Default)]
+pub struct EmbedderRegistry {
+    embedders: Arc<BTreeMap<String, Arc<Embedder>>>,
+}
+
+impl EmbedderRegistry {
+    /// Load every declared embedder. Relative paths resolve against `base_dir`.
+    pub fn load(
+        specs: &BTreeMap<String, EmbedderSpec>,
+        base_dir: &Path,
+    ) -> Result<Self, EmbeddingError> {
+        let mut embedders = BTreeMap::new();
+        for (name, spec) in specs {
+            let embedder = match spec {
+                EmbedderSpec::Command { command, model } => {
+                    let (program, args) = command
+                        .split_first()
+                        .ok_or_else(|| EmbeddingError::EmptyCommand(name.clone()))?;
+                    let program = Path::new(program);
+                    let program = if program.is_relative() && program.components().count() > 1 {
+                        base_dir.join(program)
+                    } else {
+                        program.to_path_buf()
+                    };
+                    Embedder::Command {
+                        program,
+                        args: args.to_vec(),
+                        model: model.clone(),
+                    }
+                }
+                #[cfg(feature = "onnx")]
+                EmbedderSpec::Onnx {
+                    path,
+                    tokenizer,
+                    model,
+                } => Embedder::Onnx(Box::new(onnx::OnnxEmbedder::load(
+                    name,
+                    &base_dir.join(path),
+                    &base_dir.join(tokenizer),
+                    model,
+                )?)),
+            };
+            embedders.insert(name.clone(), Arc::new(embedder));
+        }
+        Ok(Self {
+            embedders: Arc::new(embedders),
+        })
+    }
+
+    /// Look up an embedder by name.
+    pub fn get(&self, name: &str) -> Option<Arc<Embedder>> {
+        self.embedders.get(name).cloned()
+    }
+
+    /// Whether `name` refers to a declared embedder.
+    pub fn contains(&self, name: &str) -> bool {
+        self.embedders.contains_key(name)
+    }
+}
+
+impl std::fmt::Debug for EmbedderRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.embedders.keys()).finish()
+    }
+}
+
+#[cfg(feature = "onnx")]
+mod onnx {
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use ort::session::Session;
+    use ort::value::Tensor;
+
+    use super::EmbeddingError;
+
+    /// A sentence-embedding model run with ONNX Runtime on the CPU.
+    ///
+    /// The model takes `input_ids` and `attention_mask` (and `token_type_ids`
+    /// if it declares that input) and returns either pooled embeddings
+    /// `[1, dim]` or token embeddings `[1, tokens, dim]`, which are
+    /// mean-pooled over the attention mask.
+    pub struct OnnxEmbedder {
+        session: Mutex<Session>,
+        tokenizer: tokenizers::Tokenizer,
+        pub(super) model: String,
+    }
+
+    impl OnnxEmbedder {
+        pub fn load(
+            name: &str,
+            path: &Path,
+            tokenizer: &Path,
+            model: &str,
+        ) -> Result<Self, EmbeddingError> {
+            let load_error = |message: String| EmbeddingError::Load {
+                name: name.to_string(),
+                message,
+            };
+            let session = Session::builder()
+                .and_then(|builder| builder.with_intra_threads(1))
+                .and_then(|builder| builder.commit_from_file(path))
+                .map_err(|e| load_error(e.to_string()))?;
+            let tokenizer =
+                tokenizers::Tokenizer::from_file(tokenizer).map_err(|e| load_error(e.to_string()))?;
+            Ok(Self {
+                session: Mutex::new(session),
+                tokenizer,
+                model: model.to_string(),
+            })
+        }
+
+        pub fn embed(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+            let inference = |e: String| EmbeddingError::Inference(e);
+            let encoding = self
+                .tokenizer
+                .encode(text, true)
+                .map_err(|e| inference(e.to_string()))?;
+            let ids: Vec<i64> = encoding.get_ids().iter().map(|&v| v as i64).collect();
+            let mask: Vec<i64> = encoding.get_attention_mask().iter().map(|&v| v as i64).collect();
+            let types: Vec<i64> = encoding.get_type_ids().iter().map(|&v| v as i64).collect();
+            let shape = [1, ids.len()];
+
+            let tensor = |data: Vec<i64>| Tensor::from_array((shape, data)).map_err(|e| inference(e.to_string()));
+            let mut inputs = ort::inputs! {
+                "input_ids" => tensor(ids)?,
+                "attention_mask" => tensor(mask.clone())?,
+            };
+
+            let mut session = self.session.lock().map_err(|e| inference(e.to_string()))?;
+            if session.inputs.iter().any(|input| input.name == "token_type_ids") {
+                inputs.push(("token_type_ids".into(), tensor(types)?.into()));
+            }
+            let outputs = session.run(inputs).map_err(|e| inference(e.to_string()))?;
+            let (dims, data) = outputs[0]
+                .try_extract_tensor::<f32>()
+                .map_err(|e| inference(e.to_string()))?;
+
+            match **dims {
+                [1, dim] => Ok(data[..dim as usize].to_vec()),
+                [1, tokens, dim] => {
+                    let (tokens, dim) = (tokens as usize, dim as usize);
+                    let mut pooled = vec![0.0_f32; dim];
+                    let mut count = 0.0_f32;
+                    for t in 0..tokens {
+                        if mask.get(t).copied().unwrap_or(0) == 0 {
+                            continue;
+                        }
+                        count += 1.0;
+                        for (p, v) in pooled.iter_mut().zip(&data[t * dim..(t + 1) * dim]) {
+                            *p += v;
+                        }
+                    }
+                    if count > 0.0 {
+                        pooled.iter_mut().for_each(|p| *p /= count);
+                    }
+                    Ok(pooled)
+                }
+                _ => Err(inference(format!("unexpected output shape {:?}", &**dims))),
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Query expansion from synonym and acronym dictionaries.
+//!
+//! A cache can ship [`DICTIONARY_FILE`] next to `manifest.json`, and the
+//! config file can name a dictionary per cache. Both use the same layout:
+//!
+//! ```json
+//! {
+//!   "synonyms": [["k8s", "kubernetes"], ["db", "database"]],
+//!   "acronyms": { "sla": ["service level agreement"] }
+//! }
+//! ```
+//!
+//! Every member of a synonym group expands to the other members; an acronym
+//! expands to its spelled-out forms (one way only). Entries are normalized
+//! like query words, so a multi-word entry matches, and expands to, a
+//! phrase.
+//!
+//! [`expand`] appends the expansions of every scored clause as optional
+//! clauses. They add to a document's score but are never required, and
+//! excluded clauses are not expanded. Expansions come out in dictionary
+//! order (config dictionary first, then the cache's), so an expanded query
+//! is a pure function of the query and the dictionaries.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::query::{self, Clause, Operator, ParsedQuery};
+
+/// File name of a cache's dictionary inside the cache directory.
+pub const DICTIONARY_FILE: &str = "dictionary.json";
+
+/// On-disk layout of a dictionary file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DictionaryFile {
+    #[serde(default)]
+    synonyms: Vec<Vec<String>>,
+    #[serde(default)]
+    acronyms: BTreeMap<String, Vec<String>>,
+}
+
+/// Normalized entry words to the normalized expansions, in dictionary order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dictionary {
+    entries: BTreeMap<Vec<String>, Vec<Vec<String>>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DictionaryError {
+    #[error("cannot read dictionary: {0}")]
+    Unreadable(std::io::Error),
+    #[error("invalid dictionary: {0}")]
+    Invalid(serde_json::Error),
+}
+
+impl Dictionary {
+    /// Load a dictionary file.
+    pub fn load(path: &Path) -> Result<Self, DictionaryError> {
+        let text = std::fs::read_to_string(path).map_err(DictionaryError::Unreadable)?;
+        let file: DictionaryFile = serde_json::from_str(&text).map_err(DictionaryError::Invalid)?;
+        Ok(Self::from_file(file))
+    }
+
+    /// Load the dictionary shipped with the cache at `cache_path`, if any.
+    pub fn for_cache(cache_path: &Path) -> Result<Option<Self>, DictionaryError> {
+        let path = cache_path.join(DICTIONARY_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Self::load(&path).map(Some)
+    }
+
+    fn from_file(file: DictionaryFile) -> Self {
+        let mut dictionary = Self::default();
+        for group in &file.synonyms {
+            let members: Vec<Vec<String>> = group
+                .iter()
+                .map(|m| query::normalize_words(m))
+                .filter(|m| !m.is_empty())
+                .collect();
+            for member in &members {
+                for other in &members {
+                    if other != member {
+                        dictionary.add(member.clone(), other.clone());
+                    }
+                }
+            }
+        }
+        for (acronym, expansions) in &file.acronyms {
+            let acronym = query::normalize_words(acronym);
+            if acronym.is_empty() {
+                continue;
+            }
+            for expansion in expansions {
+                let expansion = query::normalize_words(expansion);
+                if !expansion.is_empty() && expansion != acronym {
+                    dictionary.add(acronym.clone(), expansion);
+                }
+            }
+        }
+        dictionary
+    }
+
+    fn add(&mut self, entry: Vec<String>, expansion: Vec<String>) {
+        let expansions = self.entries.entry(entry).or_default();
+        if !expansions.contains(&expansion) {
+            expansions.push(expansion);
+        }
+    }
+
+    /// Add the entries of `other` after this dictionary's own.
+    pub fn extend(&mut self, other: &Dictionary) {
+        for (entry, expansions) in &other.entries {
+            for expansion in expansions {
+                self.add(entry.clone(), expansion.clone());
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Append the dictionary expansions of `query`'s scored clauses.
+///
+/// Expansions that repeat a clause already in the query are skipped. A
+/// query with nothing to expand is returned unchanged.
+pub fn expand(query: &ParsedQuery, dictionary: &Dictionary) -> ParsedQuery {
+    let mut expanded = query.clone();
+    for clause in &query.clauses {
+        if clause.operator == Operator::Excluded {
+            continue;
+        }
+        let Some(expansions) = dictionary.entries.get(&clause.words) else {
+            continue;
+        };
+        for words in expansions {
+            if expanded.clauses.iter().any(|c| &c.words == words) {
+                continue;
+            }
+            expanded.clauses.push(Clause {
+                quoted: words.len() > 1,
+                words: words.clone(),
+                operator: Operator::Optional,
+                expanded_from: Some(clause.words.join(" ")),
+            });
+        }
+    }
+    expanded
+}
+
+/// The `why.query_terms` of a document scored against `query`.
+///
+/// A plain list of words, as context-core reports it, unless the query was
+/// expanded: then each word is an object marking it `original` or
+/// `expanded`, with the query text an expansion came from.
+pub fn query_terms(query: &ParsedQuery) -> serde_json::Value {
+    if !query.is_expanded() {
+        return serde_json::json!(query.positive_terms());
+    }
+
+    let mut seen: Vec<&str> = Vec::new();
+    let mut terms = Vec::new();
+    for clause in query.positive_clauses() {
+        for word in &clause.words {
+            if seen.contains(&word.as_str()) {
+                continue;
+            }
+            seen.push(word);
+            terms.push(match &clause.expanded_from {
+                None => serde_json::json!({"term": word, "kind": "original"}),
+                Some(from) => serde_json::json!({"term": word, "kind": "expanded", "expanded_from": from}),
+            });
+        }
+    }
+    terms.into()
+}
@@ -0,0 +1,171 @@
+//! `explain: true` annotations for `context.resolve`.
+//!
+//! Explain mode re-ranks every document with [`selection::rank_all`] and
+//! annotates the serialized selection in place: each selected
+//! document's `why` gains its rank and per-term match positions, and a
+//! top-level `explain` block lists the score formula and every document
+//! that was not selected, with the reason it was dropped. The annotations
+//! are derived only from cache contents and the query, so explained output
+//! is as deterministic as plain output.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::selection::{self, Candidate, Excluded, ExclusionReason};
+
+/// How context-core computes a document's `score` from its `why` inputs.
+/// Server-side scoring modes report their own formula (see
+/// [`crate::scoring`]).
+pub const SCORE_FORMULA: &str = "term_matches / total_words";
+
+/// Maximum match positions reported per term. `matches` is always exact.
+pub const MAX_POSITIONS_PER_TERM: usize = 32;
+
+/// Top-level `explain` block of an explained selection.
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    pub score_formula: &'static str,
+    /// Documents that were ranked but not selected, in rank order.
+    pub excluded: Vec<ExcludedDocument>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExcludedDocument {
+    pub id: String,
+    pub version: String,
+    pub rank: usize,
+    pub score: f64,
+    pub tokens: usize,
+    pub reason: ExclusionReason,
+}
+
+/// Matches of one query term within a document.
+#[derive(Debug, Serialize)]
+pub struct TermMatches {
+    pub term: String,
+    pub matches: usize,
+    /// The first [`MAX_POSITIONS_PER_TERM`] matches, in document order.
+    pub positions: Vec<Position>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Position {
+    /// 1-based line number.
+    pub line: usize,
+    /// 0-based word index, counting words as `total_words` does.
+    pub word: usize,
+}
+
+/// Per-term matches of `terms` in `content`. Duplicate terms are reported
+/// once, in first-occurrence order.
+pub fn term_matches(content: &str, terms: &[String]) -> Vec<TermMatches> {
+    let words = selection::words(content);
+    let mut out: Vec<TermMatches> = Vec::new();
+
+    for term in terms {
+        if out.iter().any(|t| &t.term == term) {
+            continue;
+        }
+        let hits: Vec<Position> = words
+            .iter()
+            .filter(|w| &w.text == term)
+            .map(|w| Position {
+                line: w.line,
+                word: w.index,
+            })
+            .collect();
+        out.push(TermMatches {
+            term: term.clone(),
+            matches: hits.len(),
+            positions: hits.into_iter().take(MAX_POSITIONS_PER_TERM).collect(),
+        });
+    }
+
+    out
+}
+
+/// Annotate a serialized selection with explain data.
+///
+/// `ranked` must be the full ranking for the same cache and query (see
+/// [`selection::rank_all`]), `excluded` the ranked documents left out of
+/// the selection, and `score_formula` how `ranked` was scored.
+pub fn annotate(
+    result: &mut serde_json::Value,
+    ranked: &[Candidate],
+    excluded: &[Excluded],
+    score_formula: &'static str,
+) -> Result<(), serde_json::Error> {
+    let positions: HashMap<&str, usize> = ranked
+        .iter()
+        .enumerate()
+        .map(|(idx, c)| (c.id.as_str(), idx))
+        .collect();
+    let rank_of = |id: &str| positions.get(id).copied();
+
+    if let Some(documents) = result.get_mut("documents").and_then(|d| d.as_array_mut()) {
+        for doc in documents {
+            let Some(idx) = doc["id"].as_str().and_then(rank_of) else {
+                continue;
+            };
+            let candidate = &ranked[idx];
+            let terms = term_matches(&candidate.content, &candidate.query_terms());
+
+            if let Some(why) = doc.get_mut("why").and_then(|w| w.as_object_mut()) {
+                why.insert("rank".into(), (idx + 1).into());
+                why.insert("terms".into(), serde_json::to_value(terms)?);
+            }
+        }
+    }
+
+    let mut excluded: Vec<ExcludedDocument> = excluded
+        .iter()
+        .filter_map(|e| {
+            let idx = rank_of(&e.id)?;
+            let candidate = &ranked[idx];
+            Some(ExcludedDocument {
+                id: e.id.clone(),
+                version: candidate.version.clone(),
+                rank: idx + 1,
+                score: candidate.score,
+                tokens: e.tokens,
+                reason: e.reason,
+            })
+        })
+        .collect();
+    excluded.sort_by_key(|e| e.rank);
+
+    let explanation = Explanation {
+        score_formula,
+        excluded,
+    };
+    if let Some(obj) = result.as_object_mut() {
+        obj.insert("explain".into(), serde_json::to_value(explanation)?);
+    }
+    Ok(())
+}
+
+/// Documents of `ranked` missing from context-core's serialized `result`.
+///
+/// context-core leaves out documents that match no query term and those
+/// that do not fit the budget.
+pub fn budget_exclusions(result: &serde_json::Value, ranked: &[Candidate]) -> Vec<Excluded> {
+    let selected: HashSet<&str> = result["documents"]
+        .as_array()
+        .map(|docs| docs.iter().filter_map(|d| d["id"].as_str()).collect())
+        .unwrap_or_default();
+
+    ranked
+        .iter()
+        .filter(|c| !selected.contains(c.id.as_str()))
+        .map(|c| Excluded {
+            id: c.id.clone(),
+            tokens: c.tokens,
+            reason: if c.score > 0.0 {
+                ExclusionReason::BudgetExceeded
+            } else {
+                ExclusionReason::NoMatch
+            },
+        })
+        .collect()
+}
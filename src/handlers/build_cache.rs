@@ -0,0 +1,391 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use context_core::cache::{CacheBuildConfig, CacheBuilder};
+use context_core::document::{Document, DocumentId, Metadata};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{error, warn};
+
+use crate::config::ServerConfig;
+use crate::manifest::{self, ManifestDocument, ManifestError};
+use crate::notifications::Progress;
+use crate::protocol::{BuildCacheParams, McpErrorCode, McpErrorResponse, ToolResult};
+use crate::redaction::{RedactStage, RedactionCounts, Redactor};
+
+use super::run_blocking;
+
+#[derive(Debug, Serialize)]
+struct BuildCacheResponse {
+    cache: String,
+    cache_version: String,
+    document_count: usize,
+    /// Source files left out because they are not UTF-8 text, relative to
+    /// the source directory and sorted.
+    skipped: Vec<String>,
+    /// What an incremental refresh changed relative to the previous cache.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh: Option<RefreshCounts>,
+    /// Secrets redacted from the sources, by pattern, when the cache
+    /// redacts at build time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redactions: Option<RedactionCounts>,
+}
+
+/// Document counts of an incremental refresh.
+#[derive(Debug, Default, Serialize)]
+struct RefreshCounts {
+    added: usize,
+    changed: usize,
+    removed: usize,
+    unchanged: usize,
+}
+
+/// A regular file under a cache's source directory.
+pub(crate) struct SourceFile {
+    /// Path relative to the source directory, `/`-separated.
+    pub relative: String,
+    pub path: PathBuf,
+}
+
+/// Handle a `context.build_cache` tool call.
+///
+/// Ingests every regular file under the cache's configured `source`
+/// directory (hidden entries and symlinks are skipped) and builds the cache
+/// with context-core's `CacheBuilder` into a temporary directory under the
+/// cache root, which then replaces the cache by rename. Clients never see a
+/// half-written cache. Progress is reported per ingested file.
+///
+/// With `incremental`, documents of the existing cache whose source file
+/// hashes to the same `version` are reused instead of re-ingested. The new
+/// cache is still written by `CacheBuilder` from the full document set, so
+/// it is identical to a full rebuild of the same sources.
+///
+/// A cache with the `build` redaction stage has secrets replaced in each
+/// source file before it is hashed and ingested.
+///
+/// Builds of the same cache run one at a time, so one build never renames
+/// away a cache another is still installing.
+pub async fn handle(params: BuildCacheParams, config: &ServerConfig, progress: Progress) -> ToolResult {
+    if !is_cache_name(&params.cache) {
+        return ToolResult::error(format!("Invalid cache name: {}", params.cache));
+    }
+    let Some(source) = config
+        .caches
+        .get(&params.cache)
+        .and_then(|settings| settings.source.clone())
+    else {
+        return ToolResult::error(format!(
+            "No source directory configured for cache: {}",
+            params.cache
+        ));
+    };
+
+    let cache_root = config.cache_root.clone();
+    let redactor = config.redactor_for(&params.cache, RedactStage::Build);
+    run_blocking(config, move || {
+        let lock = build_lock(&params.cache);
+        let _building = lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let response = build(
+            &cache_root,
+            &params.cache,
+            &source,
+            params.incremental,
+            redactor.as_deref(),
+            &progress,
+        )?;
+        let json = serde_json::to_string(&response).map_err(|e| {
+            error!(error = %e, "Serialization failed");
+            McpErrorResponse::canonical(McpErrorCode::InternalError)
+        })?;
+        Ok(ToolResult::text(json).with_cache_version(response.cache_version))
+    })
+    .await
+}
+
+fn build(
+    cache_root: &Path,
+    name: &str,
+    source: &Path,
+    incremental: bool,
+    redactor: Option<&Redactor>,
+    progress: &Progress,
+) -> Result<BuildCacheResponse, McpErrorResponse> {
+    progress.report(0, None, "Scanning source directory");
+    let files = source_files(source).map_err(|e| {
+        warn!(error = %e, "Cannot read source directory");
+        McpErrorResponse::canonical(McpErrorCode::IoError)
+    })?;
+    let previous = incremental.then(|| PreviousCache::load(&cache_root.join(name)));
+
+    let total = files.len() as u64;
+    let step = (files.len() / 100).max(1);
+    let mut documents = Vec::with_capacity(files.len());
+    let mut skipped = Vec::new();
+    let mut refresh = previous.as_ref().map(|_| RefreshCounts::default());
+    let mut redactions = redactor.map(|_| RedactionCounts::new());
+    for (i, file) in files.iter().enumerate() {
+        let mut bytes = read_source(file)?;
+        if let (Some(redactor), Some(counts), Ok(text)) =
+            (redactor, &mut redactions, std::str::from_utf8(&bytes))
+        {
+            bytes = redactor
+                .redact(text, counts)
+                .map_err(|e| {
+                    error!(error = %e, "Redaction failed");
+                    McpErrorResponse::canonical(McpErrorCode::InternalError)
+                })?
+                .into_bytes();
+        }
+        let reused = previous
+            .as_ref()
+            .and_then(|previous| previous.reuse(&file.relative, &bytes));
+        let unchanged = reused.is_some();
+        let document = match reused {
+            Some(document) => Some(document),
+            None => ingest(source, file, bytes)?,
+        };
+        match document {
+            Some(document) => {
+                if let (Some(refresh), Some(previous)) = (&mut refresh, &previous) {
+                    if unchanged {
+                        refresh.unchanged += 1;
+                    } else if previous.documents.contains_key(document.id.as_str()) {
+                        refresh.changed += 1;
+                    } else {
+                        refresh.added += 1;
+                    }
+                }
+                documents.push(document);
+            }
+            None => skipped.push(file.relative.clone()),
+        }
+        if (i + 1) % step == 0 {
+            progress.report(i as u64 + 1, Some(total), "Ingesting documents");
+        }
+    }
+    if let (Some(refresh), Some(previous)) = (&mut refresh, &previous) {
+        let current: BTreeSet<&str> = documents.iter().map(|d| d.id.as_str()).collect();
+        refresh.removed = previous
+            .documents
+            .keys()
+            .filter(|id| !current.contains(id.as_str()))
+            .count();
+    }
+
+    progress.report(total, Some(total), "Writing cache");
+    let staging = staging_dir(cache_root, name);
+    let result = CacheBuilder::new(CacheBuildConfig::v0())
+        .build(documents, &staging)
+        .map_err(|e| {
+            error!(error = %e, "Cache build failed");
+            McpErrorResponse::canonical(McpErrorCode::InternalError)
+        })
+        .and_then(|_| {
+            manifest::read_manifest(&staging).map_err(|e| {
+                error!(error = %e, "Built cache is unreadable");
+                McpErrorResponse::canonical(McpErrorCode::InternalError)
+            })
+        })
+        .and_then(|view| {
+            replace_cache(cache_root, name, &staging).map_err(|e| {
+                warn!(error = %e, "Cannot install built cache");
+                McpErrorResponse::canonical(McpErrorCode::IoError)
+            })?;
+            Ok(view)
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_dir_all(&staging);
+    }
+    let view = result?;
+
+    Ok(BuildCacheResponse {
+        cache: name.to_string(),
+        cache_version: view.cache_version,
+        document_count: view.document_count,
+        skipped,
+        refresh,
+        redactions,
+    })
+}
+
+/// The lock held while cache `name` is built in this process.
+fn build_lock(name: &str) -> Arc<Mutex<()>> {
+    static LOCKS: OnceLock<Mutex<HashMap<String, Arc<Mutex<()>>>>> = OnceLock::new();
+    let mut locks = LOCKS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    Arc::clone(locks.entry(name.to_string()).or_default())
+}
+
+/// Whether `name` can name a cache directory directly under the cache root.
+fn is_cache_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+/// Regular files under `source`, sorted by relative path. Hidden files and
+/// directories and symlinks are skipped, so a build never leaves `source`.
+pub(crate) fn source_files(source: &Path) -> std::io::Result<Vec<SourceFile>> {
+    let mut files = Vec::new();
+    let mut pending = vec![(source.to_path_buf(), String::new())];
+    while let Some((dir, prefix)) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                warn!(dir = %dir.display(), "Skipping source entry with a non-UTF-8 name");
+                continue;
+            };
+            if file_name.starts_with('.') {
+                continue;
+            }
+
+            let relative = format!("{prefix}{file_name}");
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push((entry.path(), format!("{relative}/")));
+            } else if file_type.is_file() {
+                files.push(SourceFile {
+                    relative,
+                    path: entry.path(),
+                });
+            }
+        }
+    }
+    files.sort_by(|a, b| a.relative.cmp(&b.relative));
+    Ok(files)
+}
+
+/// The raw bytes of a source file.
+fn read_source(file: &SourceFile) -> Result<Vec<u8>, McpErrorResponse> {
+    std::fs::read(&file.path).map_err(|e| {
+        warn!(error = %e, "Cannot read source file");
+        McpErrorResponse::canonical(McpErrorCode::IoError)
+    })
+}
+
+/// Ingest one source file's bytes as a document; `None` if they are not
+/// UTF-8 text.
+pub(crate) fn ingest(
+    source: &Path,
+    file: &SourceFile,
+    bytes: Vec<u8>,
+) -> Result<Option<Document>, McpErrorResponse> {
+    if std::str::from_utf8(&bytes).is_err() {
+        return Ok(None);
+    }
+
+    let id = DocumentId::from_path(source, &file.path).map_err(|e| {
+        error!(error = %e, "Invalid document id");
+        McpErrorResponse::canonical(McpErrorCode::InternalError)
+    })?;
+    let document = Document::ingest(id, file.relative.clone(), bytes, Metadata::default())
+        .map_err(|e| {
+            error!(error = %e, "Cannot ingest document");
+            McpErrorResponse::canonical(McpErrorCode::InternalError)
+        })?;
+    Ok(Some(document))
+}
+
+/// The document `version` context-core assigns to source `bytes`.
+fn content_version(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    format!("sha256:{hex}")
+}
+
+/// Documents of the cache an incremental build refreshes, by id.
+struct PreviousCache {
+    root: PathBuf,
+    documents: BTreeMap<String, ManifestDocument>,
+}
+
+impl PreviousCache {
+    /// The cache at `cache_path`. A missing, unreadable or unsupported
+    /// manifest yields an empty cache, so every file is ingested afresh.
+    fn load(cache_path: &Path) -> Self {
+        let documents = match manifest::read_manifest(cache_path) {
+            Ok(view) if view.is_supported_version() => view
+                .documents
+                .into_iter()
+                .map(|doc| (doc.id.clone(), doc))
+                .collect(),
+            Ok(view) => {
+                warn!(version = ?view.build_config.version, "Rebuilding in full: unsupported build_config.version");
+                BTreeMap::new()
+            }
+            Err(ManifestError::Missing) => BTreeMap::new(),
+            Err(e) => {
+                warn!(error = %e, "Rebuilding in full");
+                BTreeMap::new()
+            }
+        };
+        Self {
+            root: cache_path.to_path_buf(),
+            documents,
+        }
+    }
+
+    /// The cached document for `id` if it was built from exactly `bytes`.
+    ///
+    /// Any doubt (a document file outside the cache, an unreadable one, or
+    /// one that disagrees with the manifest) returns `None` so the file is
+    /// re-ingested.
+    fn reuse(&self, id: &str, bytes: &[u8]) -> Option<Document> {
+        let entry = self.documents.get(id)?;
+        if !entry.has_valid_path() || entry.version != content_version(bytes) {
+            return None;
+        }
+        let file = std::fs::File::open(self.root.join(&entry.file)).ok()?;
+        let document: Document = serde_json::from_reader(std::io::BufReader::new(file)).ok()?;
+        (document.id.as_str() == id && document.version == entry.version).then_some(document)
+    }
+}
+
+/// A fresh directory path under the cache root to build `name` into.
+///
+/// Names are unique per build, so concurrent builds never share a
+/// directory, and start with a dot, which cache names cannot.
+pub(crate) fn staging_dir(cache_root: &Path, name: &str) -> PathBuf {
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+    cache_root.join(format!(
+        ".{name}.build-{}-{}",
+        std::process::id(),
+        BUILDS.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Whether `name`, an entry of the cache root, is a staging directory from
+/// [`staging_dir`] or a cache [`replace_cache`] retired. Either is only left
+/// behind by a build that was interrupted.
+pub(crate) fn is_build_leftover(name: &str) -> bool {
+    name.starts_with('.') && name.contains(".build-")
+}
+
+/// Move the built cache at `staging` into place as `cache_root/name`.
+///
+/// A directory cannot be renamed over a non-empty one, so an existing cache
+/// is first renamed aside, then removed once the new one is in place. If
+/// the final rename fails the old cache is put back.
+pub(crate) fn replace_cache(cache_root: &Path, name: &str, staging: &Path) -> std::io::Result<()> {
+    let target = cache_root.join(name);
+    if !target.exists() {
+        return std::fs::rename(staging, &target);
+    }
+
+    let mut retired = staging.as_os_str().to_owned();
+    retired.push(".old");
+    let retired = PathBuf::from(retired);
+    std::fs::rename(&target, &retired)?;
+    if let Err(e) = std::fs::rename(staging, &target) {
+        let _ = std::fs::rename(&retired, &target);
+        return Err(e);
+    }
+    if let Err(e) = std::fs::remove_dir_all(&retired) {
+        warn!(error = %e, "Cannot remove previous cache");
+    }
+    Ok(())
+}
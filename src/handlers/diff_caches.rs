@@ -0,0 +1,209 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use serde::Serialize;
+use tracing::{error, warn};
+
+use crate::config::ServerConfig;
+use crate::manifest::{self, DocumentFile, ManifestView};
+use crate::protocol::{DiffCachesParams, McpErrorCode, McpErrorResponse, ToolResult};
+use crate::selection;
+
+use super::{load_cache, resolve_cache_path, run_blocking};
+
+#[derive(Debug, Serialize)]
+struct DiffCachesResponse {
+    base: CacheSide,
+    target: CacheSide,
+    /// `true` when both caches have the same `cache_version`.
+    identical: bool,
+    documents: DocumentChanges,
+    metadata_changes: Vec<MetadataChange>,
+    total_tokens_delta: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct CacheSide {
+    cache: String,
+    cache_version: String,
+    created_at: serde_json::Value,
+    build_config: serde_json::Value,
+    document_count: usize,
+    total_tokens: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct DocumentChanges {
+    added: Vec<DocumentRef>,
+    removed: Vec<DocumentRef>,
+    changed: Vec<ChangedDocument>,
+    unchanged: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct DocumentRef {
+    id: String,
+    version: String,
+    tokens: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ChangedDocument {
+    id: String,
+    base_version: String,
+    target_version: String,
+    tokens_delta: i64,
+}
+
+/// A metadata key that differs for a document present in both caches.
+/// A side without the key is reported as `null`.
+#[derive(Debug, Serialize)]
+struct MetadataChange {
+    id: String,
+    key: String,
+    base: serde_json::Value,
+    target: serde_json::Value,
+}
+
+/// One cache's manifest summary plus per-document state, keyed by id.
+struct Snapshot {
+    side: CacheSide,
+    documents: BTreeMap<String, DocumentState>,
+}
+
+struct DocumentState {
+    version: String,
+    tokens: usize,
+    metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Handle a `context.diff_caches` tool call.
+///
+/// Compares the manifests of two caches under the cache root (typically two
+/// builds of the same sources) and reports added, removed, and changed
+/// documents by version hash, per-document metadata changes, and the change
+/// in total tokens. All lists are sorted by document id for determinism.
+pub async fn handle(params: DiffCachesParams, config: &ServerConfig) -> ToolResult {
+    let base_path = match resolve_cache_path(&config.cache_root, &params.base) {
+        Ok(p) => p,
+        Err(err) => return err.into(),
+    };
+    let target_path = match resolve_cache_path(&config.cache_root, &params.target) {
+        Ok(p) => p,
+        Err(err) => return err.into(),
+    };
+
+    run_blocking(config, move || {
+        let base = snapshot(&params.base, &base_path)?;
+        let target = snapshot(&params.target, &target_path)?;
+        let cache_version = target.side.cache_version.clone();
+
+        let json = serde_json::to_string(&diff(base, target)).map_err(|e| {
+            error!(error = %e, "Serialization failed");
+            McpErrorResponse::canonical(McpErrorCode::InternalError)
+        })?;
+        Ok(ToolResult::text(json).with_cache_version(cache_version))
+    })
+    .await
+}
+
+fn snapshot(name: &str, cache_path: &Path) -> Result<Snapshot, McpErrorResponse> {
+    let cache = load_cache(cache_path)?;
+
+    let manifest_value = serde_json::to_value(&cache.manifest).map_err(|e| {
+        error!(error = %e, "Serialization failed");
+        McpErrorResponse::canonical(McpErrorCode::InternalError)
+    })?;
+    let view: ManifestView = serde_json::from_value(manifest_value.clone()).map_err(|e| {
+        warn!(error = %e, "Invalid manifest");
+        McpErrorResponse::canonical(McpErrorCode::CacheInvalid)
+    })?;
+
+    // Each document file is read once, for its metadata and token count.
+    let mut documents = BTreeMap::new();
+    for doc in &view.documents {
+        let DocumentFile { content, metadata } = manifest::read_document(cache_path, doc).map_err(|e| {
+            warn!(error = %e, "Cannot read document");
+            McpErrorResponse::canonical(McpErrorCode::CacheInvalid)
+        })?;
+        documents.insert(
+            doc.id.clone(),
+            DocumentState {
+                version: doc.version.clone(),
+                tokens: selection::estimate_tokens(&content),
+                metadata,
+            },
+        );
+    }
+
+    let side = CacheSide {
+        cache: name.to_string(),
+        cache_version: view.cache_version,
+        created_at: manifest_value.get("created_at").cloned().unwrap_or_default(),
+        build_config: manifest_value.get("build_config").cloned().unwrap_or_default(),
+        document_count: view.document_count,
+        total_tokens: documents.values().map(|d| d.tokens).sum(),
+    };
+
+    Ok(Snapshot { side, documents })
+}
+
+fn diff(base: Snapshot, target: Snapshot) -> DiffCachesResponse {
+    let mut changes = DocumentChanges::default();
+    let mut metadata_changes = Vec::new();
+
+    for (id, old) in &base.documents {
+        let Some(new) = target.documents.get(id) else {
+            changes.removed.push(DocumentRef {
+                id: id.clone(),
+                version: old.version.clone(),
+                tokens: old.tokens,
+            });
+            continue;
+        };
+
+        if old.version == new.version {
+            changes.unchanged += 1;
+        } else {
+            changes.changed.push(ChangedDocument {
+                id: id.clone(),
+                base_version: old.version.clone(),
+                target_version: new.version.clone(),
+                tokens_delta: new.tokens as i64 - old.tokens as i64,
+            });
+        }
+
+        let keys: BTreeSet<&String> = old.metadata.keys().chain(new.metadata.keys()).collect();
+        for key in keys {
+            let before = old.metadata.get(key).cloned().unwrap_or_default();
+            let after = new.metadata.get(key).cloned().unwrap_or_default();
+            if before != after {
+                metadata_changes.push(MetadataChange {
+                    id: id.clone(),
+                    key: key.clone(),
+                    base: before,
+                    target: after,
+                });
+            }
+        }
+    }
+
+    for (id, new) in &target.documents {
+        if !base.documents.contains_key(id) {
+            changes.added.push(DocumentRef {
+                id: id.clone(),
+                version: new.version.clone(),
+                tokens: new.tokens,
+            });
+        }
+    }
+
+    DiffCachesResponse {
+        identical: base.side.cache_version == target.side.cache_version,
+        total_tokens_delta: target.side.total_tokens as i64 - base.side.total_tokens as i64,
+        base: base.side,
+        target: target.side,
+        documents: changes,
+        metadata_changes,
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+use tracing::error;
+
+use crate::budget;
+use crate::config::ServerConfig;
+use crate::protocol::{DiffResolveParams, McpErrorCode, McpErrorResponse, ToolResult};
+use crate::selection::{self, Candidate};
+
+use super::{load_cache, resolve_cache_path, run_blocking};
+
+#[derive(Debug, Serialize)]
+struct DiffResolveResponse {
+    query: String,
+    budget: usize,
+    base: SelectionSide,
+    target: SelectionSide,
+    /// `true` when both selections contain the same documents, versions,
+    /// and scores in the same order.
+    identical: bool,
+    /// Selected from `target` but not from `base`, in target rank order.
+    entered: Vec<RankedDocument>,
+    /// Selected from `base` but not from `target`, in base rank order.
+    left: Vec<RankedDocument>,
+    /// Selected from both, in target rank order.
+    retained: Vec<RetainedDocument>,
+}
+
+#[derive(Debug, Serialize)]
+struct SelectionSide {
+    cache: String,
+    cache_version: String,
+    documents_selected: usize,
+    tokens_used: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct RankedDocument {
+    id: String,
+    version: String,
+    rank: usize,
+    score: f64,
+    tokens: usize,
+}
+
+/// A document selected from both caches. Ranks are 1-based; a positive
+/// `rank_delta` means the document moved up in `target`.
+#[derive(Debug, Serialize)]
+struct RetainedDocument {
+    id: String,
+    base_rank: usize,
+    target_rank: usize,
+    rank_delta: i64,
+    base_score: f64,
+    target_score: f64,
+    score_delta: f64,
+    version_changed: bool,
+}
+
+/// One cache's selection for the query and budget.
+struct Selected {
+    side: SelectionSide,
+    documents: Vec<Candidate>,
+}
+
+/// Handle a `context.diff_resolve` tool call.
+///
+/// Runs the same query and budget against two caches and compares the
+/// resulting selections: documents that entered or left the selection,
+/// rank changes, and score deltas for documents selected from both.
+pub async fn handle(params: DiffResolveParams, config: &ServerConfig) -> ToolResult {
+    let budget = match budget::resolve(&params.budget, &config.profiles) {
+        Ok(b) => b,
+        Err(err) => return err.into(),
+    };
+
+    let base_path = match resolve_cache_path(&config.cache_root, &params.base) {
+        Ok(p) => p,
+        Err(err) => return err.into(),
+    };
+    let target_path = match resolve_cache_path(&config.cache_root, &params.target) {
+        Ok(p) => p,
+        Err(err) => return err.into(),
+    };
+
+    run_blocking(config, move || {
+        let base = select(&params.base, &base_path, &params.query, budget)?;
+        let target = select(&params.target, &target_path, &params.query, budget)?;
+        let cache_version = target.side.cache_version.clone();
+
+        let json = serde_json::to_string(&diff(params.query, budget, base, target)).map_err(|e| {
+            error!(error = %e, "Serialization failed");
+            McpErrorResponse::canonical(McpErrorCode::InternalError)
+        })?;
+        Ok(ToolResult::text(json).with_cache_version(cache_version))
+    })
+    .await
+}
+
+fn select(
+    name: &str,
+    cache_path: &Path,
+    query: &str,
+    budget: usize,
+) -> Result<Selected, McpErrorResponse> {
+    let cache = load_cache(cache_path)?;
+
+    let documents = selection::select(&cache, query, budget).map_err(|e| {
+        error!(error = %e, "Selection failed");
+        McpErrorResponse::canonical(McpErrorCode::InternalError)
+    })?;
+
+    let side = SelectionSide {
+        cache: name.to_string(),
+        cache_version: cache.manifest.cache_version.clone(),
+        documents_selected: documents.len(),
+        tokens_used: documents.iter().map(|d| d.tokens).sum(),
+    };
+
+    Ok(Selected { side, documents })
+}
+
+fn diff(query: String, budget: usize, base: Selected, target: Selected) -> DiffResolveResponse {
+    let base_ranks: BTreeMap<&str, (usize, &Candidate)> = base
+        .documents
+        .iter()
+        .enumerate()
+        .map(|(i, d)| (d.id.as_str(), (i + 1, d)))
+        .collect();
+    let target_ids: BTreeMap<&str, usize> = target
+        .documents
+        .iter()
+        .enumerate()
+        .map(|(i, d)| (d.id.as_str(), i + 1))
+        .collect();
+
+    let mut entered = Vec::new();
+    let mut retained = Vec::new();
+    for (i, new) in target.documents.iter().enumerate() {
+        let target_rank = i + 1;
+        match base_ranks.get(new.id.as_str()) {
+            Some(&(base_rank, old)) => retained.push(RetainedDocument {
+                id: new.id.clone(),
+                base_rank,
+                target_rank,
+                rank_delta: base_rank as i64 - target_rank as i64,
+                base_score: old.score,
+                target_score: new.score,
+                score_delta: new.score - old.score,
+                version_changed: old.version != new.version,
+            }),
+            None => entered.push(ranked(new, target_rank)),
+        }
+    }
+
+    let left = base
+        .documents
+        .iter()
+        .enumerate()
+        .filter(|(_, old)| !target_ids.contains_key(old.id.as_str()))
+        .map(|(i, old)| ranked(old, i + 1))
+        .collect();
+
+    let identical = base.documents.len() == target.documents.len()
+        && base.documents.iter().zip(&target.documents).all(|(a, b)| {
+            a.id == b.id && a.version == b.version && a.score == b.score
+        });
+
+    DiffResolveResponse {
+        query,
+        budget,
+        base: base.side,
+        target: target.side,
+        identical,
+        entered,
+        left,
+        retained,
+    }
+}
+
+fn ranked(doc: &Candidate, rank: usize) -> RankedDocument {
+    RankedDocument {
+        id: doc.id.clone(),
+        version: doc.version.clone(),
+        rank,
+        score: doc.score,
+        tokens: doc.tokens,
+    }
+}
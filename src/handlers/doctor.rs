@@ -0,0 +1,342 @@
+use std::path::Path;
+
+use serde::Serialize;
+use tracing::{error, warn};
+
+use crate::config::ServerConfig;
+use crate::index;
+use crate::manifest::{self, ManifestError, SUPPORTED_BUILD_CONFIG_VERSIONS};
+use crate::protocol::{McpErrorCode, McpErrorResponse, ToolResult};
+
+use super::{build_cache, run_blocking};
+
+/// Diagnosis of every cache under the cache root.
+#[derive(Debug, Serialize)]
+pub struct DoctorReport {
+    /// `true` when no finding has `error` severity.
+    pub healthy: bool,
+    pub caches_checked: usize,
+    pub findings: Vec<Finding>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single problem found in the installation.
+///
+/// `path` is relative to the cache root so reports never expose the
+/// server's absolute filesystem layout.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub message: String,
+    pub fix: String,
+}
+
+/// Handle a `context.doctor` tool call.
+///
+/// Walks the configured cache root and reports every problem found, each
+/// with a severity and a suggested fix. Problems are findings, not tool
+/// errors: the call only fails if the report cannot be produced.
+pub async fn handle(config: &ServerConfig) -> ToolResult {
+    let cache_root = config.cache_root.clone();
+    run_blocking(config, move || {
+        serde_json::to_string(&diagnose(&cache_root)).map_err(|e| {
+            error!(error = %e, "Serialization failed");
+            McpErrorResponse::canonical(McpErrorCode::InternalError)
+        })
+    })
+    .await
+}
+
+/// Diagnose every cache under `cache_root`.
+///
+/// Caches are visited in name order (UTF-8 byte order) and checks run in a
+/// fixed order per cache, so the report is deterministic.
+pub fn diagnose(cache_root: &Path) -> DoctorReport {
+    let mut findings = Vec::new();
+    let mut caches_checked = 0;
+
+    let root_canonical = match cache_root.canonicalize() {
+        Ok(p) if p.is_dir() => p,
+        _ => {
+            findings.push(Finding {
+                severity: Severity::Error,
+                code: "cache_root_missing",
+                cache: None,
+                path: None,
+                message: "Cache root does not exist or is not a directory".into(),
+                fix: "Set CONTEXT_CACHE_ROOT (or --cache-root) to an existing directory".into(),
+            });
+            return DoctorReport::new(caches_checked, findings);
+        }
+    };
+
+    let mut entries = match std::fs::read_dir(&root_canonical) {
+        Ok(entries) => entries.filter_map(Result::ok).collect::<Vec<_>>(),
+        Err(e) => {
+            warn!(error = %e, "Cannot read cache root");
+            findings.push(unreadable_dir(None, None));
+            return DoctorReport::new(caches_checked, findings);
+        }
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        // Hidden entries are not caches; only an interrupted build's are
+        // worth mentioning.
+        if name.starts_with('.') {
+            if file_type.is_dir() && build_cache::is_build_leftover(&name) {
+                findings.push(build_leftover(name));
+            }
+            continue;
+        }
+
+        if file_type.is_symlink() {
+            check_symlink(&root_canonical, &entry.path(), None, &name, &mut findings);
+            continue;
+        }
+
+        if file_type.is_dir() {
+            caches_checked += 1;
+            check_cache(&root_canonical, &entry.path(), &name, &mut findings);
+        }
+    }
+
+    DoctorReport::new(caches_checked, findings)
+}
+
+impl DoctorReport {
+    fn new(caches_checked: usize, findings: Vec<Finding>) -> Self {
+        let healthy = findings.iter().all(|f| f.severity != Severity::Error);
+        Self {
+            healthy,
+            caches_checked,
+            findings,
+        }
+    }
+}
+
+fn check_cache(root: &Path, cache_path: &Path, name: &str, findings: &mut Vec<Finding>) {
+    if !walk_cache_dir(root, cache_path, name, name, findings) {
+        return;
+    }
+
+    let manifest_rel = format!("{name}/{}", manifest::MANIFEST_FILE);
+    let manifest = match manifest::read_manifest(cache_path) {
+        Ok(m) => m,
+        Err(err) => {
+            let (code, fix) = match &err {
+                ManifestError::Missing => (
+                    "manifest_missing",
+                    "Rebuild the cache with `context build`, or remove the directory if it is not a cache",
+                ),
+                ManifestError::Unreadable(_) => (
+                    "manifest_unreadable",
+                    "Make manifest.json readable by the server's user",
+                ),
+                ManifestError::InvalidJson(_) => (
+                    "manifest_invalid_json",
+                    "The manifest is corrupt; rebuild the cache with `context build`",
+                ),
+                ManifestError::Invalid(_) => (
+                    "manifest_invalid",
+                    "Rebuild the cache with a context-core version compatible with this server",
+                ),
+            };
+            let message = match &err {
+                // Do not echo OS error text; it may contain absolute paths.
+                ManifestError::Unreadable(_) => "manifest.json cannot be read".to_string(),
+                other => other.to_string(),
+            };
+            findings.push(Finding {
+                severity: Severity::Error,
+                code,
+                cache: Some(name.to_string()),
+                path: Some(manifest_rel),
+                message,
+                fix: fix.into(),
+            });
+            return;
+        }
+    };
+
+    if !manifest.is_supported_version() {
+        findings.push(Finding {
+            severity: Severity::Error,
+            code: "build_config_unsupported",
+            cache: Some(name.to_string()),
+            path: Some(manifest_rel.clone()),
+            message: format!(
+                "Cache was built with build_config.version {:?}; supported versions: {}",
+                manifest.build_config.version,
+                SUPPORTED_BUILD_CONFIG_VERSIONS.join(", ")
+            ),
+            fix: "Rebuild the cache with a context-core version compatible with this server".into(),
+        });
+    }
+
+    if manifest.document_count != manifest.documents.len() {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            code: "document_count_mismatch",
+            cache: Some(name.to_string()),
+            path: Some(manifest_rel.clone()),
+            message: format!(
+                "document_count is {} but the manifest lists {} documents",
+                manifest.document_count,
+                manifest.documents.len()
+            ),
+            fix: "Rebuild the cache with `context build`".into(),
+        });
+    }
+
+    for doc in &manifest.documents {
+        let doc_rel = format!("{name}/{}", doc.file);
+
+        if !doc.has_valid_path() {
+            findings.push(Finding {
+                severity: Severity::Error,
+                code: "document_path_invalid",
+                cache: Some(name.to_string()),
+                path: Some(manifest_rel.clone()),
+                message: format!("Document {:?} points outside the cache directory", doc.id),
+                fix: "Rebuild the cache with `context build`".into(),
+            });
+            continue;
+        }
+
+        match std::fs::symlink_metadata(cache_path.join(&doc.file)) {
+            // Symlinked documents are reported by the directory walk.
+            Ok(meta) if meta.is_file() || meta.file_type().is_symlink() => {}
+            Ok(_) => findings.push(missing_document(name, doc_rel, &doc.id)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                findings.push(missing_document(name, doc_rel, &doc.id))
+            }
+            Err(_) => findings.push(Finding {
+                severity: Severity::Error,
+                code: "document_unreadable",
+                cache: Some(name.to_string()),
+                path: Some(doc_rel),
+                message: format!("Document file for {:?} cannot be read", doc.id),
+                fix: "Make the cache's documents/ directory readable by the server's user".into(),
+            }),
+        }
+    }
+}
+
+/// Recursively walk a cache directory without following symlinks, reporting
+/// unreadable directories and symlinks that leave the cache root.
+///
+/// Returns `false` if `dir` itself cannot be read.
+fn walk_cache_dir(
+    root: &Path,
+    dir: &Path,
+    cache: &str,
+    rel: &str,
+    findings: &mut Vec<Finding>,
+) -> bool {
+    let mut entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(Result::ok).collect::<Vec<_>>(),
+        Err(_) => {
+            findings.push(unreadable_dir(Some(cache), Some(rel.to_string())));
+            return false;
+        }
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        // The inverted index is derived data the server manages itself.
+        if index::is_index_file(&file_name) {
+            continue;
+        }
+        let child_rel = format!("{rel}/{file_name}");
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_symlink() {
+            check_symlink(root, &entry.path(), Some(cache), &child_rel, findings);
+        } else if file_type.is_dir() {
+            walk_cache_dir(root, &entry.path(), cache, &child_rel, findings);
+        }
+    }
+
+    true
+}
+
+fn check_symlink(
+    root: &Path,
+    link: &Path,
+    cache: Option<&str>,
+    rel: &str,
+    findings: &mut Vec<Finding>,
+) {
+    match link.canonicalize() {
+        Ok(target) if target.starts_with(root) => {}
+        Ok(_) => findings.push(Finding {
+            severity: Severity::Error,
+            code: "symlink_escapes_root",
+            cache: cache.map(str::to_string),
+            path: Some(rel.to_string()),
+            message: "Symlink points outside the cache root".into(),
+            fix: "Replace the symlink with a copy of its target, or move the target inside the cache root".into(),
+        }),
+        Err(_) => findings.push(Finding {
+            severity: Severity::Warning,
+            code: "symlink_broken",
+            cache: cache.map(str::to_string),
+            path: Some(rel.to_string()),
+            message: "Symlink target does not exist".into(),
+            fix: "Remove the dangling symlink".into(),
+        }),
+    }
+}
+
+fn unreadable_dir(cache: Option<&str>, path: Option<String>) -> Finding {
+    Finding {
+        severity: Severity::Error,
+        code: "directory_unreadable",
+        cache: cache.map(str::to_string),
+        path,
+        message: "Directory cannot be read".into(),
+        fix: "Grant the server's user read and execute permission on the directory".into(),
+    }
+}
+
+fn build_leftover(path: String) -> Finding {
+    Finding {
+        severity: Severity::Warning,
+        code: "build_leftover",
+        cache: None,
+        path: Some(path),
+        message: "Directory left behind by an interrupted cache build".into(),
+        fix: "Remove the directory once no build is running".into(),
+    }
+}
+
+fn missing_document(cache: &str, path: String, id: &str) -> Finding {
+    Finding {
+        severity: Severity::Error,
+        code: "document_missing",
+        cache: Some(cache.to_string()),
+        path: Some(path),
+        message: format!("Document file for {id:?} is missing"),
+        fix: "Rebuild the cache with `context build`".into(),
+    }
+}
@@ -1,6 +1,11 @@
+use crate::config::ServerConfig;
 use crate::protocol::ToolResult;
 
-/// Stub: health check.
-pub async fn handle() -> ToolResult {
-    ToolResult::text(r#"{"status":"ok"}"#)
+/// Health check, with how many calls each rate limit has refused.
+pub async fn handle(config: &ServerConfig) -> ToolResult {
+    let health = serde_json::json!({
+        "status": "ok",
+        "throttled": config.limits.stats(),
+    });
+    ToolResult::text(health.to_string())
 }
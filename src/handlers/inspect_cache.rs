@@ -1,9 +1,17 @@
-use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::path::Path;
 
+use context_core::cache::CacheManifest;
 use serde::Serialize;
+use tracing::{error, warn};
 
 use crate::config::ServerConfig;
+use crate::index;
+use crate::manifest::{self, DocumentFile, ManifestView};
 use crate::protocol::{InspectCacheParams, McpErrorCode, McpErrorResponse, ToolResult};
+use crate::selection;
+
+use super::{resolve_cache_path, run_blocking};
 
 #[derive(Debug, Serialize)]
 struct InspectCacheResponse {
@@ -11,67 +19,126 @@ struct InspectCacheResponse {
     document_count: usize,
     total_bytes: u64,
     valid: bool,
+    issues: Vec<Issue>,
+    #[serde(flatten)]
+    summary: Option<CacheSummary>,
+}
+
+/// Aggregated manifest and document metadata of a valid cache.
+#[derive(Debug, Serialize)]
+struct CacheSummary {
+    created_at: serde_json::Value,
+    build_config: serde_json::Value,
+    total_tokens: usize,
+    sources: SourceDistribution,
+}
+
+/// Document and token counts grouped by connector metadata.
+///
+/// Documents without a given metadata key are counted under `"unknown"`.
+#[derive(Debug, Default, Serialize)]
+struct SourceDistribution {
+    by_source_type: BTreeMap<String, SourceStats>,
+    by_source_id: BTreeMap<String, SourceStats>,
+    by_connector_version: BTreeMap<String, SourceStats>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct SourceStats {
+    documents: usize,
+    tokens: usize,
+}
+
+/// Why a cache is not valid.
+///
+/// `code` is stable and machine-readable. `pointer` is an RFC 6901 JSON
+/// pointer into `manifest.json` when the issue concerns a specific field.
+/// Issues never carry filesystem paths or OS error text (error_schema.md).
+#[derive(Debug, Serialize)]
+struct Issue {
+    code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pointer: Option<&'static str>,
+}
+
+impl Issue {
+    fn new(code: &'static str) -> Self {
+        Self { code, pointer: None }
+    }
+
+    fn at(code: &'static str, pointer: &'static str) -> Self {
+        Self {
+            code,
+            pointer: Some(pointer),
+        }
+    }
 }
 
 /// Handle a `context.inspect_cache` tool call.
 ///
 /// Resolves the cache name against the configured cache root (with
 /// traversal protection), loads the manifest, and returns structural
-/// metadata. Does not expose document content.
+/// metadata plus a summary of document metadata. Does not expose document
+/// content.
 pub async fn handle(params: InspectCacheParams, config: &ServerConfig) -> ToolResult {
     let cache_path = match resolve_cache_path(&config.cache_root, &params.cache) {
         Ok(p) => p,
         Err(err) => return err.into(),
     };
 
-    match inspect(&cache_path) {
-        Ok(json) => ToolResult::text(json),
-        Err(mcp_err) => mcp_err.into(),
-    }
+    // Summarizing reads every document file; keep it off the async runtime.
+    run_blocking(config, move || inspect(&cache_path)).await
 }
 
-fn inspect(cache_path: &Path) -> Result<String, McpErrorResponse> {
+fn inspect(cache_path: &Path) -> Result<ToolResult, McpErrorResponse> {
     let manifest_path = cache_path.join("manifest.json");
 
-    let mut valid = true;
+    let mut issues = Vec::new();
     let mut cache_version = String::new();
     let mut document_count = 0usize;
+    let mut manifest_value = None;
 
     match std::fs::File::open(&manifest_path) {
         Ok(file) => match serde_json::from_reader::<_, serde_json::Value>(file) {
             Ok(value) => {
-                if let Some(version) = value.get("cache_version").and_then(|v| v.as_str()) {
-                    cache_version = version.to_string();
-                } else {
-                    valid = false;
+                match value.get("cache_version") {
+                    Some(v) => match v.as_str() {
+                        Some(version) => cache_version = version.to_string(),
+                        None => issues.push(Issue::at("manifest_field_invalid", "/cache_version")),
+                    },
+                    None => issues.push(Issue::at("manifest_field_missing", "/cache_version")),
                 }
 
-                if let Some(count) = value.get("document_count").and_then(|v| v.as_u64()) {
-                    document_count = count as usize;
-                } else {
-                    valid = false;
+                match value.get("document_count") {
+                    Some(v) => match v.as_u64() {
+                        Some(count) => document_count = count as usize,
+                        None => issues.push(Issue::at("manifest_field_invalid", "/document_count")),
+                    },
+                    None => issues.push(Issue::at("manifest_field_missing", "/document_count")),
                 }
+
+                manifest_value = Some(value);
             }
             Err(_) => {
-                valid = false;
+                issues.push(Issue::new("manifest_invalid_json"));
             }
         },
         Err(err) => {
             if err.kind() == std::io::ErrorKind::NotFound {
-                valid = false;
+                issues.push(Issue::new("manifest_missing"));
             } else {
-                eprintln!("Cannot read manifest: {err}");
+                warn!(error = %err, "Cannot read manifest");
                 return Err(McpErrorResponse::canonical(McpErrorCode::IoError));
             }
         }
     }
 
-    let total_bytes = if valid {
-        match total_bytes_non_recursive(cache_path) {
+    let total_bytes = if issues.is_empty() {
+        match total_bytes_recursive(cache_path) {
             Ok(val) => val,
             Err(e) => {
-                eprintln!("Error computing total_bytes: {e}");
-                valid = false;
+                warn!(error = %e, "Error computing total_bytes");
+                issues.push(Issue::new("size_unavailable"));
                 0
             }
         }
@@ -79,33 +146,99 @@ fn inspect(cache_path: &Path) -> Result<String, McpErrorResponse> {
         0
     };
 
+    let summary = match manifest_value {
+        Some(value) if issues.is_empty() => match summarize(cache_path, value) {
+            Ok(summary) => Some(summary),
+            Err(issue) => {
+                issues.push(issue);
+                None
+            }
+        },
+        _ => None,
+    };
+
+    let valid = issues.is_empty();
     let payload = InspectCacheResponse {
-        cache_version,
+        cache_version: cache_version.clone(),
         document_count,
         total_bytes,
         valid,
+        issues,
+        summary,
     };
 
-    serde_json::to_string(&payload).map_err(|e| {
-        eprintln!("Serialization failed: {e}");
+    let json = serde_json::to_string(&payload).map_err(|e| {
+        error!(error = %e, "Serialization failed");
         McpErrorResponse::canonical(McpErrorCode::InternalError)
+    })?;
+    let result = ToolResult::text(json);
+    Ok(if valid { result.with_cache_version(cache_version) } else { result })
+}
+
+/// Aggregate manifest and per-document metadata.
+///
+/// Each document file is read once. Token counts are context-core's
+/// estimate of the content, the same numbers `context.resolve` charges
+/// against a budget.
+fn summarize(cache_path: &Path, manifest_value: serde_json::Value) -> Result<CacheSummary, Issue> {
+    let view: ManifestView = serde_json::from_value(manifest_value.clone())
+        .map_err(|_| Issue::new("manifest_invalid"))?;
+    serde_json::from_value::<CacheManifest>(manifest_value.clone())
+        .map_err(|_| Issue::new("manifest_invalid"))?;
+
+    let mut sources = SourceDistribution::default();
+    let mut total_tokens = 0usize;
+
+    for doc in &view.documents {
+        let DocumentFile { content, metadata } = manifest::read_document(cache_path, doc).map_err(|e| {
+            warn!(error = %e, "Cannot read document");
+            Issue::new("documents_unreadable")
+        })?;
+        let tokens = selection::estimate_tokens(&content);
+        total_tokens += tokens;
+
+        for (key, bucket) in [
+            ("source_type", &mut sources.by_source_type),
+            ("source_id", &mut sources.by_source_id),
+            ("connector_version", &mut sources.by_connector_version),
+        ] {
+            let label = match metadata.get(key) {
+                Some(serde_json::Value::String(s)) => s.clone(),
+                Some(serde_json::Value::Null) | None => "unknown".to_string(),
+                Some(other) => other.to_string(),
+            };
+            let stats = bucket.entry(label).or_default();
+            stats.documents += 1;
+            stats.tokens += tokens;
+        }
+    }
+
+    Ok(CacheSummary {
+        created_at: manifest_value.get("created_at").cloned().unwrap_or_default(),
+        build_config: manifest_value.get("build_config").cloned().unwrap_or_default(),
+        total_tokens,
+        sources,
     })
 }
 
-/// Sum file sizes in the cache directory (non-recursive, no symlinks).
-fn total_bytes_non_recursive(root: &Path) -> Result<u64, std::io::Error> {
-    let entries = std::fs::read_dir(root)?;
+/// Sum file sizes in the cache directory and its subdirectories
+/// (including `documents/`). Symlinks are not followed, and the inverted
+/// index is left out so resolving does not change the size.
+fn total_bytes_recursive(dir: &Path) -> Result<u64, std::io::Error> {
+    let entries = std::fs::read_dir(dir)?;
     let mut total = 0u64;
 
     for entry in entries {
         let entry = entry?;
         let file_type = entry.file_type()?;
 
-        if file_type.is_symlink() {
+        if file_type.is_symlink() || index::is_index_file(&entry.file_name().to_string_lossy()) {
             continue;
         }
 
-        if file_type.is_file() {
+        if file_type.is_dir() {
+            total += total_bytes_recursive(&entry.path())?;
+        } else if file_type.is_file() {
             let metadata = entry.metadata()?;
             total += metadata.len();
         }
@@ -113,34 +246,3 @@ fn total_bytes_non_recursive(root: &Path) -> Result<u64, std::io::Error> {
 
     Ok(total)
 }
-
-/// Resolve and validate a cache path, preventing directory traversal.
-///
-/// Same protection as resolve_context: rejects `..`, absolute paths,
-/// canonicalizes both paths, verifies containment.
-fn resolve_cache_path(cache_root: &Path, cache_name: &str) -> Result<PathBuf, McpErrorResponse> {
-    if cache_name.contains("..") || cache_name.starts_with('/') || cache_name.starts_with('\\') {
-        return Err(McpErrorResponse::canonical(McpErrorCode::CacheMissing));
-    }
-
-    let candidate = cache_root.join(cache_name);
-
-    let canonical = candidate.canonicalize().map_err(|_| {
-        McpErrorResponse::canonical(McpErrorCode::CacheMissing)
-    })?;
-
-    let root_canonical = cache_root.canonicalize().map_err(|e| {
-        eprintln!("Cache root not accessible: {e}");
-        McpErrorResponse::canonical(McpErrorCode::IoError)
-    })?;
-
-    if !canonical.starts_with(&root_canonical) {
-        return Err(McpErrorResponse::canonical(McpErrorCode::CacheMissing));
-    }
-
-    if !canonical.is_dir() {
-        return Err(McpErrorResponse::canonical(McpErrorCode::CacheMissing));
-    }
-
-    Ok(canonical)
-}
@@ -1,7 +1,9 @@
 use std::path::Path;
 
 use serde::Serialize;
+use tracing::{error, warn};
 
+use crate::access::Permissions;
 use crate::config::ServerConfig;
 use crate::protocol::{McpErrorCode, McpErrorResponse, ToolResult};
 
@@ -27,19 +29,25 @@ struct CacheEntry {
 /// The server's configured cache root is used; no client-supplied root is
 /// accepted (per mcp_interface.md: "No parameters required").
 pub async fn handle(config: &ServerConfig) -> ToolResult {
-    match enumerate_caches(&config.cache_root) {
+    handle_for(config, &Permissions::all()).await
+}
+
+/// Handle a `context.list_caches` tool call for a client with
+/// `permissions`. Caches the client may not use are left out.
+pub async fn handle_for(config: &ServerConfig, permissions: &Permissions) -> ToolResult {
+    match enumerate_caches(&config.cache_root, permissions) {
         Ok(json) => ToolResult::text(json),
         Err(mcp_err) => mcp_err.into(),
     }
 }
 
-fn enumerate_caches(cache_root: &Path) -> Result<String, McpErrorResponse> {
+fn enumerate_caches(cache_root: &Path, permissions: &Permissions) -> Result<String, McpErrorResponse> {
     if !cache_root.is_dir() {
         return Err(McpErrorResponse::canonical(McpErrorCode::CacheMissing));
     }
 
     let entries = std::fs::read_dir(cache_root).map_err(|e| {
-        eprintln!("Cannot read cache root: {e}");
+        warn!(error = %e, "Cannot read cache root");
         McpErrorResponse::canonical(McpErrorCode::IoError)
     })?;
 
@@ -47,12 +55,12 @@ fn enumerate_caches(cache_root: &Path) -> Result<String, McpErrorResponse> {
 
     for entry in entries {
         let entry = entry.map_err(|e| {
-            eprintln!("Error reading directory entry: {e}");
+            warn!(error = %e, "Error reading directory entry");
             McpErrorResponse::canonical(McpErrorCode::IoError)
         })?;
 
         let file_type = entry.file_type().map_err(|e| {
-            eprintln!("Cannot read file type: {e}");
+            warn!(error = %e, "Cannot read file type");
             McpErrorResponse::canonical(McpErrorCode::IoError)
         })?;
 
@@ -61,7 +69,11 @@ fn enumerate_caches(cache_root: &Path) -> Result<String, McpErrorResponse> {
             continue;
         }
 
+        // Hidden directories are never caches; builds stage into them
         let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || !permissions.allows_cache(&name) {
+            continue;
+        }
         let manifest_path = entry.path().join("manifest.json");
 
         // Check manifest existence without following symlinks
@@ -87,7 +99,7 @@ fn enumerate_caches(cache_root: &Path) -> Result<String, McpErrorResponse> {
 
     let payload = ListCachesResponse { caches };
     serde_json::to_string(&payload).map_err(|e| {
-        eprintln!("Serialization failed: {e}");
+        error!(error = %e, "Serialization failed");
         McpErrorResponse::canonical(McpErrorCode::InternalError)
     })
 }
@@ -1,33 +1,88 @@
+pub mod build_cache;
+pub mod diff_caches;
+pub mod diff_resolve;
+pub mod doctor;
 pub mod health;
 pub mod inspect_cache;
 pub mod list_caches;
 pub mod resolve_context;
 
+use std::path::{Path, PathBuf};
+
+use context_core::cache::{CacheManifest, ContextCache};
+use serde::de::DeserializeOwned;
+use tracing::{error, warn};
+
+use crate::access::{ClientIdentity, Permissions};
 use crate::config::ServerConfig;
+use crate::notifications::{Notifier, Progress};
+use crate::response_limit::Overflow;
 use crate::protocol::{
-    InspectCacheParams, JsonRpcError, JsonRpcRequest, JsonRpcResponse, ResolveContextParams,
-    ToolCallParams, ToolResult,
+    JsonRpcError, JsonRpcRequest, JsonRpcResponse, McpErrorCode, McpErrorResponse, ToolCallParams, ToolResult,
 };
 
 /// Dispatch a JSON-RPC request to the appropriate handler.
 ///
-/// Returns `None` for notifications (no response required).
+/// Returns `None` for notifications (no response required). Notifications
+/// the handler emits are dropped and the client is anonymous; see
+/// [`dispatch_with`].
 pub async fn dispatch(
     req: &JsonRpcRequest,
     config: &ServerConfig,
 ) -> Option<JsonRpcResponse> {
+    dispatch_with(req, config, &Notifier::default(), &ClientIdentity::default()).await
+}
+
+/// Dispatch a JSON-RPC request from `client`, queueing any notifications
+/// the handler emits (such as tool progress) on `notifier`.
+///
+/// `tools/list` and `tools/call` are limited to what the config's access
+/// policy grants `client`, and `tools/call` to the config's rate limits.
+pub async fn dispatch_with(
+    req: &JsonRpcRequest,
+    config: &ServerConfig,
+    notifier: &Notifier,
+    client: &ClientIdentity,
+) -> Option<JsonRpcResponse> {
+    dispatch_recorded(req, config, notifier, client, &mut CallRecord::default()).await
+}
+
+/// What a `tools/call` was dispatched with, for the audit log.
+#[derive(Debug, Clone, Default)]
+pub struct CallRecord {
+    /// The call's params, with the caches it names replaced by the names
+    /// they resolve to. `None` when the params did not parse.
+    pub params: Option<ToolCallParams>,
+    /// `cache_version` of the cache the tool read or built.
+    pub cache_version: Option<String>,
+}
+
+/// [`dispatch_with`], describing a `tools/call` in `record`.
+pub async fn dispatch_recorded(
+    req: &JsonRpcRequest,
+    config: &ServerConfig,
+    notifier: &Notifier,
+    client: &ClientIdentity,
+    record: &mut CallRecord,
+) -> Option<JsonRpcResponse> {
+    let permissions = config.access.permissions(client);
     match req.method.as_str() {
         "initialize" => {
-            let result = serde_json::json!({
+            let mut result = serde_json::json!({
                 "protocolVersion": "2024-11-05",
                 "capabilities": {
-                    "tools": {}
+                    "tools": {},
+                    "logging": {}
                 },
                 "serverInfo": {
                     "name": "mcp-context-server",
                     "version": env!("CARGO_PKG_VERSION")
                 }
             });
+            // Oversized results are paged through resources/read.
+            if config.responses.as_ref().is_some_and(|limit| limit.overflow == Overflow::Resource) {
+                result["capabilities"]["resources"] = serde_json::json!({});
+            }
             Some(JsonRpcResponse::success(req.id.clone(), result))
         }
 
@@ -36,7 +91,7 @@ pub async fn dispatch(
         "ping" => Some(JsonRpcResponse::success(req.id.clone(), serde_json::json!({}))),
 
         "tools/list" => {
-            let result = serde_json::json!({
+            let mut result = serde_json::json!({
                 "tools": [
                     {
                         "name": "context.resolve",
@@ -51,12 +106,55 @@ pub async fn dispatch(
                                 },
                                 "query": {
                                     "type": "string",
-                                    "description": "Search query for context selection"
+                                    "description": "Search query for context selection. Supports \"exact phrases\", +required and -excluded terms"
                                 },
                                 "budget": {
-                                    "type": "integer",
-                                    "description": "Maximum token budget for selected context",
-                                    "minimum": 0
+                                    "description": "Maximum token budget for selected context: a token count, a model profile name from the server config, or a share of a model's context window",
+                                    "oneOf": [
+                                        { "type": "integer", "minimum": 0 },
+                                        { "type": "string" },
+                                        {
+                                            "type": "object",
+                                            "required": ["model_window"],
+                                            "properties": {
+                                                "model_window": { "type": "integer", "minimum": 1 },
+                                                "fraction": { "type": "number", "exclusiveMinimum": 0, "maximum": 1, "default": 1 },
+                                                "reserve": { "type": "integer", "minimum": 0, "default": 0 }
+                                            },
+                                            "additionalProperties": false
+                                        }
+                                    ]
+                                },
+                                "explain": {
+                                    "type": "boolean",
+                                    "description": "Include per-term match positions, ranks, and excluded documents with reasons",
+                                    "default": false
+                                },
+                                "snippets": {
+                                    "type": "boolean",
+                                    "description": "Return the best line windows around query-term matches instead of full content; tokens are charged for snippets only",
+                                    "default": false
+                                },
+                                "dedup": {
+                                    "type": "boolean",
+                                    "description": "Suppress documents whose content hash or word shingles duplicate a higher-ranked document; suppressed documents are listed in selection.duplicates_suppressed",
+                                    "default": false
+                                },
+                                "scoring": {
+                                    "type": "string",
+                                    "enum": scoring_modes(),
+                                    "description": "How documents are scored: \"term_frequency\" is term_matches / total_words; \"bm25\" uses BM25 with corpus statistics from the cache; \"hybrid\" (servers built with the hybrid feature) fuses term frequency with similarity between query and document embeddings",
+                                    "default": "term_frequency"
+                                },
+                                "strategy": {
+                                    "type": "string",
+                                    "enum": ["score", "mmr"],
+                                    "description": "How documents are chosen within the budget: \"score\" packs in score order; \"mmr\" (maximal marginal relevance) trades score against overlap with documents already selected",
+                                    "default": "score"
+                                },
+                                "tokenizer": {
+                                    "type": "string",
+                                    "description": "Tokenizer to count tokens with, by name from the server config (default: the cache's tokenizer, else \"estimate\")"
                                 }
                             }
                         }
@@ -82,14 +180,100 @@ pub async fn dispatch(
                                 }
                             }
                         }
+                    },
+                    {
+                        "name": "context.doctor",
+                        "description": "Diagnose caches under the server's cache root and suggest fixes",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {}
+                        }
+                    },
+                    {
+                        "name": "context.diff_caches",
+                        "description": "Compare two caches: added, removed, and changed documents, metadata changes, and token delta",
+                        "inputSchema": {
+                            "type": "object",
+                            "required": ["base", "target"],
+                            "properties": {
+                                "base": {
+                                    "type": "string",
+                                    "description": "Cache to compare from (relative to CONTEXT_CACHE_ROOT)"
+                                },
+                                "target": {
+                                    "type": "string",
+                                    "description": "Cache to compare to (relative to CONTEXT_CACHE_ROOT)"
+                                }
+                            }
+                        }
+                    },
+                    {
+                        "name": "context.diff_resolve",
+                        "description": "Resolve the same query and budget against two caches and compare the selections",
+                        "inputSchema": {
+                            "type": "object",
+                            "required": ["base", "target", "query", "budget"],
+                            "properties": {
+                                "base": {
+                                    "type": "string",
+                                    "description": "Cache to compare from (relative to CONTEXT_CACHE_ROOT)"
+                                },
+                                "target": {
+                                    "type": "string",
+                                    "description": "Cache to compare to (relative to CONTEXT_CACHE_ROOT)"
+                                },
+                                "query": {
+                                    "type": "string",
+                                    "description": "Search query for context selection"
+                                },
+                                "budget": {
+                                    "description": "Maximum token budget for selected context: a token count, a model profile name from the server config, or a share of a model's context window",
+                                    "oneOf": [
+                                        { "type": "integer", "minimum": 0 },
+                                        { "type": "string" },
+                                        {
+                                            "type": "object",
+                                            "required": ["model_window"],
+                                            "properties": {
+                                                "model_window": { "type": "integer", "minimum": 1 },
+                                                "fraction": { "type": "number", "exclusiveMinimum": 0, "maximum": 1, "default": 1 },
+                                                "reserve": { "type": "integer", "minimum": 0, "default": 0 }
+                                            },
+                                            "additionalProperties": false
+                                        }
+                                    ]
+                                }
+                            }
+                        }
+                    },
+                    {
+                        "name": "context.build_cache",
+                        "description": "Build or rebuild a cache from its configured source directory, replacing the cache atomically; reports progress when the request carries a progressToken",
+                        "inputSchema": {
+                            "type": "object",
+                            "required": ["cache"],
+                            "properties": {
+                                "cache": {
+                                    "type": "string",
+                                    "description": "Cache to build (a name under CONTEXT_CACHE_ROOT with a source directory in the server config)"
+                                },
+                                "incremental": {
+                                    "type": "boolean",
+                                    "description": "Re-ingest only source files added or changed since the existing cache was built; the result is identical to a full rebuild"
+                                }
+                            }
+                        }
                     }
                 ]
             });
+            if let Some(tools) = result["tools"].as_array_mut() {
+                tools.retain(|tool| tool["name"].as_str().is_some_and(|name| permissions.allows_tool(name)));
+            }
             Some(JsonRpcResponse::success(req.id.clone(), result))
         }
 
         "tools/call" => {
-            let params: ToolCallParams = match &req.params {
+            let mut params: ToolCallParams = match &req.params {
                 Some(v) => match serde_json::from_value(v.clone()) {
                     Ok(p) => p,
                     Err(e) => {
@@ -109,7 +293,33 @@ pub async fn dispatch(
                 }
             };
 
-            let tool_result = dispatch_tool_call(&params, config).await;
+            let span = tracing::Span::current();
+            span.record("tool", params.name.as_str());
+            let caches = if permissions.allows_tool(&params.name) {
+                resolve_cache_names(&mut params, config, &permissions)
+            } else {
+                Err(forbidden())
+            };
+            // The arguments now hold the names the caches resolved to.
+            let argument = |name: &str| params.arguments.as_ref()?.get(name)?.as_str();
+            if let Some(cache) = argument("cache").or_else(|| argument("target")) {
+                span.record("cache", cache);
+            }
+            if let Some(base) = argument("base") {
+                span.record("base_cache", base);
+            }
+
+            let progress_token = params.meta.as_ref().and_then(|m| m.progress_token.clone());
+            let progress = Progress::new(notifier.clone(), progress_token);
+            let tool_result = match caches {
+                Err(denied) => denied,
+                Ok(caches) => match admit(&params, config, client, &caches) {
+                    Err(retry_after) => McpErrorResponse::rate_limited(retry_after).into(),
+                    Ok(()) => dispatch_tool_call(&params, config, progress, &permissions).await,
+                },
+            };
+            record.params = Some(params);
+            record.cache_version = tool_result.cache_version.clone();
             let result_json = serde_json::to_value(&tool_result).expect("ToolResult must serialize to JSON Value");
             Some(JsonRpcResponse::success(req.id.clone(), result_json))
         }
@@ -121,50 +331,264 @@ pub async fn dispatch(
     }
 }
 
-async fn dispatch_tool_call(params: &ToolCallParams, config: &ServerConfig) -> ToolResult {
+/// Scoring modes this build of the server accepts.
+fn scoring_modes() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut modes = vec!["term_frequency", "bm25"];
+    #[cfg(feature = "hybrid")]
+    modes.push("hybrid");
+    modes
+}
+
+async fn dispatch_tool_call(
+    params: &ToolCallParams,
+    config: &ServerConfig,
+    progress: Progress,
+    permissions: &Permissions,
+) -> ToolResult {
     match params.name.as_str() {
-        "context.resolve" => {
-            let resolve_params: ResolveContextParams = match &params.arguments {
-                Some(v) => match serde_json::from_value(v.clone()) {
-                    Ok(p) => p,
-                    Err(e) => {
-                        return ToolResult::error(format!(
-                            "Invalid arguments for context.resolve: {e}"
-                        ));
-                    }
-                },
-                None => {
-                    return ToolResult::error(
-                        "Missing arguments for context.resolve",
-                    );
-                }
-            };
-            resolve_context::handle(resolve_params, config).await
+        "context.resolve" => match call(params) {
+            Ok(args) => resolve_context::handle(args, config).await,
+            Err(result) => result,
+        },
+
+        "context.list_caches" => list_caches::handle_for(config, permissions).await,
+
+        "context.inspect_cache" => match call(params) {
+            Ok(args) => inspect_cache::handle(args, config).await,
+            Err(result) => result,
+        },
+
+        "context.doctor" => doctor::handle(config).await,
+
+        "context.diff_caches" => match call(params) {
+            Ok(args) => diff_caches::handle(args, config).await,
+            Err(result) => result,
+        },
+
+        "context.diff_resolve" => match call(params) {
+            Ok(args) => diff_resolve::handle(args, config).await,
+            Err(result) => result,
+        },
+
+        "context.build_cache" => match call(params) {
+            Ok(args) => build_cache::handle(args, config, progress).await,
+            Err(result) => result,
+        },
+
+        "health" => health::handle(config).await,
+
+        _ => ToolResult::error(format!("Unknown tool: {}", params.name)),
+    }
+}
+
+/// Parse the arguments of a call to `params.name`.
+fn call<P: DeserializeOwned>(params: &ToolCallParams) -> Result<P, ToolResult> {
+    let Some(arguments) = &params.arguments else {
+        return Err(ToolResult::error(format!("Missing arguments for {}", params.name)));
+    };
+    serde_json::from_value(arguments.clone())
+        .map_err(|e| ToolResult::error(format!("Invalid arguments for {}: {e}", params.name)))
+}
+
+/// Arguments that name a cache.
+const CACHE_ARGUMENTS: [&str; 3] = ["cache", "base", "target"];
+
+/// Replace each cache a call names with the name it resolves to under the
+/// cache root, so access checks, rate limits, per-cache settings, and the
+/// audit log all see `hr` whether the request said `hr`, `hr/`, or `./hr`.
+/// Returns the names of the caches that exist, which are the ones charged
+/// to per-cache rate limits.
+///
+/// Access is checked on the name as written before the cache is looked up,
+/// so a denial does not reveal whether it exists, then on the resolved name
+/// in case a symlink leads elsewhere. Caches that do not resolve keep the
+/// name as written, for the tool to reject (or, for `context.build_cache`,
+/// create).
+fn resolve_cache_names(
+    params: &mut ToolCallParams,
+    config: &ServerConfig,
+    permissions: &Permissions,
+) -> Result<Vec<String>, ToolResult> {
+    let Some(arguments) = params.arguments.as_mut().and_then(|v| v.as_object_mut()) else {
+        return Ok(Vec::new());
+    };
+    let mut caches = Vec::new();
+    for name in CACHE_ARGUMENTS {
+        let Some(serde_json::Value::String(cache)) = arguments.get_mut(name) else {
+            continue;
+        };
+        let written = normalize_cache_name(cache);
+        if !permissions.allows_cache(&written) {
+            return Err(forbidden());
         }
+        let Ok(path) = resolve_cache_path(&config.cache_root, &written) else {
+            *cache = written;
+            continue;
+        };
+        let resolved = cache_key(&config.cache_root, &path);
+        if !permissions.allows_cache(&resolved) {
+            return Err(forbidden());
+        }
+        cache.clone_from(&resolved);
+        caches.push(resolved);
+    }
+    Ok(caches)
+}
+
+/// `name` without empty or `.` components. Absolute names and names with
+/// `..` are returned as they are, for [`resolve_cache_path`] to reject.
+fn normalize_cache_name(name: &str) -> String {
+    let components: Vec<&str> = name
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect();
+    if name.starts_with('/') || components.is_empty() || components.contains(&"..") {
+        return name.to_string();
+    }
+    components.join("/")
+}
 
-        "context.list_caches" => list_caches::handle(config).await,
+/// The result for a tool or cache the client may not use. Caches are
+/// checked before they are looked up, so a denial does not reveal whether
+/// the cache exists.
+fn forbidden() -> ToolResult {
+    McpErrorResponse::canonical(McpErrorCode::Forbidden).into()
+}
 
-        "context.inspect_cache" => {
-            let inspect_params: InspectCacheParams = match &params.arguments {
-                Some(v) => match serde_json::from_value(v.clone()) {
-                    Ok(p) => p,
-                    Err(e) => {
-                        return ToolResult::error(format!(
-                            "Invalid arguments for context.inspect_cache: {e}"
-                        ));
-                    }
-                },
-                None => {
-                    return ToolResult::error(
-                        "Missing arguments for context.inspect_cache",
-                    );
-                }
-            };
-            inspect_cache::handle(inspect_params, config).await
+/// Charge a tool call to the rate limits of `client` and of `caches`, the
+/// resolved names of the existing caches it names. `health` is free, so
+/// throttle counts stay readable.
+fn admit(
+    params: &ToolCallParams,
+    config: &ServerConfig,
+    client: &ClientIdentity,
+    caches: &[String],
+) -> Result<(), std::time::Duration> {
+    if params.name == "health" {
+        return Ok(());
+    }
+    let caches: Vec<&str> = caches.iter().map(String::as_str).collect();
+    config.limits.admit(client, &caches)
+}
+
+/// Run synchronous tool work on a blocking thread, bounded by the configured
+/// tool timeout and the cap on concurrent blocking work.
+///
+/// context-core is synchronous; running it inline would stall the JSON-RPC
+/// loop. When every slot is busy the call waits for one, and the wait counts
+/// toward the timeout. Join failures and timeouts map to `internal_error`. A
+/// slot is held until the work finishes, even if the call has already timed
+/// out.
+pub(crate) async fn run_blocking<F, T>(config: &ServerConfig, work: F) -> ToolResult
+where
+    F: FnOnce() -> Result<T, McpErrorResponse> + Send + 'static,
+    T: Into<ToolResult> + Send + 'static,
+{
+    let timeout = config.tool_timeout;
+    // Events from the blocking thread belong to the request's span.
+    let span = tracing::Span::current();
+    let task = async {
+        let permit = config.limits.selection_permit().await;
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            span.in_scope(work)
+        })
+        .await
+    };
+
+    match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(Ok(result))) => result.into(),
+        Ok(Ok(Err(mcp_err))) => mcp_err.into(),
+        Ok(Err(join_err)) => {
+            error!(error = %join_err, "Task join error");
+            McpErrorResponse::canonical(McpErrorCode::InternalError).into()
         }
+        Err(_) => {
+            error!(timeout_secs = timeout.as_secs(), "Operation timed out");
+            McpErrorResponse::canonical(McpErrorCode::InternalError).into()
+        }
+    }
+}
 
-        "health" => health::handle().await,
+/// Resolve and validate a cache path, preventing directory traversal.
+///
+/// Canonicalizes both the cache root and the joined path, then verifies the
+/// result is still inside the root. Rejects `..` segments, absolute paths,
+/// symlinks that escape the root, and hidden directories.
+pub(crate) fn resolve_cache_path(
+    cache_root: &Path,
+    cache_name: &str,
+) -> Result<PathBuf, McpErrorResponse> {
+    // Reject obvious traversal attempts before touching the filesystem
+    if cache_name.contains("..") || cache_name.starts_with('/') || cache_name.starts_with('\\') {
+        return Err(McpErrorResponse::canonical(McpErrorCode::CacheMissing));
+    }
 
-        _ => ToolResult::error(format!("Unknown tool: {}", params.name)),
+    let candidate = cache_root.join(cache_name);
+
+    // Canonicalize resolves symlinks and normalizes the path
+    let canonical = candidate.canonicalize().map_err(|_| {
+        McpErrorResponse::canonical(McpErrorCode::CacheMissing)
+    })?;
+
+    let root_canonical = cache_root.canonicalize().map_err(|e| {
+        error!(error = %e, "Cache root not accessible");
+        McpErrorResponse::canonical(McpErrorCode::IoError)
+    })?;
+
+    // Outside the root, or in a hidden directory such as a build's staging
+    // directory
+    let Ok(relative) = canonical.strip_prefix(&root_canonical) else {
+        return Err(McpErrorResponse::canonical(McpErrorCode::CacheMissing));
+    };
+    if relative
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+    {
+        return Err(McpErrorResponse::canonical(McpErrorCode::CacheMissing));
     }
+
+    if !canonical.is_dir() {
+        return Err(McpErrorResponse::canonical(McpErrorCode::CacheMissing));
+    }
+
+    Ok(canonical)
+}
+
+/// The name a resolved cache goes by in the config file: its path relative
+/// to the canonical cache root, with `/` separators. `hr/`, `./hr`, and a
+/// symlink to `hr` all resolve to `hr`.
+pub(crate) fn cache_key(cache_root: &Path, cache_path: &Path) -> String {
+    let root = cache_root.canonicalize().unwrap_or_else(|_| cache_root.to_path_buf());
+    let relative = cache_path.strip_prefix(&root).unwrap_or(cache_path);
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Load a cache's manifest from a resolved cache path.
+pub(crate) fn load_cache(cache_path: &Path) -> Result<ContextCache, McpErrorResponse> {
+    let manifest_path = cache_path.join("manifest.json");
+    let manifest_file = std::fs::File::open(&manifest_path).map_err(|e| {
+        warn!(error = %e, "Cannot read manifest");
+        // OS-level failure (permission denied, disk error) → io_error
+        // Missing file in a validated directory → cache_invalid (structural)
+        if e.kind() == std::io::ErrorKind::NotFound {
+            McpErrorResponse::canonical(McpErrorCode::CacheInvalid)
+        } else {
+            McpErrorResponse::canonical(McpErrorCode::IoError)
+        }
+    })?;
+    let manifest: CacheManifest = serde_json::from_reader(manifest_file).map_err(|e| {
+        warn!(error = %e, "Invalid manifest JSON");
+        McpErrorResponse::canonical(McpErrorCode::CacheInvalid)
+    })?;
+
+    Ok(ContextCache {
+        root: cache_path.to_path_buf(),
+        manifest,
+    })
 }
@@ -1,122 +1,323 @@
-use std::path::{Path, PathBuf};
+use std::path::Path;
+use std::sync::Arc;
 
-use context_core::cache::{CacheManifest, ContextCache};
+use context_core::cache::ContextCache;
 use context_core::selection::ContextSelector;
 use context_core::types::Query;
+use tracing::{error, warn};
 
+use crate::budget;
 use crate::config::ServerConfig;
-use crate::protocol::{McpErrorCode, McpErrorResponse, ResolveContextParams, ToolResult};
+#[cfg(feature = "hybrid")]
+use crate::embedding::Embedder;
+use crate::expansion::{self, Dictionary};
+use crate::explain;
+#[cfg(feature = "hybrid")]
+use crate::hybrid;
+use crate::index;
+use crate::protocol::{McpErrorCode, McpErrorResponse, ResolveContextParams, Scoring, ToolResult};
+use crate::query::{self, ParsedQuery};
+use crate::redaction::{RedactStage, Redactor};
+use crate::scoring;
+use crate::selection::{self, PackOptions};
+use crate::tokenizer::{self, Tokenizer};
+
+use super::{cache_key, load_cache, resolve_cache_path, run_blocking};
 
 /// Handle a `context.resolve` tool call.
 pub async fn handle(
     params: ResolveContextParams,
     config: &ServerConfig,
 ) -> ToolResult {
-    // Validate budget
-    if params.budget < 0 {
-        return McpErrorResponse::canonical(McpErrorCode::InvalidBudget).into();
-    }
-    let budget = params.budget as usize;
+    // Validate budget and resolve windows and profiles to a token count
+    let budget = match budget::resolve(&params.budget, &config.profiles) {
+        Ok(b) => b,
+        Err(err) => return err.into(),
+    };
+
+    // Parse phrases and +/- operators; plain term lists parse trivially
+    let query = match query::parse(&params.query) {
+        Ok(q) => q,
+        Err(e) => {
+            warn!(error = %e, "Invalid query");
+            return McpErrorResponse::canonical(McpErrorCode::InvalidQuery).into();
+        }
+    };
 
     // Resolve cache path (with traversal protection)
     let cache_path = match resolve_cache_path(&config.cache_root, &params.cache) {
         Ok(p) => p,
         Err(err) => return err.into(),
     };
+    // Per-cache settings are keyed by the resolved name, however the request
+    // spelled it.
+    let cache = cache_key(&config.cache_root, &cache_path);
 
-    // Load cache and run selection on a blocking thread (context-core is sync)
-    let timeout = config.tool_timeout;
-    let task = tokio::task::spawn_blocking(move || {
-        load_and_select(&cache_path, &params.query, budget)
+    // A tokenizer named by the request wins over the cache's configured one.
+    let tokenizer_name = params.tokenizer.clone().or_else(|| {
+        config
+            .caches
+            .get(&cache)
+            .and_then(|settings| settings.tokenizer.clone())
     });
+    let tokenizer = match &tokenizer_name {
+        Some(name) => match config.tokenizers.get(name) {
+            Some(t) => Some((name.clone(), t)),
+            None => {
+                warn!(tokenizer = %name, "Unknown tokenizer");
+                return McpErrorResponse::canonical(McpErrorCode::InvalidParams).into();
+            }
+        },
+        None => None,
+    };
 
-    match tokio::time::timeout(timeout, task).await {
-        Ok(Ok(Ok(selection_json))) => ToolResult::text(selection_json),
-        Ok(Ok(Err(mcp_err))) => mcp_err.into(),
-        Ok(Err(join_err)) => {
-            eprintln!("Task join error: {join_err}");
-            McpErrorResponse::canonical(McpErrorCode::InternalError).into()
+    // Hybrid scoring embeds the query with the cache's configured embedder.
+    #[cfg(feature = "hybrid")]
+    let embedder = match params.scoring {
+        Some(Scoring::Hybrid) => {
+            let name = config
+                .caches
+                .get(&cache)
+                .and_then(|settings| settings.embedder.as_deref());
+            match name.and_then(|name| config.embedders.get(name)) {
+                Some(embedder) => Some(embedder),
+                None => {
+                    return ToolResult::error(format!(
+                        "No embedder configured for cache: {cache}"
+                    ))
+                }
+            }
         }
-        Err(_) => {
-            eprintln!("Operation timed out after {} seconds", timeout.as_secs());
-            McpErrorResponse::canonical(McpErrorCode::InternalError).into()
-        }
-    }
+        _ => None,
+    };
+
+    let resources = Resources {
+        tokenizer,
+        index_min_documents: config.index_min_documents,
+        dictionary: config.dictionaries.get(&cache).cloned(),
+        redactor: config.redactor_for(&cache, RedactStage::Output),
+        #[cfg(feature = "hybrid")]
+        embedder,
+    };
+
+    // Load cache and run selection on a blocking thread (context-core is sync)
+    run_blocking(config, move || {
+        load_and_select(&cache_path, &params, &query, budget, resources)
+    })
+    .await
+}
+
+/// Config-derived state a selection needs, resolved before going blocking.
+struct Resources {
+    tokenizer: Option<(String, Arc<Tokenizer>)>,
+    index_min_documents: usize,
+    /// The cache's dictionary from the config file.
+    dictionary: Option<Arc<Dictionary>>,
+    /// Set when the cache redacts secrets from resolved content.
+    redactor: Option<Arc<Redactor>>,
+    #[cfg(feature = "hybrid")]
+    embedder: Option<Arc<Embedder>>,
 }
 
 /// Synchronous cache load + selection (runs inside spawn_blocking).
+///
+/// The query is first expanded with the config's and the cache's
+/// dictionaries. Without a tokenizer, `snippets`, `dedup`, `strategy`,
+/// `scoring`, query operators or expansions, or `explain`, context-core's
+/// result is serialized untouched;
+/// caches of at least `index_min_documents` documents are resolved through
+/// the inverted index, which yields the same bytes.
+///
+/// A cache with the `output` redaction stage has secrets replaced in the
+/// selected content afterwards, with the counts in a top-level
+/// `redactions` object. `tokens` still count the cached content.
 fn load_and_select(
     cache_path: &Path,
-    query_str: &str,
+    params: &ResolveContextParams,
+    query: &ParsedQuery,
     budget: usize,
-) -> Result<String, McpErrorResponse> {
-    let manifest_path = cache_path.join("manifest.json");
-    let manifest_file = std::fs::File::open(&manifest_path).map_err(|e| {
-        eprintln!("Cannot read manifest: {e}");
-        // OS-level failure (permission denied, disk error) → io_error
-        // Missing file in a validated directory → cache_invalid (structural)
-        if e.kind() == std::io::ErrorKind::NotFound {
-            McpErrorResponse::canonical(McpErrorCode::CacheInvalid)
+    resources: Resources,
+) -> Result<ToolResult, McpErrorResponse> {
+    let cache = load_cache(cache_path)?;
+    let query = &expand_query(cache_path, query, resources.dictionary.as_deref())?;
+    let redactor = resources.redactor.clone();
+
+    let json = if needs_packing(params, query, &resources.tokenizer) || params.explain {
+        select_extended(&cache, params, query, budget, resources)?
+    } else {
+        let indexed = if cache.manifest.documents.len() >= resources.index_min_documents {
+            index::load_or_build(cache_path, &cache)
+                .and_then(|idx| index::select(&cache, &idx, &params.query, budget))
+                .map_err(|e| warn!(error = %e, "Inverted index unavailable, scanning all documents"))
+                .ok()
         } else {
-            McpErrorResponse::canonical(McpErrorCode::IoError)
-        }
-    })?;
-    let manifest: CacheManifest = serde_json::from_reader(manifest_file).map_err(|e| {
-        eprintln!("Invalid manifest JSON: {e}");
-        McpErrorResponse::canonical(McpErrorCode::CacheInvalid)
-    })?;
+            None
+        };
+
+        let selection = match indexed {
+            Some(selection) => selection,
+            None => ContextSelector::default()
+                .select(&cache, Query::new(&params.query), budget)
+                .map_err(|e| {
+                    error!(error = %e, "Selection failed");
+                    McpErrorResponse::canonical(McpErrorCode::InternalError)
+                })?,
+        };
 
-    let cache = ContextCache {
-        root: cache_path.to_path_buf(),
-        manifest,
+        serde_json::to_string(&selection).map_err(|e| {
+            error!(error = %e, "Serialization failed");
+            McpErrorResponse::canonical(McpErrorCode::InternalError)
+        })?
     };
 
-    let selector = ContextSelector::default();
-    let query = Query::new(query_str);
+    let json = match redactor {
+        Some(redactor) => redact(&json, &redactor)?,
+        None => json,
+    };
+    Ok(ToolResult::text(format!("{json}\n")).with_cache_version(cache.manifest.cache_version.clone()))
+}
 
-    let selection = selector.select(&cache, query, budget).map_err(|e| {
-        eprintln!("Selection failed: {e}");
+/// Redact the selected content of a serialized result and report the
+/// counts in `redactions`.
+fn redact(json: &str, redactor: &Redactor) -> Result<String, McpErrorResponse> {
+    let internal_error = |e: &dyn std::fmt::Display| {
+        error!(error = %e, "Redaction failed");
         McpErrorResponse::canonical(McpErrorCode::InternalError)
-    })?;
+    };
+    let mut value: serde_json::Value = serde_json::from_str(json).map_err(|e| internal_error(&e))?;
+    let counts = redactor.redact_selection(&mut value).map_err(|e| internal_error(&e))?;
+    value["redactions"] = serde_json::to_value(counts).map_err(|e| internal_error(&e))?;
+    serde_json::to_string(&value).map_err(|e| internal_error(&e))
+}
 
-    let json = serde_json::to_string(&selection).map_err(|e| {
-        eprintln!("Serialization failed: {e}");
-        McpErrorResponse::canonical(McpErrorCode::InternalError)
+/// Expand `query` with the config dictionary, then the one shipped with the
+/// cache, if any.
+fn expand_query(
+    cache_path: &Path,
+    query: &ParsedQuery,
+    config_dictionary: Option<&Dictionary>,
+) -> Result<ParsedQuery, McpErrorResponse> {
+    let cache_dictionary = Dictionary::for_cache(cache_path).map_err(|e| {
+        warn!(error = %e, "Cache dictionary unusable");
+        McpErrorResponse::canonical(McpErrorCode::CacheInvalid)
     })?;
 
-    Ok(format!("{json}\n"))
-}
-
-/// Resolve and validate a cache path, preventing directory traversal.
-///
-/// Canonicalizes both the cache root and the joined path, then verifies the
-/// result is still inside the root. Rejects `..` segments, absolute paths,
-/// and symlinks that escape the root.
-fn resolve_cache_path(cache_root: &Path, cache_name: &str) -> Result<PathBuf, McpErrorResponse> {
-    // Reject obvious traversal attempts before touching the filesystem
-    if cache_name.contains("..") || cache_name.starts_with('/') || cache_name.starts_with('\\') {
-        return Err(McpErrorResponse::canonical(McpErrorCode::CacheMissing));
+    let mut dictionary = config_dictionary.cloned().unwrap_or_default();
+    if let Some(cache_dictionary) = &cache_dictionary {
+        dictionary.extend(cache_dictionary);
+    }
+    if dictionary.is_empty() {
+        return Ok(query.clone());
     }
+    Ok(expansion::expand(query, &dictionary))
+}
 
-    let candidate = cache_root.join(cache_name);
+/// Selection with server-side options applied on top of context-core's full
+/// ranking. The server packs the budget itself when the cost or eligibility
+/// of a document changes (a tokenizer, snippets, dedup, query operators, or
+/// expansions) or a strategy or scoring mode is requested; explain alone
+/// annotates context-core's own selection.
+fn select_extended(
+    cache: &ContextCache,
+    params: &ResolveContextParams,
+    query: &ParsedQuery,
+    budget: usize,
+    resources: Resources,
+) -> Result<String, McpErrorResponse> {
+    let tokenizer = resources.tokenizer;
+    let serialization_failure = |e: serde_json::Error| {
+        error!(error = %e, "Serialization failed");
+        McpErrorResponse::canonical(McpErrorCode::InternalError)
+    };
 
-    // Canonicalize resolves symlinks and normalizes the path
-    let canonical = candidate.canonicalize().map_err(|_| {
-        McpErrorResponse::canonical(McpErrorCode::CacheMissing)
+    // context-core ranks by the terms that score; operators and phrase
+    // quotes are the server's syntax, not terms.
+    let ranking_query = if query.has_operators() {
+        query.positive_terms().join(" ")
+    } else {
+        params.query.clone()
+    };
+    let ranked = selection::rank_all(cache, &ranking_query).map_err(|e| {
+        error!(error = %e, "Selection failed");
+        McpErrorResponse::canonical(McpErrorCode::InternalError)
     })?;
 
-    let root_canonical = cache_root.canonicalize().map_err(|e| {
-        eprintln!("Cache root not accessible: {e}");
-        McpErrorResponse::canonical(McpErrorCode::IoError)
-    })?;
+    // The server scores when asked for a mode or when the query needs it.
+    let scoring = params.scoring.or_else(|| {
+        (query.has_operators() || query.is_expanded()).then_some(Scoring::TermFrequency)
+    });
+    let (ranked, filtered) = match scoring {
+        #[cfg(feature = "hybrid")]
+        Some(Scoring::Hybrid) => {
+            let embedder = resources
+                .embedder
+                .ok_or_else(|| McpErrorResponse::canonical(McpErrorCode::InternalError))?;
+            hybrid::rescore(ranked, query, &cache.root, &embedder).map_err(|e| {
+                error!(error = %e, "Hybrid scoring failed");
+                if e.is_cache_error() {
+                    McpErrorResponse::canonical(McpErrorCode::CacheInvalid)
+                } else {
+                    McpErrorResponse::canonical(McpErrorCode::InternalError)
+                }
+            })?
+        }
+        Some(mode) => scoring::rescore(ranked, query, mode),
+        None => (ranked, Default::default()),
+    };
+    let score_formula = match scoring {
+        Some(Scoring::Bm25) => scoring::BM25_FORMULA,
+        #[cfg(feature = "hybrid")]
+        Some(Scoring::Hybrid) => hybrid::HYBRID_FORMULA,
+        _ => explain::SCORE_FORMULA,
+    };
 
-    if !canonical.starts_with(&root_canonical) {
-        return Err(McpErrorResponse::canonical(McpErrorCode::CacheMissing));
-    }
+    let (mut value, excluded) = if needs_packing(params, query, &tokenizer) {
+        let (name, tokenizer) = tokenizer
+            .unwrap_or_else(|| (tokenizer::ESTIMATE.to_string(), Arc::new(Tokenizer::Estimate)));
+        let options = PackOptions {
+            tokenizer_name: &name,
+            tokenizer: &tokenizer,
+            snippets: params.snippets,
+            dedup: params.dedup,
+            strategy: params.strategy.unwrap_or_default(),
+            scoring,
+            filtered: &filtered,
+        };
+        let (selection, excluded) = selection::pack(&ranked, &params.query, budget, &options);
+        let value = serde_json::to_value(selection).map_err(serialization_failure)?;
+        (value, excluded)
+    } else {
+        let selection = ContextSelector::default()
+            .select(cache, Query::new(&params.query), budget)
+            .map_err(|e| {
+                error!(error = %e, "Selection failed");
+                McpErrorResponse::canonical(McpErrorCode::InternalError)
+            })?;
+        let value = serde_json::to_value(&selection).map_err(serialization_failure)?;
+        let excluded = explain::budget_exclusions(&value, &ranked);
+        (value, excluded)
+    };
 
-    if !canonical.is_dir() {
-        return Err(McpErrorResponse::canonical(McpErrorCode::CacheMissing));
+    if params.explain {
+        explain::annotate(&mut value, &ranked, &excluded, score_formula)
+            .map_err(serialization_failure)?;
     }
 
-    Ok(canonical)
+    serde_json::to_string(&value).map_err(serialization_failure)
+}
+
+/// Whether the request needs the server to pack the budget itself rather
+/// than use context-core's selection.
+fn needs_packing(
+    params: &ResolveContextParams,
+    query: &ParsedQuery,
+    tokenizer: &Option<(String, Arc<Tokenizer>)>,
+) -> bool {
+    tokenizer.is_some()
+        || params.snippets
+        || params.dedup
+        || params.strategy.is_some()
+        || params.scoring.is_some()
+        || query.has_operators()
+        || query.is_expanded()
 }
@@ -0,0 +1,176 @@
+//! Hybrid lexical and embedding scoring for `context.resolve` (`hybrid`
+//! feature).
+//!
+//! A cache opts in by shipping [`EMBEDDINGS_FILE`] next to `manifest.json`:
+//! one precomputed vector per document, tagged with the document version it
+//! was computed from and the model that produced it. With
+//! `"scoring": "hybrid"` the server embeds the query with the cache's
+//! configured embedder (see [`crate::embedding`]) and fuses
+//!
+//! ```text
+//! LEXICAL_WEIGHT * lexical / max_lexical + (1 - LEXICAL_WEIGHT) * max(0, cosine)
+//! ```
+//!
+//! where `lexical` is the term-frequency score of [`crate::scoring`]. Both
+//! components and the fused score are rounded to [`PRECISION`] decimal
+//! places before ranking (score descending, then id ascending), so float
+//! noise from the embedder cannot reorder documents. Documents without a
+//! vector for their current version score zero on the semantic side.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::embedding::{Embedder, EmbeddingError};
+use crate::protocol::Scoring;
+use crate::query::ParsedQuery;
+use crate::scoring;
+use crate::selection::Candidate;
+
+/// File name of the precomputed document vectors inside a cache directory.
+pub const EMBEDDINGS_FILE: &str = "embeddings.json";
+
+/// Weight of the lexical score in the fused score, in `[0, 1]`.
+pub const LEXICAL_WEIGHT: f64 = 0.5;
+
+/// Decimal places kept of each score component and the fused score.
+pub const PRECISION: i32 = 6;
+
+/// How hybrid scoring computes a document's `score`, as reported by explain
+/// mode.
+pub const HYBRID_FORMULA: &str = "round(0.5 * lexical_score + 0.5 * semantic_score, 6), lexical_score = round(term_matches / total_words / max over documents, 6), semantic_score = round(max(0, cosine(query_embedding, document_embedding)), 6)";
+
+/// On-disk layout of [`EMBEDDINGS_FILE`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheEmbeddings {
+    /// Model the vectors were computed with.
+    pub model: String,
+    pub dimensions: usize,
+    /// Document id to its vector.
+    pub documents: BTreeMap<String, DocumentEmbedding>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DocumentEmbedding {
+    /// Document version the vector was computed from.
+    pub version: String,
+    pub vector: Vec<f32>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HybridError {
+    #[error("cannot read {EMBEDDINGS_FILE}: {0}")]
+    Unreadable(std::io::Error),
+    #[error("invalid {EMBEDDINGS_FILE}: {0}")]
+    Invalid(serde_json::Error),
+    #[error("vector of document {0:?} does not have the declared dimensions")]
+    Dimensions(String),
+    #[error("cache embeddings use model {cache:?} but the embedder produces {embedder:?}")]
+    ModelMismatch { cache: String, embedder: String },
+    #[error("query embedding has {actual} dimensions, cache has {expected}")]
+    QueryDimensions { expected: usize, actual: usize },
+    #[error(transparent)]
+    Embedding(#[from] EmbeddingError),
+}
+
+impl HybridError {
+    /// Whether the error lies in the cache rather than the embedder.
+    pub fn is_cache_error(&self) -> bool {
+        matches!(
+            self,
+            Self::Unreadable(_) | Self::Invalid(_) | Self::Dimensions(_) | Self::ModelMismatch { .. }
+        )
+    }
+}
+
+/// Read and check the embeddings of the cache at `cache_path`.
+pub fn read_embeddings(cache_path: &Path) -> Result<CacheEmbeddings, HybridError> {
+    let file = std::fs::File::open(cache_path.join(EMBEDDINGS_FILE)).map_err(HybridError::Unreadable)?;
+    let embeddings: CacheEmbeddings =
+        serde_json::from_reader(std::io::BufReader::new(file)).map_err(HybridError::Invalid)?;
+    for (id, doc) in &embeddings.documents {
+        if doc.vector.len() != embeddings.dimensions {
+            return Err(HybridError::Dimensions(id.clone()));
+        }
+    }
+    Ok(embeddings)
+}
+
+/// Re-score `ranked` (every document of a cache) against `query` by fusing
+/// term-frequency scores with embedding similarity.
+///
+/// Returns the re-ranked documents and the ids filtered out by `+` and `-`
+/// clauses, like [`scoring::rescore`]. Each document's `why` additionally
+/// carries `lexical_score` and `semantic_score`.
+pub fn rescore(
+    ranked: Vec<Candidate>,
+    query: &ParsedQuery,
+    cache_path: &Path,
+    embedder: &Embedder,
+) -> Result<(Vec<Candidate>, BTreeSet<String>), HybridError> {
+    let embeddings = read_embeddings(cache_path)?;
+    if embeddings.model != embedder.model() {
+        return Err(HybridError::ModelMismatch {
+            cache: embeddings.model,
+            embedder: embedder.model().to_string(),
+        });
+    }
+
+    // Excluded clauses only filter; embed what the query asks for.
+    let query_vector = embedder.embed(&query.positive_terms().join(" "))?;
+    if query_vector.len() != embeddings.dimensions {
+        return Err(HybridError::QueryDimensions {
+            expected: embeddings.dimensions,
+            actual: query_vector.len(),
+        });
+    }
+
+    let (lexical, filtered) = scoring::rescore(ranked, query, Scoring::TermFrequency);
+    let best = lexical.iter().map(|c| c.score).fold(0.0, f64::max);
+
+    let mut fused: Vec<Candidate> = lexical
+        .into_iter()
+        .map(|mut candidate| {
+            let lexical_score = round(if best > 0.0 { candidate.score / best } else { 0.0 });
+            let semantic_score = embeddings
+                .documents
+                .get(&candidate.id)
+                .filter(|doc| doc.version == candidate.version)
+                .map_or(0.0, |doc| round(cosine(&query_vector, &doc.vector).max(0.0)));
+
+            candidate.score =
+                round(LEXICAL_WEIGHT * lexical_score + (1.0 - LEXICAL_WEIGHT) * semantic_score);
+            if let Some(why) = candidate.why.as_object_mut() {
+                why.insert("lexical_score".into(), lexical_score.into());
+                why.insert("semantic_score".into(), semantic_score.into());
+            }
+            candidate
+        })
+        .collect();
+
+    fused.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+    Ok((fused, filtered))
+}
+
+/// Cosine similarity, accumulated in `f64` in vector order; 0 when either
+/// vector is all zeros.
+fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0_f64, 0.0_f64, 0.0_f64);
+    for (&x, &y) in a.iter().zip(b) {
+        let (x, y) = (f64::from(x), f64::from(y));
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+/// Round to [`PRECISION`] decimal places.
+fn round(value: f64) -> f64 {
+    let scale = 10_f64.powi(PRECISION);
+    (value * scale).round() / scale
+}
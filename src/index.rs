@@ -0,0 +1,311 @@
+//! Persistent inverted index for large caches.
+//!
+//! Plain `context.resolve` calls read and score every document in a cache.
+//! For large caches the server instead keeps an inverted index in
+//! [`INDEX_FILE`], next to `manifest.json`: per-document token counts and,
+//! for every normalized word, the documents containing it. The index is
+//! keyed to the manifest's `cache_version` and rebuilt when it is missing,
+//! unreadable, or stale.
+//!
+//! Loaded indexes stay in memory, so a resolve only reads the index file
+//! again when the cache was rebuilt or the file changed on disk.
+//!
+//! [`select`] uses the index to hand context-core only the documents that
+//! can appear in the result: every document matching a query term, plus
+//! the zero-score documents that still fit in the budget after them. The
+//! result is context-core's own selection over that subset, with the
+//! corpus-wide counts restored, so it is byte-identical to a full scan.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::SystemTime;
+
+use context_core::cache::{CacheManifest, ContextCache};
+use context_core::selection::{ContextSelector, SelectionResult};
+use context_core::types::Query;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::manifest::{self, ManifestError, ManifestView};
+use crate::selection::{self, SelectionError};
+
+/// File name of the index inside a cache directory.
+pub const INDEX_FILE: &str = "inverted_index.json";
+
+/// Layout version of [`INDEX_FILE`]. Indexes with another version are
+/// rebuilt.
+pub const INDEX_FORMAT_VERSION: u32 = 1;
+
+/// Caches with fewer documents are scanned without an index.
+pub const DEFAULT_MIN_DOCUMENTS: usize = 1000;
+
+/// On-disk inverted index of one cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvertedIndex {
+    pub format_version: u32,
+    /// `cache_version` of the manifest the index was built from.
+    pub cache_version: String,
+    /// One entry per manifest document, in manifest order.
+    pub documents: Vec<IndexedDocument>,
+    /// Normalized word to the manifest positions of documents containing it,
+    /// ascending.
+    pub postings: BTreeMap<String, Vec<usize>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedDocument {
+    pub id: String,
+    /// Tokens context-core charges for the document.
+    pub tokens: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IndexError {
+    #[error("cannot read manifest: {0}")]
+    Manifest(#[from] manifest::ManifestError),
+    #[error(transparent)]
+    Selection(#[from] SelectionError),
+    #[error("cannot write index: {0}")]
+    Write(std::io::Error),
+    #[error("cannot build cache subset: {0}")]
+    Subset(serde_json::Error),
+    #[error("selection over cache subset failed: {0}")]
+    Core(String),
+}
+
+/// Whether `name` is the index file or one of its temporary files. Both
+/// are derived data, left out of cache sizes and directory scans.
+pub fn is_index_file(name: &str) -> bool {
+    name == INDEX_FILE || (name.starts_with(&format!("{INDEX_FILE}.")) && name.ends_with(".tmp"))
+}
+
+/// Load the index of the cache at `cache_path`, rebuilding it first if it
+/// is missing or does not match the manifest.
+///
+/// The index is kept in memory for the cache's `cache_version` and reused
+/// while the index file is unchanged. A rebuilt index is written
+/// atomically (temporary file, then rename). If the cache directory is not
+/// writable the freshly built index is still returned.
+pub fn load_or_build(cache_path: &Path, cache: &ContextCache) -> Result<Arc<InvertedIndex>, IndexError> {
+    let cache_version = &cache.manifest.cache_version;
+    let index_path = cache_path.join(INDEX_FILE);
+    if let Some(loaded) = loaded().get(cache_path) {
+        if loaded.index.cache_version == *cache_version && loaded.stamp == FileStamp::of(&index_path) {
+            return Ok(loaded.index.clone());
+        }
+    }
+
+    let manifest = serde_json::to_value(&cache.manifest)
+        .and_then(serde_json::from_value::<ManifestView>)
+        .map_err(ManifestError::Invalid)?;
+    let ids: Vec<&str> = manifest.documents.iter().map(|d| d.id.as_str()).collect();
+
+    // Stamp the file before reading it, so a change while reading is noticed
+    // on the next call.
+    let before = FileStamp::of(&index_path);
+    let (index, stamp) = match read(cache_path) {
+        Some(index)
+            if index.format_version == INDEX_FORMAT_VERSION
+                && index.cache_version == *cache_version
+                && index.documents.iter().map(|d| d.id.as_str()).eq(ids.iter().copied()) =>
+        {
+            (index, before)
+        }
+        _ => {
+            let index = build(cache, cache_version, &ids)?;
+            if let Err(e) = write(cache_path, &index) {
+                warn!(error = %e, "Inverted index not saved");
+            }
+            (index, FileStamp::of(&index_path))
+        }
+    };
+
+    let index = Arc::new(index);
+    loaded().insert(
+        cache_path.to_path_buf(),
+        Loaded {
+            index: index.clone(),
+            stamp,
+        },
+    );
+    Ok(index)
+}
+
+/// An index held in memory, with the state of the file it was read from
+/// or written to.
+struct Loaded {
+    index: Arc<InvertedIndex>,
+    stamp: Option<FileStamp>,
+}
+
+/// Size and modification time of an index file, to notice it changing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: SystemTime,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok()?,
+        })
+    }
+}
+
+/// Indexes loaded so far, by cache directory.
+fn loaded() -> MutexGuard<'static, HashMap<PathBuf, Loaded>> {
+    static LOADED: OnceLock<Mutex<HashMap<PathBuf, Loaded>>> = OnceLock::new();
+    LOADED
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Build an index from a full scan of `cache`. `ids` are the manifest's
+/// document ids in manifest order.
+pub fn build(cache: &ContextCache, cache_version: &str, ids: &[&str]) -> Result<InvertedIndex, IndexError> {
+    let ranked = selection::rank_all(cache, "")?;
+    let by_id: BTreeMap<&str, &selection::Candidate> =
+        ranked.iter().map(|c| (c.id.as_str(), c)).collect();
+
+    let mut documents = Vec::with_capacity(ids.len());
+    let mut postings: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (position, id) in ids.iter().enumerate() {
+        let candidate = by_id.get(id);
+        documents.push(IndexedDocument {
+            id: id.to_string(),
+            tokens: candidate.map_or(0, |c| c.tokens),
+        });
+
+        let words: BTreeSet<String> = candidate
+            .map(|c| selection::words(&c.content).into_iter().map(|w| w.text).collect())
+            .unwrap_or_default();
+        for word in words {
+            postings.entry(word).or_default().push(position);
+        }
+    }
+
+    Ok(InvertedIndex {
+        format_version: INDEX_FORMAT_VERSION,
+        cache_version: cache_version.to_string(),
+        documents,
+        postings,
+    })
+}
+
+/// Run context-core's selection for `query` within `budget`, reading only
+/// the documents that can be selected.
+pub fn select(
+    cache: &ContextCache,
+    index: &InvertedIndex,
+    query: &str,
+    budget: usize,
+) -> Result<SelectionResult, IndexError> {
+    // Query terms as context-core splits them; a term matches a document
+    // word only if it is already normalized.
+    let mut matching = BTreeSet::new();
+    for term in query.split_whitespace().map(str::to_lowercase) {
+        if let Some(docs) = index.postings.get(&term) {
+            matching.extend(docs.iter().copied());
+        }
+    }
+
+    // Matching documents rank above every zero-score document, so they are
+    // packed first and their packing does not depend on the rest.
+    let manifest = serde_json::to_value(&cache.manifest).map_err(IndexError::Subset)?;
+    let mut result = select_subset(cache, &manifest, &matching, query, budget)?;
+
+    // Zero-score documents follow in id order; only those that still fit
+    // can be selected.
+    let mut zero: Vec<usize> = (0..index.documents.len())
+        .filter(|i| !matching.contains(i))
+        .collect();
+    zero.sort_by(|&a, &b| index.documents[a].id.cmp(&index.documents[b].id));
+
+    let mut tokens_used = result.selection.tokens_used;
+    let mut fitting = matching.clone();
+    for i in zero {
+        let tokens = index.documents[i].tokens;
+        if tokens_used + tokens <= budget {
+            tokens_used += tokens;
+            fitting.insert(i);
+        }
+    }
+    if fitting.len() > matching.len() {
+        result = select_subset(cache, &manifest, &fitting, query, budget)?;
+    }
+
+    // Every document the subset left out was excluded by the budget or
+    // could not fit it.
+    let total = index.documents.len();
+    result.selection.documents_considered = total;
+    result.selection.documents_excluded_by_budget = total - result.selection.documents_selected;
+    Ok(result)
+}
+
+/// context-core's selection over the documents at `positions` of
+/// `manifest`, the cache's manifest as JSON.
+fn select_subset(
+    cache: &ContextCache,
+    manifest: &serde_json::Value,
+    positions: &BTreeSet<usize>,
+    query: &str,
+    budget: usize,
+) -> Result<SelectionResult, IndexError> {
+    let mut manifest = manifest.clone();
+    if let Some(documents) = manifest.get_mut("documents").and_then(|d| d.as_array_mut()) {
+        let kept: Vec<serde_json::Value> = documents
+            .drain(..)
+            .enumerate()
+            .filter(|(i, _)| positions.contains(i))
+            .map(|(_, doc)| doc)
+            .collect();
+        manifest["document_count"] = kept.len().into();
+        manifest["documents"] = kept.into();
+    }
+    let manifest: CacheManifest = serde_json::from_value(manifest).map_err(IndexError::Subset)?;
+
+    let subset = ContextCache {
+        root: cache.root.clone(),
+        manifest,
+    };
+    ContextSelector::default()
+        .select(&subset, Query::new(query), budget)
+        .map_err(|e| IndexError::Core(e.to_string()))
+}
+
+/// Read the index file, if present and well-formed.
+fn read(cache_path: &Path) -> Option<InvertedIndex> {
+    let file = std::fs::File::open(cache_path.join(INDEX_FILE)).ok()?;
+    serde_json::from_reader(std::io::BufReader::new(file)).ok()
+}
+
+/// Write `index` to the cache directory via a temporary file and rename.
+///
+/// Temporary names are unique per write, so concurrent rebuilds of the same
+/// cache never interleave; the last rename wins with a complete file.
+fn write(cache_path: &Path, index: &InvertedIndex) -> Result<(), IndexError> {
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let tmp_path = cache_path.join(format!(
+        "{INDEX_FILE}.{}.{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = (|| {
+        let mut file = std::fs::File::create(&tmp_path)?;
+        let json = serde_json::to_vec(index)?;
+        file.write_all(&json)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, cache_path.join(INDEX_FILE))
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result.map_err(IndexError::Write)
+}
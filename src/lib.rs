@@ -1,13 +1,40 @@
 //! MCP server for the Context Engine.
 //!
-//! Exposes `context.resolve`, `context.list_caches`, and `context.inspect_cache`
-//! tools over JSON-RPC 2.0 stdio transport, compatible with any MCP-aware AI agent.
+//! Exposes the `context.*` tools (`resolve`, `list_caches`, `inspect_cache`,
+//! `doctor`, ...) over newline-delimited JSON-RPC 2.0 on stdio, TCP, or a
+//! Unix socket, compatible with any MCP-aware AI agent.
 //!
 //! See <https://github.com/contextenginehq/context-engine> for the full platform.
 
+pub mod access;
+pub mod audit;
+pub mod auth;
+pub mod budget;
+pub mod cli;
 pub mod config;
+pub mod dedup;
+#[cfg(feature = "hybrid")]
+pub mod embedding;
+pub mod expansion;
+pub mod explain;
 pub mod handlers;
+#[cfg(feature = "hybrid")]
+pub mod hybrid;
+pub mod index;
+pub mod limits;
+pub mod logging;
+pub mod manifest;
+pub mod mmr;
+pub mod notifications;
 pub mod protocol;
+pub mod query;
+pub mod redaction;
+pub mod response_limit;
+pub mod scoring;
+pub mod selection;
 pub mod server;
+pub mod snippets;
+pub mod tokenizer;
+pub mod transport;
 
 pub mod schema;
@@ -0,0 +1,252 @@
+//! Rate limits and concurrency quotas for tool calls.
+//!
+//! The config file's `limits` section bounds how hard clients can drive a
+//! shared server:
+//!
+//! ```json
+//! {
+//!   "limits": {
+//!     "per_client": { "rate": 5, "burst": 20 },
+//!     "per_cache": { "rate": 50, "burst": 100 },
+//!     "max_concurrent_selections": 8
+//!   }
+//! }
+//! ```
+//!
+//! `per_client` and `per_cache` are token buckets: each `tools/call` (other
+//! than `health`) takes one token from the caller's bucket and one from the
+//! bucket of every existing cache it names, buckets hold up to `burst`
+//! tokens, and they refill at `rate` tokens per second. A call over either
+//! fails with `rate_limited` and a `retry_after_ms` hint. Buckets that have
+//! refilled completely are dropped, so idle clients and caches cost nothing.
+//!
+//! `max_concurrent_selections` caps the tool calls running on blocking
+//! threads at once (selections, diffs, inspections, and builds); further
+//! calls wait for a slot. Refusals and waits are counted by the `health`
+//! tool.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::access::{ClientIdentity, Principal};
+
+/// Bucket count above which full buckets are dropped on the next call.
+const BUCKETS_BEFORE_SWEEP: usize = 256;
+
+/// The config file's `limits` section.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    #[serde(default)]
+    pub per_client: Option<RateLimit>,
+    #[serde(default)]
+    pub per_cache: Option<RateLimit>,
+    #[serde(default)]
+    pub max_concurrent_selections: Option<usize>,
+}
+
+/// A token bucket: `burst` calls at once, then `rate` calls per second.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+/// How many calls each rate limit has turned away, and how many had to wait
+/// for a selection slot, since the server started.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ThrottleStats {
+    pub client: u64,
+    pub cache: u64,
+    pub selections: u64,
+}
+
+/// The server's limits. Clones share buckets, slots, and counters.
+#[derive(Debug, Clone, Default)]
+pub struct Limiter {
+    per_client: Option<RateLimit>,
+    per_cache: Option<RateLimit>,
+    buckets: Arc<Mutex<Buckets>>,
+    selections: Option<Arc<Semaphore>>,
+    throttled: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    clients: HashMap<String, Bucket>,
+    caches: HashMap<String, Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    client: AtomicU64,
+    cache: AtomicU64,
+    selections: AtomicU64,
+}
+
+impl Limiter {
+    /// Limits as `config` declares them. Rates must be positive and bursts
+    /// and the selection cap at least 1.
+    pub fn new(config: &LimitsConfig) -> Result<Self, String> {
+        for (name, limit) in [("per_client", &config.per_client), ("per_cache", &config.per_cache)] {
+            if let Some(limit) = limit {
+                if !(limit.rate.is_finite() && limit.rate > 0.0) || limit.burst == 0 {
+                    return Err(format!("limits.{name} needs a positive rate and a burst of at least 1"));
+                }
+            }
+        }
+        if config.max_concurrent_selections == Some(0) {
+            return Err("limits.max_concurrent_selections must be at least 1".to_string());
+        }
+        Ok(Self {
+            per_client: config.per_client,
+            per_cache: config.per_cache,
+            selections: config
+                .max_concurrent_selections
+                .map(|max| Arc::new(Semaphore::new(max))),
+            ..Self::default()
+        })
+    }
+
+    /// Take a token for a call from `client` naming `caches`. Either every
+    /// bucket involved gives one up or none does; on refusal, returns how
+    /// long until all of them could.
+    pub fn admit(&self, client: &ClientIdentity, caches: &[&str]) -> Result<(), Duration> {
+        if self.per_client.is_none() && (self.per_cache.is_none() || caches.is_empty()) {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Buckets {
+            clients,
+            caches: cache_buckets,
+        } = &mut *buckets;
+
+        if let Some(limit) = self.per_client {
+            sweep(clients, limit, now);
+        }
+        if let Some(limit) = self.per_cache {
+            sweep(cache_buckets, limit, now);
+        }
+
+        let mut taken: Vec<(&mut Bucket, RateLimit)> = Vec::new();
+        let mut client_wait = Duration::ZERO;
+        if let Some(limit) = self.per_client {
+            let bucket = refill(clients, client_key(client), limit, now);
+            client_wait = wait(bucket, limit);
+            taken.push((bucket, limit));
+        }
+        let mut cache_wait = Duration::ZERO;
+        if let Some(limit) = self.per_cache {
+            let mut seen: Vec<&str> = caches.to_vec();
+            seen.sort_unstable();
+            seen.dedup();
+            for cache in seen {
+                refill(cache_buckets, cache.to_string(), limit, now);
+            }
+            for (cache, bucket) in cache_buckets.iter_mut() {
+                if caches.contains(&cache.as_str()) {
+                    cache_wait = cache_wait.max(wait(bucket, limit));
+                    taken.push((bucket, limit));
+                }
+            }
+        }
+
+        if client_wait.is_zero() && cache_wait.is_zero() {
+            for (bucket, _) in taken {
+                bucket.tokens -= 1.0;
+            }
+            return Ok(());
+        }
+        if !client_wait.is_zero() {
+            self.throttled.client.fetch_add(1, Ordering::Relaxed);
+        }
+        if !cache_wait.is_zero() {
+            self.throttled.cache.fetch_add(1, Ordering::Relaxed);
+        }
+        Err(client_wait.max(cache_wait))
+    }
+
+    /// A slot to run blocking tool work in, waiting for one when all are
+    /// taken, or `None` when concurrency is unlimited. The slot is held until
+    /// the permit is dropped.
+    pub async fn selection_permit(&self) -> Option<OwnedSemaphorePermit> {
+        let selections = self.selections.clone()?;
+        if let Ok(permit) = selections.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+        self.throttled.selections.fetch_add(1, Ordering::Relaxed);
+        // The semaphore is never closed.
+        selections.acquire_owned().await.ok()
+    }
+
+    /// Calls refused or made to wait so far, by limit.
+    pub fn stats(&self) -> ThrottleStats {
+        ThrottleStats {
+            client: self.throttled.client.load(Ordering::Relaxed),
+            cache: self.throttled.cache.load(Ordering::Relaxed),
+            selections: self.throttled.selections.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The bucket key for a client: how it authenticated, its uid, or its
+/// trusted name. Anonymous clients share one bucket.
+fn client_key(client: &ClientIdentity) -> String {
+    match (&client.principal, client.uid, &client.client_name) {
+        (Some(Principal::Token(name)), ..) => format!("token:{name}"),
+        (Some(Principal::Certificate(name)), ..) => format!("certificate:{name}"),
+        (None, Some(uid), _) => format!("uid:{uid}"),
+        (None, None, Some(name)) => format!("client_name:{name}"),
+        (None, None, None) => "anonymous".to_string(),
+    }
+}
+
+/// The bucket for `key`, topped up for the time since it was last used.
+/// New buckets start full.
+fn refill(buckets: &mut HashMap<String, Bucket>, key: String, limit: RateLimit, now: Instant) -> &mut Bucket {
+    let burst = f64::from(limit.burst);
+    let bucket = buckets.entry(key).or_insert(Bucket {
+        tokens: burst,
+        updated: now,
+    });
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * limit.rate).min(burst);
+    bucket.updated = now;
+    bucket
+}
+
+/// Drop the buckets that have refilled to `burst` by `now`, once there are
+/// enough to matter. A full bucket is the same as a new one.
+fn sweep(buckets: &mut HashMap<String, Bucket>, limit: RateLimit, now: Instant) {
+    if buckets.len() < BUCKETS_BEFORE_SWEEP {
+        return;
+    }
+    let burst = f64::from(limit.burst);
+    buckets.retain(|_, bucket| {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens + elapsed * limit.rate < burst
+    });
+}
+
+/// How long until `bucket` holds a whole token; zero if it does now. Waits
+/// too long for a `Duration`, under a vanishingly small rate, saturate.
+fn wait(bucket: &Bucket, limit: RateLimit) -> Duration {
+    if bucket.tokens >= 1.0 {
+        Duration::ZERO
+    } else {
+        Duration::try_from_secs_f64((1.0 - bucket.tokens) / limit.rate).unwrap_or(Duration::MAX)
+    }
+}
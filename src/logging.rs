@@ -0,0 +1,259 @@
+//! Structured logging.
+//!
+//! Diagnostics are `tracing` events with fields. [`init`] writes them to
+//! stderr, as text lines or JSON objects, filtered by the `CONTEXT_LOG`
+//! environment variable (`EnvFilter` directives such as `debug` or
+//! `mcp_context_server::audit=warn`; `info` when unset).
+//!
+//! Each request runs in a `request` span carrying the session, the
+//! JSON-RPC `id`, `method`, `tool`, `cache`, and `duration_ms`. MCP clients
+//! can also receive the events of their own requests as
+//! `notifications/message`, after asking with `logging/setLevel`. Only this
+//! crate's events are forwarded, and only at levels some session asked for.
+//! Events outside a request (startup, connection failures) only go to stderr.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use clap::ValueEnum;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Filter, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::notifications::Notifier;
+
+/// Environment variable holding the stderr level filter.
+pub const LOG_ENV: &str = "CONTEXT_LOG";
+
+/// How events are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// One human-readable line per event, with its spans' fields.
+    #[default]
+    Pretty,
+    /// One JSON object per event, with the current span's fields.
+    Json,
+}
+
+/// Install the process-wide subscriber: stderr in `format`, plus
+/// forwarding to MCP clients that asked for messages. Fails if `CONTEXT_LOG`
+/// is invalid or a subscriber is already installed.
+pub fn init(format: LogFormat) -> Result<(), String> {
+    let filter = match std::env::var(LOG_ENV) {
+        Ok(directives) => EnvFilter::try_new(&directives).map_err(|e| format!("invalid {LOG_ENV}: {e}"))?,
+        Err(_) => EnvFilter::new("info"),
+    };
+    let stderr = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
+    let stderr = match format {
+        LogFormat::Pretty => stderr.boxed(),
+        LogFormat::Json => stderr.json().with_current_span(true).with_span_list(false).boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(stderr.with_filter(filter))
+        .with(ClientLog.with_filter(ClientFilter))
+        .try_init()
+        .map_err(|e| e.to_string())
+}
+
+/// A new session id for `request` spans.
+pub fn next_session() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The `tracing` level for an MCP log level name. MCP's eight levels fold
+/// onto `tracing`'s five: `notice` is info, and `critical`, `alert`, and
+/// `emergency` are error.
+pub fn parse_level(name: &str) -> Option<Level> {
+    match name {
+        "debug" => Some(Level::DEBUG),
+        "info" | "notice" => Some(Level::INFO),
+        "warning" => Some(Level::WARN),
+        "error" | "critical" | "alert" | "emergency" => Some(Level::ERROR),
+        _ => None,
+    }
+}
+
+/// Send `session`'s events at `level` and above to its client on `notifier`.
+pub fn forward_to_client(session: u64, notifier: Notifier, level: Level) {
+    let mut clients = clients();
+    clients.insert(session, Client { notifier, level });
+    update_client_level(&clients);
+}
+
+/// Stop sending `session`'s events to its client.
+pub fn forget_client(session: u64) {
+    let mut clients = clients();
+    clients.remove(&session);
+    update_client_level(&clients);
+}
+
+/// A session that asked for `notifications/message`.
+struct Client {
+    notifier: Notifier,
+    level: Level,
+}
+
+fn clients() -> MutexGuard<'static, HashMap<u64, Client>> {
+    static CLIENTS: OnceLock<Mutex<HashMap<u64, Client>>> = OnceLock::new();
+    CLIENTS
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The most verbose level any client asked for, as [`verbosity`]; 0 when
+/// none did.
+static CLIENT_LEVEL: AtomicU8 = AtomicU8::new(0);
+
+fn update_client_level(clients: &HashMap<u64, Client>) {
+    let level = clients.values().map(|client| verbosity(client.level)).max().unwrap_or(0);
+    CLIENT_LEVEL.store(level, Ordering::Relaxed);
+}
+
+/// 1 for error up to 5 for trace.
+fn verbosity(level: Level) -> u8 {
+    match level {
+        Level::ERROR => 1,
+        Level::WARN => 2,
+        Level::INFO => 3,
+        Level::DEBUG => 4,
+        Level::TRACE => 5,
+    }
+}
+
+/// Whether `target` is this crate or one of its modules.
+fn is_own_target(target: &str) -> bool {
+    const CRATE: &str = env!("CARGO_CRATE_NAME");
+    target
+        .strip_prefix(CRATE)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+/// The MCP level name for a `tracing` level.
+fn mcp_level(level: Level) -> &'static str {
+    match level {
+        Level::ERROR => "error",
+        Level::WARN => "warning",
+        Level::INFO => "info",
+        _ => "debug",
+    }
+}
+
+/// Layer forwarding events to the client of the session they happen in.
+struct ClientLog;
+
+/// [`ClientLog`]'s filter: this crate's spans, which carry the session, and
+/// its events at levels some client asked for. Other events never reach the
+/// layer, so they cost nothing while no client listens.
+struct ClientFilter;
+
+impl<S> Filter<S> for ClientFilter {
+    fn enabled(&self, meta: &Metadata<'_>, _: &Context<'_, S>) -> bool {
+        is_own_target(meta.target())
+            && (meta.is_span() || verbosity(*meta.level()) <= CLIENT_LEVEL.load(Ordering::Relaxed))
+    }
+
+    fn callsite_enabled(&self, meta: &'static Metadata<'static>) -> Interest {
+        // The client level changes at runtime, so only the target is final.
+        if is_own_target(meta.target()) {
+            Interest::sometimes()
+        } else {
+            Interest::never()
+        }
+    }
+}
+
+/// Span extension: the session a span belongs to.
+struct SessionId(u64);
+
+impl<S> Layer<S> for ClientLog
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut session = SessionField(None);
+        attrs.record(&mut session);
+        if let (Some(session), Some(span)) = (session.0, ctx.span(id)) {
+            span.extensions_mut().insert(SessionId(session));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(session) = ctx
+            .event_scope(event)
+            .and_then(|scope| scope.from_root().find_map(|span| span.extensions().get::<SessionId>().map(|s| s.0)))
+        else {
+            return;
+        };
+        // Sending happens after the lock is released.
+        let client = clients().get(&session).map(|client| (client.notifier.clone(), client.level));
+        let Some((notifier, client_level)) = client else {
+            return;
+        };
+        let level = *event.metadata().level();
+        if level > client_level {
+            return;
+        }
+        let mut fields = JsonFields::default();
+        event.record(&mut fields);
+        notifier.send(
+            "notifications/message",
+            serde_json::json!({
+                "level": mcp_level(level),
+                "logger": event.metadata().target(),
+                "data": fields.0,
+            }),
+        );
+    }
+}
+
+/// Visitor picking out a span's `session` field.
+struct SessionField(Option<u64>);
+
+impl Visit for SessionField {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == "session" {
+            self.0 = Some(value);
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+/// Visitor collecting an event's fields, `message` included, as JSON.
+#[derive(Default)]
+struct JsonFields(serde_json::Map<String, serde_json::Value>);
+
+impl Visit for JsonFields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
@@ -1,19 +1,13 @@
-use mcp_context_server::config::ServerConfig;
-use mcp_context_server::server::McpServer;
+use clap::Parser;
+use mcp_context_server::cli::{self, Cli};
+use mcp_context_server::logging;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
-    let config = match ServerConfig::from_env() {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("mcp-context-server: configuration error: {e}");
-            std::process::exit(1);
-        }
-    };
-
-    let mut server = McpServer::new(config);
-    if let Err(e) = server.run().await {
-        eprintln!("mcp-context-server: fatal error: {e}");
+    let cli = Cli::parse();
+    if let Err(e) = logging::init(cli.config.log_format) {
+        eprintln!("mcp-context-server: {e}");
         std::process::exit(1);
     }
+    std::process::exit(cli::run(cli).await);
 }
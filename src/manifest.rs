@@ -0,0 +1,117 @@
+//! Read-only views of a cache's `manifest.json` and document files.
+//!
+//! Handlers that only need cache metadata read it through this module
+//! instead of constructing a full `ContextCache`. The manifest is first
+//! validated against `context_core::cache::CacheManifest`, so anything
+//! accepted here is also accepted by selection.
+
+use std::path::Path;
+
+use context_core::cache::CacheManifest;
+use serde::Deserialize;
+
+/// File name of the manifest inside a cache directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// `build_config.version` values this server knows how to read.
+pub const SUPPORTED_BUILD_CONFIG_VERSIONS: &[&str] = &["1"];
+
+/// The subset of manifest fields the server inspects.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestView {
+    pub cache_version: String,
+    pub build_config: BuildConfigView,
+    pub document_count: usize,
+    pub documents: Vec<ManifestDocument>,
+}
+
+/// `build_config` block of a manifest.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BuildConfigView {
+    pub version: String,
+}
+
+/// One entry of the manifest's `documents` array.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestDocument {
+    pub id: String,
+    pub version: String,
+    pub file: String,
+}
+
+/// Why a manifest could not be read.
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error("manifest.json does not exist")]
+    Missing,
+    #[error("manifest.json cannot be read: {0}")]
+    Unreadable(std::io::Error),
+    #[error("manifest.json is not valid JSON: {0}")]
+    InvalidJson(serde_json::Error),
+    #[error("manifest.json does not match the cache manifest schema: {0}")]
+    Invalid(serde_json::Error),
+}
+
+impl ManifestView {
+    /// Whether the manifest was built with a supported `build_config.version`.
+    pub fn is_supported_version(&self) -> bool {
+        SUPPORTED_BUILD_CONFIG_VERSIONS.contains(&self.build_config.version.as_str())
+    }
+}
+
+impl ManifestDocument {
+    /// Whether `file` stays inside the cache directory: relative and
+    /// without `..`.
+    pub fn has_valid_path(&self) -> bool {
+        !self.file.contains("..") && !Path::new(&self.file).is_absolute()
+    }
+}
+
+/// Read and validate the manifest of the cache at `cache_path`.
+pub fn read_manifest(cache_path: &Path) -> Result<ManifestView, ManifestError> {
+    let bytes = std::fs::read(cache_path.join(MANIFEST_FILE)).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            ManifestError::Missing
+        } else {
+            ManifestError::Unreadable(e)
+        }
+    })?;
+
+    let value: serde_json::Value =
+        serde_json::from_slice(&bytes).map_err(ManifestError::InvalidJson)?;
+
+    serde_json::from_value::<CacheManifest>(value.clone()).map_err(ManifestError::Invalid)?;
+    serde_json::from_value(value).map_err(ManifestError::Invalid)
+}
+
+/// A document's cached file: its content and `metadata` object.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DocumentFile {
+    pub content: String,
+    /// Empty for documents without metadata.
+    #[serde(default, deserialize_with = "object_or_empty")]
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Read a manifest document's cached file. Files outside the cache
+/// directory are refused.
+pub fn read_document(
+    cache_path: &Path,
+    doc: &ManifestDocument,
+) -> Result<DocumentFile, Box<dyn std::error::Error>> {
+    if !doc.has_valid_path() {
+        return Err(format!("document file {:?} is outside the cache", doc.file).into());
+    }
+    let file = std::fs::File::open(cache_path.join(&doc.file))?;
+    Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+}
+
+fn object_or_empty<'de, D>(deserializer: D) -> Result<serde_json::Map<String, serde_json::Value>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Object(map) => Ok(map),
+        _ => Ok(serde_json::Map::new()),
+    }
+}
@@ -0,0 +1,89 @@
+//! Maximal-marginal-relevance selection for `context.resolve`.
+//!
+//! Score-ordered packing can spend the whole budget on sections that say
+//! the same thing. MMR instead picks, at each step, the document that fits
+//! in the remaining budget and maximizes
+//!
+//! ```text
+//! LAMBDA * relevance - (1 - LAMBDA) * max_similarity_to_selected
+//! ```
+//!
+//! where relevance is the document's score divided by the best score in
+//! the ranking, and similarity is the word-shingle Jaccard similarity used
+//! by [`crate::dedup`]. Ties go to the higher-ranked document, so the
+//! result depends only on the ranking and is deterministic.
+//!
+//! Each step compares every remaining candidate, so only the top
+//! [`POOL_SIZE`] candidates that matched the query are weighed this way.
+//! The rest then fill whatever budget is left in rank order.
+
+use crate::dedup;
+use crate::selection::Candidate;
+
+/// Weight of relevance against diversity, in `[0, 1]`. 1 is plain score
+/// order; 0 ignores scores entirely.
+pub const LAMBDA: f64 = 0.7;
+
+/// Most candidates MMR weighs against each other.
+pub const POOL_SIZE: usize = 256;
+
+/// Choose documents from `candidates` (in rank order, with their costs in
+/// `tokens`) within `budget`.
+///
+/// Returns indices into `candidates` in selection order. Every candidate
+/// not returned did not fit in the budget left when selection stopped.
+pub fn select(candidates: &[&Candidate], tokens: &[usize], budget: usize) -> Vec<usize> {
+    let pool = candidates
+        .iter()
+        .take(POOL_SIZE)
+        .take_while(|c| c.score > 0.0)
+        .count();
+    let best = candidates.iter().map(|c| c.score).fold(0.0, f64::max);
+    let relevance: Vec<f64> = candidates[..pool]
+        .iter()
+        .map(|c| if best > 0.0 { c.score / best } else { 0.0 })
+        .collect();
+    let signatures: Vec<Vec<u64>> = candidates[..pool].iter().map(|c| dedup::shingles(&c.content)).collect();
+
+    // Highest similarity of each candidate to any selected document.
+    let mut redundancy = vec![0.0_f64; pool];
+    let mut remaining: Vec<usize> = (0..pool).collect();
+    let mut selected = Vec::new();
+    let mut tokens_used = 0;
+
+    loop {
+        let mut best: Option<(usize, f64)> = None;
+        for (pos, &idx) in remaining.iter().enumerate() {
+            if tokens_used + tokens[idx] > budget {
+                continue;
+            }
+            let value = LAMBDA * relevance[idx] - (1.0 - LAMBDA) * redundancy[idx];
+            // `remaining` is in rank order, so strict comparison keeps the
+            // higher-ranked document on ties.
+            if best.map_or(true, |(_, v)| value > v) {
+                best = Some((pos, value));
+            }
+        }
+
+        let Some((pos, _)) = best else {
+            break;
+        };
+        let idx = remaining.remove(pos);
+        tokens_used += tokens[idx];
+        selected.push(idx);
+
+        for &other in &remaining {
+            let similarity = dedup::jaccard(&signatures[idx], &signatures[other]);
+            redundancy[other] = redundancy[other].max(similarity);
+        }
+    }
+
+    for (idx, &cost) in tokens.iter().enumerate().skip(pool) {
+        if tokens_used + cost <= budget {
+            tokens_used += cost;
+            selected.push(idx);
+        }
+    }
+
+    selected
+}
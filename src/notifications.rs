@@ -0,0 +1,71 @@
+//! Server-to-client notifications sent while a request is in flight.
+//!
+//! Handlers return a single response, but long-running tools also report
+//! progress along the way. A [`Notifier`] is the session's outgoing
+//! notification queue: the server drains it onto the output stream while
+//! it waits for the handler, so notifications always precede the response
+//! of the request that produced them. A default `Notifier` (used by the
+//! one-shot CLI and by tests) drops everything.
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::protocol::JsonRpcNotification;
+
+/// Queue of notifications for one client session. Cheap to clone; usable
+/// from blocking threads.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    sender: Option<UnboundedSender<JsonRpcNotification>>,
+}
+
+impl Notifier {
+    /// A notifier and the receiving end the session writes out.
+    pub fn channel() -> (Self, UnboundedReceiver<JsonRpcNotification>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (
+            Self {
+                sender: Some(sender),
+            },
+            receiver,
+        )
+    }
+
+    /// Queue a notification. Dropped if the session has gone away.
+    pub fn send(&self, method: &str, params: serde_json::Value) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(JsonRpcNotification::new(method, params));
+        }
+    }
+}
+
+/// Reports `notifications/progress` for one request.
+///
+/// Progress is only sent when the request carried a `progressToken` in
+/// `params._meta`, as MCP requires.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    notifier: Notifier,
+    token: Option<serde_json::Value>,
+}
+
+impl Progress {
+    pub fn new(notifier: Notifier, token: Option<serde_json::Value>) -> Self {
+        Self { notifier, token }
+    }
+
+    /// Report that `progress` of `total` units are done.
+    pub fn report(&self, progress: u64, total: Option<u64>, message: &str) {
+        let Some(token) = &self.token else {
+            return;
+        };
+        let mut params = serde_json::json!({
+            "progressToken": token,
+            "progress": progress,
+            "message": message,
+        });
+        if let Some(total) = total {
+            params["total"] = total.into();
+        }
+        self.notifier.send("notifications/progress", params);
+    }
+}
@@ -2,10 +2,11 @@ pub mod request;
 pub mod response;
 
 pub use request::{
-    InitializeParams, InspectCacheParams, JsonRpcRequest, ListCachesParams, ResolveContextParams,
-    RpcId, ToolCallParams,
+    Budget, BudgetWindow, BuildCacheParams, ClientInfo, DiffCachesParams, DiffResolveParams,
+    InitializeParams, InspectCacheParams, JsonRpcRequest, ListCachesParams, RequestMeta,
+    ResolveContextParams, RpcId, Scoring, Strategy, ToolCallParams,
 };
 pub use response::{
-    JsonRpcError, JsonRpcResponse, McpError, McpErrorCode, McpErrorResponse, ToolResult,
-    ToolResultContent,
+    JsonRpcError, JsonRpcNotification, JsonRpcResponse, McpError, McpErrorCode,
+    McpErrorResponse, ToolResult, ToolResultContent,
 };
@@ -8,6 +8,15 @@ pub enum RpcId {
     Str(String),
 }
 
+impl std::fmt::Display for RpcId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcId::Number(n) => write!(f, "{n}"),
+            RpcId::Str(s) => f.write_str(s),
+        }
+    }
+}
+
 /// JSON-RPC 2.0 request envelope.
 #[derive(Debug, Clone, Deserialize)]
 pub struct JsonRpcRequest {
@@ -17,13 +26,134 @@ pub struct JsonRpcRequest {
     pub params: Option<serde_json::Value>,
 }
 
+/// A token budget: an absolute token count, a share of a model's context
+/// window, or the name of a model profile from the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Budget {
+    /// Accepts i64 so we can detect negative values before casting to usize.
+    Tokens(i64),
+    Profile(String),
+    Window(BudgetWindow),
+    /// Any other shape. Rejected as `invalid_budget` rather than as
+    /// malformed arguments.
+    Invalid(serde_json::Value),
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self::Tokens(0)
+    }
+}
+
+impl From<i64> for Budget {
+    fn from(tokens: i64) -> Self {
+        Self::Tokens(tokens)
+    }
+}
+
+/// A budget relative to a model's context window:
+/// `floor((model_window - reserve) * fraction)` tokens.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetWindow {
+    pub model_window: i64,
+    /// Share of the window left after `reserve`, in `(0, 1]`. Defaults to 1.
+    #[serde(default = "BudgetWindow::default_fraction")]
+    pub fraction: f64,
+    /// Tokens held back for the prompt and the model's reply. Defaults to 0.
+    #[serde(default)]
+    pub reserve: i64,
+}
+
+impl BudgetWindow {
+    fn default_fraction() -> f64 {
+        1.0
+    }
+}
+
+/// How `context.resolve` chooses documents within the budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Pack documents in score order (context-core's behavior).
+    #[default]
+    Score,
+    /// Maximal marginal relevance: trade score against overlap with the
+    /// documents already selected.
+    Mmr,
+}
+
+impl std::str::FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "score" => Ok(Self::Score),
+            "mmr" => Ok(Self::Mmr),
+            other => Err(format!("unknown strategy {other:?} (expected \"score\" or \"mmr\")")),
+        }
+    }
+}
+
+/// How `context.resolve` scores documents against the query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scoring {
+    /// `term_matches / total_words` (context-core's score).
+    #[default]
+    TermFrequency,
+    /// Okapi BM25 with corpus statistics from the cache.
+    Bm25,
+    /// Term frequency fused with embedding similarity.
+    #[cfg(feature = "hybrid")]
+    Hybrid,
+}
+
+impl std::str::FromStr for Scoring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "term_frequency" => Ok(Self::TermFrequency),
+            "bm25" => Ok(Self::Bm25),
+            #[cfg(feature = "hybrid")]
+            "hybrid" => Ok(Self::Hybrid),
+            other => Err(format!(
+                "unknown scoring mode {other:?} (expected \"term_frequency\" or \"bm25\")"
+            )),
+        }
+    }
+}
+
 /// Parameters for the `context.resolve` tool.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct ResolveContextParams {
     pub cache: String,
     pub query: String,
-    /// Accepts i64 so we can detect negative values before casting to usize.
-    pub budget: i64,
+    pub budget: Budget,
+    /// Annotate the selection with per-term matches and excluded documents.
+    #[serde(default)]
+    pub explain: bool,
+    /// Return query-focused snippets instead of full document content.
+    #[serde(default)]
+    pub snippets: bool,
+    /// Suppress documents that duplicate a higher-ranked document.
+    #[serde(default)]
+    pub dedup: bool,
+    /// Selection strategy. Defaults to score order; when given, it is
+    /// reported in `selection.strategy`.
+    #[serde(default)]
+    pub strategy: Option<Strategy>,
+    /// Scoring mode. Defaults to term frequency; when given, it is reported
+    /// in `selection.scoring`.
+    #[serde(default)]
+    pub scoring: Option<Scoring>,
+    /// Tokenizer to count tokens with, by name from the config file.
+    /// Defaults to the cache's configured tokenizer, then the built-in
+    /// estimate.
+    #[serde(default)]
+    pub tokenizer: Option<String>,
 }
 
 /// Parameters for the `context.list_caches` tool.
@@ -38,6 +168,37 @@ pub struct InspectCacheParams {
     pub cache: String,
 }
 
+/// Parameters for the `context.diff_caches` tool.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DiffCachesParams {
+    /// Cache to compare from (e.g. the currently deployed build).
+    pub base: String,
+    /// Cache to compare to (e.g. the rebuilt cache).
+    pub target: String,
+}
+
+/// Parameters for the `context.diff_resolve` tool.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DiffResolveParams {
+    /// Cache to compare from (e.g. the currently deployed build).
+    pub base: String,
+    /// Cache to compare to (e.g. the rebuilt cache).
+    pub target: String,
+    pub query: String,
+    pub budget: Budget,
+}
+
+/// Parameters for the `context.build_cache` tool.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BuildCacheParams {
+    /// Cache to build; its source directory comes from the server config.
+    pub cache: String,
+    /// Reuse documents from the existing cache whose source files are
+    /// unchanged instead of re-ingesting every file.
+    #[serde(default)]
+    pub incremental: bool,
+}
+
 /// MCP `initialize` params.
 #[derive(Debug, Clone, Deserialize)]
 pub struct InitializeParams {
@@ -45,6 +206,8 @@ pub struct InitializeParams {
     pub protocol_version: Option<String>,
     #[serde(rename = "clientInfo")]
     pub client_info: Option<ClientInfo>,
+    #[serde(rename = "_meta", default)]
+    pub meta: Option<RequestMeta>,
 }
 
 /// Client information sent during `initialize`.
@@ -59,4 +222,17 @@ pub struct ClientInfo {
 pub struct ToolCallParams {
     pub name: String,
     pub arguments: Option<serde_json::Value>,
+    #[serde(rename = "_meta", default)]
+    pub meta: Option<RequestMeta>,
+}
+
+/// MCP request metadata (`params._meta`).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RequestMeta {
+    /// Token the client wants `notifications/progress` tagged with.
+    #[serde(rename = "progressToken")]
+    pub progress_token: Option<serde_json::Value>,
+    /// `Bearer <token>` credentials for TCP sessions, sent in `initialize`.
+    #[serde(default)]
+    pub authorization: Option<String>,
 }
@@ -38,6 +38,24 @@ impl JsonRpcResponse {
     }
 }
 
+/// JSON-RPC 2.0 notification sent by the server (no `id`, no response).
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonRpcNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: serde_json::Value,
+}
+
+impl JsonRpcNotification {
+    pub fn new(method: impl Into<String>, params: serde_json::Value) -> Self {
+        Self {
+            jsonrpc: "2.0".into(),
+            method: method.into(),
+            params,
+        }
+    }
+}
+
 /// JSON-RPC 2.0 error object (protocol-level errors).
 #[derive(Debug, Clone, Serialize)]
 pub struct JsonRpcError {
@@ -75,6 +93,11 @@ impl JsonRpcError {
     pub fn internal_error(detail: impl Into<String>) -> Self {
         Self { code: -32603, message: detail.into(), data: None }
     }
+
+    /// MCP's error for a `resources/read` of an unknown URI.
+    pub fn resource_not_found() -> Self {
+        Self { code: -32002, message: "Resource not found".into(), data: None }
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -87,6 +110,10 @@ pub struct ToolResult {
     pub content: Vec<ToolResultContent>,
     #[serde(rename = "isError", skip_serializing_if = "std::ops::Not::not")]
     pub is_error: bool,
+    /// `cache_version` of the cache the call read or built, for the audit
+    /// log. Not sent to the client.
+    #[serde(skip)]
+    pub cache_version: Option<String>,
 }
 
 /// A single content block inside a tool result.
@@ -105,6 +132,7 @@ impl ToolResult {
                 text: text.into(),
             }],
             is_error: false,
+            cache_version: None,
         }
     }
 
@@ -115,8 +143,21 @@ impl ToolResult {
                 text: text.into(),
             }],
             is_error: true,
+            cache_version: None,
         }
     }
+
+    /// This result, noting the `cache_version` of the cache it came from.
+    pub fn with_cache_version(mut self, cache_version: impl Into<String>) -> Self {
+        self.cache_version = Some(cache_version.into());
+        self
+    }
+}
+
+impl From<String> for ToolResult {
+    fn from(text: String) -> Self {
+        Self::text(text)
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -131,20 +172,29 @@ pub enum McpErrorCode {
     CacheInvalid,
     InvalidQuery,
     InvalidBudget,
+    /// A tool argument other than the query or budget names something the
+    /// server does not have, such as an unknown tokenizer.
+    InvalidParams,
     IoError,
     InternalError,
+    Forbidden,
+    RateLimited,
 }
 
 impl McpErrorCode {
     /// Map to the corresponding JSON-RPC 2.0 error code.
     ///
     /// Input validation failures → -32602 (Invalid params)
+    /// Access denials           → -32602 (Invalid params)
     /// Server-side failures     → -32603 (Internal error)
+    /// Rate limits              → -32000 (Server error; retry later)
     pub fn json_rpc_code(&self) -> i32 {
         match self {
             Self::CacheMissing | Self::CacheInvalid => -32602,
-            Self::InvalidQuery | Self::InvalidBudget => -32602,
+            Self::InvalidQuery | Self::InvalidBudget | Self::InvalidParams => -32602,
+            Self::Forbidden => -32602,
             Self::IoError | Self::InternalError => -32603,
+            Self::RateLimited => -32000,
         }
     }
 }
@@ -154,6 +204,9 @@ impl McpErrorCode {
 pub struct McpError {
     pub code: McpErrorCode,
     pub message: String,
+    /// For `rate_limited`: how long to wait before retrying.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
 }
 
 /// MCP error response (top-level)
@@ -168,6 +221,7 @@ impl McpErrorResponse {
             error: McpError {
                 code,
                 message: message.into(),
+                retry_after_ms: None,
             },
         }
     }
@@ -181,11 +235,23 @@ impl McpErrorResponse {
             McpErrorCode::CacheInvalid => "Cache exists but is invalid",
             McpErrorCode::InvalidQuery => "Query is invalid",
             McpErrorCode::InvalidBudget => "Budget is invalid",
+            McpErrorCode::InvalidParams => "Parameters are invalid",
             McpErrorCode::IoError => "I/O error occurred",
             McpErrorCode::InternalError => "Internal error",
+            McpErrorCode::Forbidden => "Access is forbidden",
+            McpErrorCode::RateLimited => "Rate limit exceeded",
         };
         Self::new(code, message)
     }
+
+    /// A canonical `rate_limited` error asking the client to wait
+    /// `retry_after` (rounded up to whole milliseconds).
+    pub fn rate_limited(retry_after: std::time::Duration) -> Self {
+        let mut response = Self::canonical(McpErrorCode::RateLimited);
+        let millis = retry_after.as_nanos().div_ceil(1_000_000);
+        response.error.retry_after_ms = Some(u64::try_from(millis).unwrap_or(u64::MAX));
+        response
+    }
 }
 
 /// Convert an MCP domain error into a JSON-RPC error.
@@ -0,0 +1,174 @@
+//! Query syntax for `context.resolve`.
+//!
+//! A query is a whitespace-separated list of clauses. A clause is a bare
+//! term or a double-quoted phrase, optionally prefixed with `+` (the
+//! document must contain it) or `-` (the document must not contain it):
+//!
+//! ```text
+//! "rate limit" +redis -deprecated
+//! ```
+//!
+//! `+` and `-` are operators only directly before a letter, a digit, or a
+//! phrase, and a quote opens a phrase only at the start of a clause and
+//! when a closing quote follows. Anywhere else they are part of a term, so
+//! `--verbose`, `- foo`, and `foo"bar` are plain terms.
+//!
+//! Words are normalized the way document words are (lowercased, leading and
+//! trailing non-alphanumeric characters trimmed), so a phrase matches a run
+//! of consecutive document words. A query without phrases or operators is a
+//! plain term list, exactly what context-core scores on its own.
+
+/// Whether a clause is scored, required, or forbidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// Contributes to the score; not required.
+    Optional,
+    /// `+`: documents without it are filtered out.
+    Required,
+    /// `-`: documents with it are filtered out. Never scored.
+    Excluded,
+}
+
+/// One term or phrase of a parsed query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clause {
+    /// Normalized words; more than one for a phrase.
+    pub words: Vec<String>,
+    pub operator: Operator,
+    /// Written in double quotes.
+    pub quoted: bool,
+    /// The query text this clause was expanded from by a dictionary (see
+    /// [`crate::expansion`]); `None` for clauses the query spelled out.
+    pub expanded_from: Option<String>,
+}
+
+/// A parsed `query` string.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedQuery {
+    pub clauses: Vec<Clause>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QueryError {
+    #[error("phrase at byte {0} contains no words")]
+    EmptyPhrase(usize),
+}
+
+impl ParsedQuery {
+    /// Whether the query uses phrases or operators, i.e. anything beyond a
+    /// plain term list.
+    pub fn has_operators(&self) -> bool {
+        self.clauses
+            .iter()
+            .any(|c| c.quoted || c.operator != Operator::Optional)
+    }
+
+    /// Whether a dictionary added clauses to the query.
+    pub fn is_expanded(&self) -> bool {
+        self.clauses.iter().any(|c| c.expanded_from.is_some())
+    }
+
+    /// Clauses that contribute to the score (optional and required), in
+    /// query order, without repeats.
+    pub fn positive_clauses(&self) -> Vec<&Clause> {
+        let mut out: Vec<&Clause> = Vec::new();
+        for clause in &self.clauses {
+            if clause.operator != Operator::Excluded && !out.iter().any(|c| c.words == clause.words) {
+                out.push(clause);
+            }
+        }
+        out
+    }
+
+    /// Words of the positive clauses, in query order, without repeats.
+    pub fn positive_terms(&self) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        for clause in self.positive_clauses() {
+            for word in &clause.words {
+                if !out.contains(word) {
+                    out.push(word.clone());
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Parse a `query` string.
+pub fn parse(query: &str) -> Result<ParsedQuery, QueryError> {
+    let mut clauses = Vec::new();
+    let mut pos = 0;
+
+    while let Some(c) = query[pos..].chars().next() {
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue;
+        }
+
+        let operator = match c {
+            '+' if starts_clause(&query[pos + 1..]) => Operator::Required,
+            '-' if starts_clause(&query[pos + 1..]) => Operator::Excluded,
+            _ => Operator::Optional,
+        };
+        let start = if operator == Operator::Optional { pos } else { pos + 1 };
+
+        if let Some(len) = phrase_len(&query[start..]) {
+            let words = normalize_words(&query[start + 1..start + len - 1]);
+            if words.is_empty() {
+                return Err(QueryError::EmptyPhrase(start));
+            }
+            clauses.push(Clause {
+                words,
+                operator,
+                quoted: true,
+                expanded_from: None,
+            });
+            pos = start + len;
+        } else {
+            let end = query[start..]
+                .find(char::is_whitespace)
+                .map_or(query.len(), |i| start + i);
+            // Terms that are all punctuation match nothing; drop them as
+            // context-core's word normalization would.
+            if let Some(word) = normalize(&query[start..end]) {
+                clauses.push(Clause {
+                    words: vec![word],
+                    operator,
+                    quoted: false,
+                    expanded_from: None,
+                });
+            }
+            pos = end;
+        }
+    }
+
+    Ok(ParsedQuery { clauses })
+}
+
+/// Whether `rest`, the text after a `+` or `-`, starts with a term or a
+/// phrase, making the sign an operator.
+fn starts_clause(rest: &str) -> bool {
+    rest.chars().next().is_some_and(char::is_alphanumeric) || phrase_len(rest).is_some()
+}
+
+/// The length in bytes of the phrase `text` starts with, quotes included;
+/// `None` unless `text` starts with a quote that is closed later.
+fn phrase_len(text: &str) -> Option<usize> {
+    let inner = text.strip_prefix('"')?;
+    inner.find('"').map(|end| end + 2)
+}
+
+/// Normalize each whitespace-separated word of `text`, dropping words that
+/// are all punctuation.
+pub(crate) fn normalize_words(text: &str) -> Vec<String> {
+    text.split_whitespace().filter_map(normalize).collect()
+}
+
+/// Normalize a query word like a document word; `None` if nothing is left.
+fn normalize(word: &str) -> Option<String> {
+    let word = word
+        .to_lowercase()
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_string();
+    (!word.is_empty()).then_some(word)
+}
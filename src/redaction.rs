@@ -0,0 +1,220 @@
+//! Secret redaction for document content.
+//!
+//! A [`Redactor`] replaces every match of its patterns with a
+//! `[REDACTED:<pattern>]` marker. The built-in patterns cover AWS access
+//! key ids and secret access keys, JWTs, and PEM private key blocks; the
+//! config file's `redaction` section picks which built-ins apply and adds
+//! custom regexes:
+//!
+//! ```json
+//! {
+//!   "redaction": {
+//!     "builtin": ["aws_access_key_id", "jwt", "private_key"],
+//!     "patterns": { "internal_token": "itk_[A-Za-z0-9]{32}" }
+//!   }
+//! }
+//! ```
+//!
+//! When a pattern has a capture group named `secret`, only that group is
+//! replaced, so a pattern can anchor on a key name without hiding it.
+//!
+//! Patterns run one after another over the whole text, built-ins in the
+//! order of [`BUILTIN_PATTERNS`] and then custom patterns by name, so the
+//! redacted text is a pure function of the input and the pattern set.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+/// Built-in patterns by name, in the order they are applied.
+pub const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    (
+        "private_key",
+        r"-----BEGIN (?:[A-Z0-9]+ )*PRIVATE KEY-----[\s\S]*?-----END (?:[A-Z0-9]+ )*PRIVATE KEY-----",
+    ),
+    (
+        "jwt",
+        r"\beyJ[A-Za-z0-9_-]{4,}\.eyJ[A-Za-z0-9_-]{4,}\.[A-Za-z0-9_-]+",
+    ),
+    ("aws_access_key_id", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    (
+        "aws_secret_access_key",
+        r#"(?i)aws_?secret_?access_?key["']?\s*[:=]\s*["']?(?P<secret>[A-Za-z0-9/+]{40})(?![A-Za-z0-9/+])"#,
+    ),
+];
+
+/// Name of the capture group that limits a replacement to part of a match.
+pub const SECRET_GROUP: &str = "secret";
+
+/// Redactions made, by pattern name.
+pub type RedactionCounts = BTreeMap<String, usize>;
+
+/// The config file's `redaction` section.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactionConfig {
+    /// Built-in patterns to apply; all of them when omitted.
+    #[serde(default)]
+    pub builtin: Option<Vec<String>>,
+    /// Custom patterns: name to regex.
+    #[serde(default)]
+    pub patterns: BTreeMap<String, String>,
+}
+
+/// Where a cache's content is redacted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactStage {
+    /// Before `context.build_cache` ingests a source file.
+    Build,
+    /// In document content returned by `context.resolve`.
+    Output,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RedactionError {
+    #[error("unknown built-in redaction pattern {0:?}")]
+    UnknownBuiltin(String),
+    #[error("redaction pattern name {0:?} must be non-empty and may only contain a-z, 0-9, and _")]
+    InvalidName(String),
+    #[error("redaction pattern {0:?} is already a built-in pattern")]
+    Duplicate(String),
+    #[error("invalid redaction pattern {name:?}: {source}")]
+    InvalidPattern {
+        name: String,
+        source: Box<fancy_regex::Error>,
+    },
+    #[error("redaction pattern {name:?} failed: {source}")]
+    Failed {
+        name: String,
+        source: Box<fancy_regex::Error>,
+    },
+}
+
+struct Pattern {
+    name: String,
+    regex: fancy_regex::Regex,
+}
+
+/// A compiled pattern set.
+pub struct Redactor {
+    patterns: Vec<Pattern>,
+}
+
+impl std::fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = self.patterns.iter().map(|p| p.name.as_str()).collect();
+        f.debug_struct("Redactor").field("patterns", &names).finish()
+    }
+}
+
+impl Default for Redactor {
+    /// Every built-in pattern.
+    fn default() -> Self {
+        Self::new(&RedactionConfig {
+            builtin: None,
+            patterns: BTreeMap::new(),
+        })
+        .expect("built-in redaction patterns compile")
+    }
+}
+
+impl Redactor {
+    /// Compile the patterns `config` selects.
+    pub fn new(config: &RedactionConfig) -> Result<Self, RedactionError> {
+        let is_builtin = |name: &str| BUILTIN_PATTERNS.iter().any(|(n, _)| *n == name);
+        if let Some(builtin) = &config.builtin {
+            if let Some(unknown) = builtin.iter().find(|name| !is_builtin(name)) {
+                return Err(RedactionError::UnknownBuiltin(unknown.clone()));
+            }
+        }
+        for name in config.patterns.keys() {
+            if name.is_empty()
+                || !name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+            {
+                return Err(RedactionError::InvalidName(name.clone()));
+            }
+            if is_builtin(name) {
+                return Err(RedactionError::Duplicate(name.clone()));
+            }
+        }
+
+        let builtin = BUILTIN_PATTERNS.iter().filter(|(name, _)| match &config.builtin {
+            Some(selected) => selected.iter().any(|s| s == name),
+            None => true,
+        });
+        let custom = config.patterns.iter().map(|(name, regex)| (name.as_str(), regex.as_str()));
+        let mut patterns = Vec::new();
+        for (name, regex) in builtin.copied().chain(custom) {
+            let regex = fancy_regex::Regex::new(regex).map_err(|e| RedactionError::InvalidPattern {
+                name: name.to_string(),
+                source: Box::new(e),
+            })?;
+            patterns.push(Pattern {
+                name: name.to_string(),
+                regex,
+            });
+        }
+        Ok(Self { patterns })
+    }
+
+    /// Redact `text`, adding what was replaced to `counts`.
+    ///
+    /// Empty matches are ignored. Fails if a pattern errors mid-text (for
+    /// example by exceeding the regex backtrack limit) rather than pass
+    /// the text through unredacted.
+    pub fn redact(&self, text: &str, counts: &mut RedactionCounts) -> Result<String, RedactionError> {
+        let mut text = text.to_string();
+        for pattern in &self.patterns {
+            let mut out = String::with_capacity(text.len());
+            let mut last = 0;
+            let mut replaced = 0;
+            for captures in pattern.regex.captures_iter(&text) {
+                let captures = captures.map_err(|e| RedactionError::Failed {
+                    name: pattern.name.clone(),
+                    source: Box::new(e),
+                })?;
+                let Some(secret) = captures.name(SECRET_GROUP).or_else(|| captures.get(0)) else {
+                    continue;
+                };
+                if secret.start() == secret.end() || secret.start() < last {
+                    continue;
+                }
+                out.push_str(&text[last..secret.start()]);
+                out.push_str("[REDACTED:");
+                out.push_str(&pattern.name);
+                out.push(']');
+                last = secret.end();
+                replaced += 1;
+            }
+            if replaced > 0 {
+                out.push_str(&text[last..]);
+                text = out;
+                *counts.entry(pattern.name.clone()).or_default() += replaced;
+            }
+        }
+        Ok(text)
+    }
+
+    /// Redact the `content` and snippet `text` of every document in a
+    /// serialized `context.resolve` result, returning the counts.
+    pub fn redact_selection(&self, value: &mut serde_json::Value) -> Result<RedactionCounts, RedactionError> {
+        let mut counts = RedactionCounts::new();
+        let Some(documents) = value.get_mut("documents").and_then(|d| d.as_array_mut()) else {
+            return Ok(counts);
+        };
+        for document in documents {
+            if let Some(serde_json::Value::String(content)) = document.get_mut("content") {
+                *content = self.redact(content, &mut counts)?;
+            }
+            if let Some(snippets) = document.get_mut("snippets").and_then(|s| s.as_array_mut()) {
+                for snippet in snippets {
+                    if let Some(serde_json::Value::String(text)) = snippet.get_mut("text") {
+                        *text = self.redact(text, &mut counts)?;
+                    }
+                }
+            }
+        }
+        Ok(counts)
+    }
+}
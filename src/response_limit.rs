@@ -0,0 +1,301 @@
+//! Caps on the size of tool results written to the client.
+//!
+//! A large `budget` can make a single `tools/call` response megabytes long.
+//! The config file's `responses` section caps each response line, newline
+//! included:
+//!
+//! ```json
+//! {
+//!   "responses": { "max_bytes": 1048576, "overflow": "resource" }
+//! }
+//! ```
+//!
+//! A tool result that would exceed `max_bytes` is either truncated
+//! (`"truncate"`, the default) or kept on the session and replaced by a text
+//! item naming a resource the client pages through with `resources/read`
+//! (`"resource"`). Both use only what protocol version 2024-11-05 defines. Truncation drops trailing `documents` and marks the result
+//! `truncated`, so it stays valid JSON; a result without documents to drop is
+//! cut with a marker at the end instead. Both are deterministic: the same
+//! result always truncates to the same bytes and splits into the same pages.
+
+use std::collections::VecDeque;
+
+use serde::Deserialize;
+
+use crate::protocol::{JsonRpcError, JsonRpcResponse};
+
+/// Smallest usable cap: room for a link or marker plus the envelope.
+pub const MIN_MAX_BYTES: usize = 4096;
+
+/// Bytes kept free in every page for the `resources/read` envelope.
+const PAGE_ENVELOPE_BYTES: usize = 1024;
+
+/// How many oversized results a session keeps readable. Older ones are
+/// dropped as new ones arrive.
+pub const SPILLED_RESULTS: usize = 16;
+
+/// Default for `max_spilled_bytes`: 64 MiB.
+pub const DEFAULT_SPILLED_BYTES: usize = 64 * 1024 * 1024;
+
+/// URI prefix of spilled results.
+const URI_PREFIX: &str = "context://responses/";
+
+/// The config file's `responses` section.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseLimit {
+    pub max_bytes: usize,
+    #[serde(default)]
+    pub overflow: Overflow,
+    /// Bytes of spilled results one session keeps. Older results are
+    /// dropped to make room; a result larger than this is truncated instead.
+    #[serde(default = "ResponseLimit::default_spilled_bytes")]
+    pub max_spilled_bytes: usize,
+}
+
+/// What happens to a tool result over the cap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    /// Drop trailing documents, or cut the text, to fit.
+    #[default]
+    Truncate,
+    /// Keep the result on the session and return a text item naming it.
+    Resource,
+}
+
+impl ResponseLimit {
+    fn default_spilled_bytes() -> usize {
+        DEFAULT_SPILLED_BYTES
+    }
+
+    /// Check that the cap leaves room for a marker or link.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_bytes < MIN_MAX_BYTES {
+            return Err(format!("responses.max_bytes must be at least {MIN_MAX_BYTES}"));
+        }
+        if self.max_spilled_bytes < self.max_bytes {
+            return Err("responses.max_spilled_bytes must be at least max_bytes".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Oversized results one session has spilled, oldest first.
+#[derive(Debug, Default)]
+pub struct SpilledResults {
+    next_id: u64,
+    results: VecDeque<Spilled>,
+    /// Total `bytes` of `results`.
+    bytes: usize,
+}
+
+#[derive(Debug)]
+struct Spilled {
+    id: u64,
+    bytes: usize,
+    pages: Vec<String>,
+}
+
+impl SpilledResults {
+    /// Fit a `tools/call` response under `limit`, truncating or spilling its
+    /// result text. Responses that fit are returned untouched.
+    pub fn limit(&mut self, limit: &ResponseLimit, mut response: JsonRpcResponse) -> JsonRpcResponse {
+        let Ok(line) = serde_json::to_string(&response) else {
+            return response;
+        };
+        if line.len() < limit.max_bytes {
+            return response;
+        }
+        let Some(result) = response.result.as_mut() else {
+            return response;
+        };
+        let Some(original) = result["content"]
+            .get(0)
+            .and_then(|content| content["text"].as_str())
+            .map(str::to_string)
+        else {
+            return response;
+        };
+        // Everything on the line but the text, including the newline.
+        let envelope = line.len() + 1 - escaped_len(&original);
+
+        if limit.overflow == Overflow::Resource && original.len() <= limit.max_spilled_bytes {
+            result["content"] = serde_json::json!([self.spill(original, limit)]);
+        } else {
+            result["content"][0]["text"] = truncate(&original, limit.max_bytes.saturating_sub(envelope)).into();
+        }
+        response
+    }
+
+    /// Keep `text` as pages of at most `max_bytes` per `resources/read`
+    /// response and return a text content item whose JSON names the
+    /// resource holding it.
+    fn spill(&mut self, text: String, limit: &ResponseLimit) -> serde_json::Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        let bytes = text.len();
+        let pages = paginate(&text, limit.max_bytes - PAGE_ENVELOPE_BYTES);
+        let resource = serde_json::json!({
+            "resource": {
+                "uri": format!("{URI_PREFIX}{id}"),
+                "mimeType": "text/plain",
+                "size": bytes,
+                "pages": pages.len(),
+            }
+        });
+        let link = serde_json::json!({ "type": "text", "text": resource.to_string() });
+
+        while self.results.len() == SPILLED_RESULTS || self.bytes + bytes > limit.max_spilled_bytes {
+            let Some(dropped) = self.results.pop_front() else {
+                break;
+            };
+            self.bytes -= dropped.bytes;
+        }
+        self.results.push_back(Spilled { id, bytes, pages });
+        self.bytes += bytes;
+        link
+    }
+
+    /// `resources/list`: the spilled results still readable.
+    pub fn list(&self) -> serde_json::Value {
+        let resources: Vec<serde_json::Value> = self
+            .results
+            .iter()
+            .map(|spilled| {
+                serde_json::json!({
+                    "uri": format!("{URI_PREFIX}{}", spilled.id),
+                    "name": format!("Tool result {}", spilled.id),
+                    "mimeType": "text/plain",
+                })
+            })
+            .collect();
+        serde_json::json!({ "resources": resources })
+    }
+
+    /// `resources/read` of `<uri>?page=N` (1-based; the bare URI is page 1).
+    /// Every page but the last names the next one in `_meta.nextPage`.
+    pub fn read(&self, params: Option<&serde_json::Value>) -> Result<serde_json::Value, JsonRpcError> {
+        let uri = params
+            .and_then(|p| p.get("uri"))
+            .and_then(|uri| uri.as_str())
+            .ok_or_else(|| JsonRpcError::invalid_params("Missing uri for resources/read"))?;
+        let (id, page) = parse_uri(uri).ok_or_else(JsonRpcError::resource_not_found)?;
+        let spilled = self
+            .results
+            .iter()
+            .find(|spilled| spilled.id == id)
+            .ok_or_else(JsonRpcError::resource_not_found)?;
+        let text = page
+            .checked_sub(1)
+            .and_then(|index| spilled.pages.get(index))
+            .ok_or_else(JsonRpcError::resource_not_found)?;
+
+        let mut result = serde_json::json!({
+            "contents": [{ "uri": uri, "mimeType": "text/plain", "text": text }]
+        });
+        if page < spilled.pages.len() {
+            result["_meta"] = serde_json::json!({ "nextPage": format!("{URI_PREFIX}{id}?page={}", page + 1) });
+        }
+        Ok(result)
+    }
+}
+
+/// Shorten `text` so it serializes to at most `budget` bytes: by dropping
+/// trailing documents when it is a result with `documents`, otherwise by
+/// cutting it.
+fn truncate(text: &str, budget: usize) -> String {
+    drop_documents(text, budget).unwrap_or_else(|| cut(text, budget))
+}
+
+/// Keep the longest prefix of the result's `documents` that fits in
+/// `budget`, adding `truncated`, `documents_omitted`, and `tokens_omitted`.
+/// `None` when `text` has no `documents` array or does not fit without any.
+fn drop_documents(text: &str, budget: usize) -> Option<String> {
+    let mut value: serde_json::Value = serde_json::from_str(text).ok()?;
+    let documents = std::mem::take(value.get_mut("documents")?.as_array_mut()?);
+    let render = |kept: usize| {
+        let omitted = &documents[kept..];
+        let mut value = value.clone();
+        value["documents"] = documents[..kept].into();
+        value["truncated"] = true.into();
+        value["documents_omitted"] = omitted.len().into();
+        value["tokens_omitted"] = omitted.iter().filter_map(|doc| doc["tokens"].as_u64()).sum::<u64>().into();
+        format!("{value}\n")
+    };
+    let fits = |kept: usize| escaped_len(&render(kept)) <= budget;
+
+    if !fits(0) {
+        return None;
+    }
+    // Fewer documents always serialize shorter: find the most that fit.
+    let (mut fitting, mut too_many) = (0, documents.len() + 1);
+    while too_many - fitting > 1 {
+        let mid = (fitting + too_many) / 2;
+        if fits(mid) {
+            fitting = mid;
+        } else {
+            too_many = mid;
+        }
+    }
+    Some(render(fitting))
+}
+
+/// Cut `text` so it serializes to at most `budget` bytes including the
+/// marker. Cuts fall on character boundaries.
+fn cut(text: &str, budget: usize) -> String {
+    let marker = format!("\n[truncated: result was {} bytes]\n", text.len());
+    let budget = budget.saturating_sub(escaped_len(&marker));
+    let end = prefix_end(text, budget);
+    format!("{}{marker}", &text[..end])
+}
+
+/// Split `text` into pieces that each serialize to at most `budget` bytes.
+fn paginate(text: &str, budget: usize) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let end = prefix_end(rest, budget).max(rest.chars().next().map_or(0, char::len_utf8));
+        pages.push(rest[..end].to_string());
+        rest = &rest[end..];
+    }
+    if pages.is_empty() {
+        pages.push(String::new());
+    }
+    pages
+}
+
+/// Byte length of the longest prefix of `text` whose JSON-escaped form fits
+/// in `budget` bytes.
+fn prefix_end(text: &str, budget: usize) -> usize {
+    let mut used = 0;
+    for (i, c) in text.char_indices() {
+        used += escaped_char_len(c);
+        if used > budget {
+            return i;
+        }
+    }
+    text.len()
+}
+
+/// Length of `text` as a JSON string body, without the quotes.
+fn escaped_len(text: &str) -> usize {
+    text.chars().map(escaped_char_len).sum()
+}
+
+fn escaped_char_len(c: char) -> usize {
+    match c {
+        '"' | '\\' | '\n' | '\r' | '\t' | '\u{08}' | '\u{0c}' => 2,
+        c if (c as u32) < 0x20 => 6,
+        c => c.len_utf8(),
+    }
+}
+
+/// The result id and 1-based page of a spilled result URI.
+fn parse_uri(uri: &str) -> Option<(u64, usize)> {
+    let rest = uri.strip_prefix(URI_PREFIX)?;
+    match rest.split_once("?page=") {
+        Some((id, page)) => Some((id.parse().ok()?, page.parse().ok()?)),
+        None => Some((rest.parse().ok()?, 1)),
+    }
+}
@@ -0,0 +1,138 @@
+//! Server-side scoring for `context.resolve`.
+//!
+//! context-core scores a document by `term_matches / total_words`. When a
+//! request asks for BM25, its query uses phrases or `+`/`-` operators (see
+//! [`crate::query`]), or a dictionary expanded it (see [`crate::expansion`]),
+//! the server re-scores context-core's ranking itself:
+//! every document is matched against the parsed query, documents that miss
+//! a required clause or contain an excluded one are filtered out, and the
+//! rest are scored and re-ranked (score descending, then id ascending, as
+//! context-core ranks).
+//!
+//! BM25 corpus statistics (document count, average length, and document
+//! frequency per clause) are computed over the whole cache on each request,
+//! so scores depend only on the cache and the query.
+
+use std::collections::BTreeSet;
+
+use crate::expansion;
+use crate::protocol::Scoring;
+use crate::query::{Clause, Operator, ParsedQuery};
+use crate::selection::{self, Candidate};
+
+/// BM25 term-frequency saturation.
+pub const BM25_K1: f64 = 1.2;
+
+/// BM25 length normalization.
+pub const BM25_B: f64 = 0.75;
+
+/// How BM25 computes a document's `score`, as reported by explain mode.
+pub const BM25_FORMULA: &str = "sum over clauses of idf * tf * (k1 + 1) / (tf + k1 * (1 - b + b * total_words / avg_total_words)), k1 = 1.2, b = 0.75";
+
+/// Re-score `ranked` (every document of a cache) against `query`.
+///
+/// Returns the documents re-ranked under `scoring`, and the ids of those
+/// filtered out by `+` and `-` clauses. Filtered documents stay in the
+/// ranking so they can be reported as excluded.
+pub fn rescore(
+    ranked: Vec<Candidate>,
+    query: &ParsedQuery,
+    scoring: Scoring,
+) -> (Vec<Candidate>, BTreeSet<String>) {
+    let positive = query.positive_clauses();
+    let query_terms = expansion::query_terms(query);
+
+    let documents: Vec<(Vec<String>, Vec<usize>)> = ranked
+        .iter()
+        .map(|candidate| {
+            let words: Vec<String> = selection::words(&candidate.content)
+                .into_iter()
+                .map(|w| w.text)
+                .collect();
+            let counts = positive.iter().map(|c| occurrences(&words, c)).collect();
+            (words, counts)
+        })
+        .collect();
+
+    let total_docs = ranked.len() as f64;
+    let avg_len = match documents.len() {
+        0 => 0.0,
+        n => documents.iter().map(|(w, _)| w.len()).sum::<usize>() as f64 / n as f64,
+    };
+    let idf: Vec<f64> = (0..positive.len())
+        .map(|i| {
+            let df = documents.iter().filter(|(_, counts)| counts[i] > 0).count() as f64;
+            (1.0 + (total_docs - df + 0.5) / (df + 0.5)).ln()
+        })
+        .collect();
+
+    let mut filtered = BTreeSet::new();
+    let mut rescored: Vec<Candidate> = ranked
+        .into_iter()
+        .zip(documents)
+        .map(|(mut candidate, (words, counts))| {
+            let keep = query.clauses.iter().all(|clause| match clause.operator {
+                Operator::Optional => true,
+                Operator::Required => occurrences(&words, clause) > 0,
+                Operator::Excluded => occurrences(&words, clause) == 0,
+            });
+            if !keep {
+                filtered.insert(candidate.id.clone());
+            }
+
+            let term_matches: usize = positive
+                .iter()
+                .zip(&counts)
+                .map(|(clause, &count)| count * clause.words.len())
+                .sum();
+            let total_words = words.len();
+
+            candidate.score = match scoring {
+                Scoring::Bm25 => {
+                    let norm = if avg_len > 0.0 {
+                        1.0 - BM25_B + BM25_B * total_words as f64 / avg_len
+                    } else {
+                        1.0
+                    };
+                    counts
+                        .iter()
+                        .zip(&idf)
+                        .map(|(&tf, idf)| {
+                            let tf = tf as f64;
+                            idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * norm)
+                        })
+                        .sum()
+                }
+                // Hybrid scoring fuses term frequency with embeddings; see
+                // `crate::hybrid`.
+                _ if total_words == 0 => 0.0,
+                _ => term_matches as f64 / total_words as f64,
+            };
+            candidate.why = serde_json::json!({
+                "query_terms": query_terms,
+                "term_matches": term_matches,
+                "total_words": total_words,
+            });
+            candidate
+        })
+        .collect();
+
+    rescored.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+    (rescored, filtered)
+}
+
+/// Non-overlapping occurrences of `clause` in `words`.
+fn occurrences(words: &[String], clause: &Clause) -> usize {
+    let len = clause.words.len();
+    let mut count = 0;
+    let mut i = 0;
+    while i + len <= words.len() {
+        if words[i..i + len] == clause.words[..] {
+            count += 1;
+            i += len;
+        } else {
+            i += 1;
+        }
+    }
+    count
+}
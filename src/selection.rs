@@ -0,0 +1,362 @@
+//! Server-side access to context-core's ranking.
+//!
+//! `context.resolve` serializes context-core's `SelectionResult` directly.
+//! Features that need to inspect a selection use [`select`], which decodes
+//! the same result into [`Candidate`]s. Features that need every document's
+//! score and token count (rather than only those packed into a budget) use
+//! [`rank_all`], which runs the same selector with an unbounded budget so
+//! scores and tokens match `resolve` exactly.
+//!
+//! When a request changes what a document costs, which documents are
+//! eligible, or how they are chosen (a different tokenizer, snippets instead
+//! of full content, dedup, query operators, or the MMR strategy), the server
+//! re-packs the ranking itself with [`pack`], which applies the same greedy
+//! rule as context-core and produces output of the same shape.
+
+use context_core::cache::ContextCache;
+use context_core::selection::ContextSelector;
+use context_core::types::Query;
+use std::collections::{BTreeSet, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::dedup::{self, Duplicate};
+use crate::mmr;
+use crate::protocol::{Scoring, Strategy};
+use crate::snippets::{self, Snippet};
+use crate::tokenizer::Tokenizer;
+
+/// A document as scored by context-core, in ranking order.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Candidate {
+    pub id: String,
+    pub version: String,
+    pub content: String,
+    pub score: f64,
+    pub tokens: usize,
+    pub why: serde_json::Value,
+}
+
+impl Candidate {
+    /// The query terms the document was scored against, from
+    /// `why.query_terms`. Expanded queries list terms as objects (see
+    /// [`crate::expansion::query_terms`]); their `term` is returned.
+    pub fn query_terms(&self) -> Vec<String> {
+        self.why
+            .get("query_terms")
+            .and_then(serde_json::Value::as_array)
+            .map(|terms| {
+                terms
+                    .iter()
+                    .filter_map(|t| t.as_str().or_else(|| t.get("term")?.as_str()))
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// A word of document content, normalized the way context-core matches
+/// query terms: lowercased, with leading and trailing non-alphanumeric
+/// characters trimmed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    /// 1-based line number.
+    pub line: usize,
+    /// 0-based index among all words of the document.
+    pub index: usize,
+    pub text: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SelectionError {
+    #[error("selection failed: {0}")]
+    Core(String),
+    #[error("selection output could not be decoded: {0}")]
+    Decode(#[from] serde_json::Error),
+}
+
+/// Score every document in `cache` against `query`.
+///
+/// Documents are returned in context-core's ranking order (score
+/// descending, then id ascending).
+pub fn rank_all(cache: &ContextCache, query: &str) -> Result<Vec<Candidate>, SelectionError> {
+    select(cache, query, usize::MAX)
+}
+
+/// Run context-core's selection for `query` within `budget` and return the
+/// selected documents in ranking order, exactly as `context.resolve` would.
+pub fn select(
+    cache: &ContextCache,
+    query: &str,
+    budget: usize,
+) -> Result<Vec<Candidate>, SelectionError> {
+    let selector = ContextSelector::default();
+    let result = selector
+        .select(cache, Query::new(query), budget)
+        .map_err(|e| SelectionError::Core(e.to_string()))?;
+
+    let mut value = serde_json::to_value(&result)?;
+    let documents = value
+        .get_mut("documents")
+        .map(serde_json::Value::take)
+        .unwrap_or_default();
+    Ok(serde_json::from_value(documents)?)
+}
+
+/// Why a ranked document is missing from a selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExclusionReason {
+    /// The document did not fit in the remaining token budget.
+    BudgetExceeded,
+    /// The document matched no query term and was left out: snippets mode
+    /// has nothing to return for it, and an explained selection reports
+    /// zero-score documents that did not fit this way. Zero-score documents
+    /// that fit are still selected in full-content mode.
+    NoMatch,
+    /// The document was removed by a filter before packing.
+    Filtered,
+}
+
+/// A ranked document left out of a selection.
+#[derive(Debug, Clone)]
+pub struct Excluded {
+    pub id: String,
+    /// Tokens the document would have been charged.
+    pub tokens: usize,
+    pub reason: ExclusionReason,
+}
+
+/// How [`pack`] charges and renders documents.
+pub struct PackOptions<'a> {
+    /// Name reported in `selection.tokenizer`.
+    pub tokenizer_name: &'a str,
+    pub tokenizer: &'a Tokenizer,
+    /// Return snippets instead of full content.
+    pub snippets: bool,
+    /// Suppress near-duplicates of higher-ranked documents before packing.
+    pub dedup: bool,
+    pub strategy: Strategy,
+    /// Scoring mode reported in `selection.scoring`, when the server scored
+    /// the ranking.
+    pub scoring: Option<Scoring>,
+    /// Ids of documents filtered out by query operators.
+    pub filtered: &'a BTreeSet<String>,
+}
+
+/// A selection packed by the server. Serializes like context-core's
+/// `SelectionResult`.
+#[derive(Debug, Serialize)]
+pub struct Selection {
+    pub documents: Vec<SelectedDocument>,
+    pub selection: SelectionMetadata,
+}
+
+/// Field order mirrors context-core's `SelectedDocument`; exactly one of
+/// `content` and `snippets` is present.
+#[derive(Debug, Serialize)]
+pub struct SelectedDocument {
+    pub id: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippets: Option<Vec<Snippet>>,
+    pub score: f64,
+    pub tokens: usize,
+    pub why: serde_json::Value,
+}
+
+/// context-core's `SelectionMetadata` plus the server-side options in effect.
+#[derive(Debug, Serialize)]
+pub struct SelectionMetadata {
+    pub query: String,
+    pub budget: usize,
+    pub tokens_used: usize,
+    pub documents_considered: usize,
+    pub documents_selected: usize,
+    pub documents_excluded_by_budget: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documents_excluded_no_match: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_mode: Option<&'static str>,
+    pub tokenizer: String,
+    pub strategy: Strategy,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scoring: Option<Scoring>,
+    /// Documents removed by `+` and `-` query clauses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documents_excluded_by_query: Option<usize>,
+    /// Documents suppressed by the dedup stage, in rank order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicates_suppressed: Option<Vec<Duplicate>>,
+}
+
+/// Pack `ranked` into `budget`.
+///
+/// With [`Strategy::Score`], documents are taken in rank order whenever
+/// their cost fits in the remaining budget, the same greedy rule
+/// context-core applies; [`Strategy::Mmr`] picks them with [`mmr::select`]
+/// instead. Documents filtered out by query operators and, with `dedup`,
+/// suppressed duplicates are skipped before packing.
+/// Returns the selection and every ranked document that was left out, in
+/// rank order.
+pub fn pack(
+    ranked: &[Candidate],
+    query: &str,
+    budget: usize,
+    options: &PackOptions<'_>,
+) -> (Selection, Vec<Excluded>) {
+    let duplicates = if options.dedup {
+        dedup::dedup(ranked.iter().filter(|c| !options.filtered.contains(&c.id)))
+    } else {
+        Vec::new()
+    };
+    let duplicate_ids: HashSet<&str> = duplicates.iter().map(|d| d.id.as_str()).collect();
+
+    // (rank index, exclusion) pairs, sorted by rank at the end.
+    let mut excluded = Vec::new();
+    let mut eligible = Vec::new();
+
+    for (index, candidate) in ranked.iter().enumerate() {
+        let filtered = options.filtered.contains(&candidate.id) || duplicate_ids.contains(candidate.id.as_str());
+        let rendered = render(candidate, options);
+
+        let Some((content, snippets, tokens)) = rendered else {
+            let reason = if filtered {
+                ExclusionReason::Filtered
+            } else {
+                ExclusionReason::NoMatch
+            };
+            excluded.push((index, Excluded { id: candidate.id.clone(), tokens: 0, reason }));
+            continue;
+        };
+
+        if filtered {
+            let reason = ExclusionReason::Filtered;
+            excluded.push((index, Excluded { id: candidate.id.clone(), tokens, reason }));
+            continue;
+        }
+
+        eligible.push((index, content, snippets, tokens));
+    }
+
+    let costs: Vec<usize> = eligible.iter().map(|e| e.3).collect();
+    let picked = match options.strategy {
+        Strategy::Score => {
+            let mut picked = Vec::new();
+            let mut tokens_used = 0;
+            for (pos, &tokens) in costs.iter().enumerate() {
+                if tokens_used + tokens <= budget {
+                    tokens_used += tokens;
+                    picked.push(pos);
+                }
+            }
+            picked
+        }
+        Strategy::Mmr => {
+            let candidates: Vec<&Candidate> = eligible.iter().map(|e| &ranked[e.0]).collect();
+            mmr::select(&candidates, &costs, budget)
+        }
+    };
+
+    let mut eligible: Vec<_> = eligible.into_iter().map(Some).collect();
+    let mut documents = Vec::new();
+    let mut tokens_used = 0;
+    for pos in picked {
+        let Some((index, content, snippets, tokens)) = eligible[pos].take() else {
+            continue;
+        };
+        let candidate = &ranked[index];
+        tokens_used += tokens;
+        documents.push(SelectedDocument {
+            id: candidate.id.clone(),
+            version: candidate.version.clone(),
+            content,
+            snippets,
+            score: candidate.score,
+            tokens,
+            why: candidate.why.clone(),
+        });
+    }
+    for (index, _, _, tokens) in eligible.into_iter().flatten() {
+        let id = ranked[index].id.clone();
+        let reason = ExclusionReason::BudgetExceeded;
+        excluded.push((index, Excluded { id, tokens, reason }));
+    }
+    excluded.sort_by_key(|(index, _)| *index);
+    let excluded: Vec<Excluded> = excluded.into_iter().map(|(_, e)| e).collect();
+
+    let count = |reason| excluded.iter().filter(|e| e.reason == reason).count();
+    let selection = SelectionMetadata {
+        query: query.to_string(),
+        budget,
+        tokens_used,
+        documents_considered: ranked.len(),
+        documents_selected: documents.len(),
+        documents_excluded_by_budget: count(ExclusionReason::BudgetExceeded),
+        documents_excluded_no_match: options.snippets.then(|| count(ExclusionReason::NoMatch)),
+        content_mode: options.snippets.then_some("snippets"),
+        tokenizer: options.tokenizer_name.to_string(),
+        strategy: options.strategy,
+        scoring: options.scoring,
+        documents_excluded_by_query: options.scoring.map(|_| options.filtered.len()),
+        duplicates_suppressed: options.dedup.then_some(duplicates),
+    };
+
+    (
+        Selection {
+            documents,
+            selection,
+        },
+        excluded,
+    )
+}
+
+/// Render a candidate as `(content, snippets, tokens)` under `options`.
+///
+/// Returns `None` in snippets mode when the document has no matches.
+fn render(
+    candidate: &Candidate,
+    options: &PackOptions<'_>,
+) -> Option<(Option<String>, Option<Vec<Snippet>>, usize)> {
+    if options.snippets {
+        let snippets = snippets::snippets(&candidate.content, &candidate.query_terms());
+        if snippets.is_empty() {
+            return None;
+        }
+        let tokens = snippets
+            .iter()
+            .map(|s| options.tokenizer.count(&s.text))
+            .sum();
+        Some((None, Some(snippets), tokens))
+    } else {
+        let tokens = options.tokenizer.count(&candidate.content);
+        Some((Some(candidate.content.clone()), None, tokens))
+    }
+}
+
+/// Split `content` into words exactly as context-core counts `total_words`.
+pub fn words(content: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    for (line_idx, line) in content.lines().enumerate() {
+        for raw in line.split_whitespace() {
+            words.push(Word {
+                line: line_idx + 1,
+                index: words.len(),
+                text: raw
+                    .to_lowercase()
+                    .trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_string(),
+            });
+        }
+    }
+    words
+}
+
+/// Estimate the tokens in `text` the way context-core charges document
+/// content: one token per four characters, rounded up.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
@@ -1,43 +1,122 @@
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::time::Instant;
 
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use serde::Serialize;
+use tracing::field::Empty;
+use tracing::{debug, warn, Instrument};
+
+use crate::access::{ClientIdentity, Principal};
 use crate::config::ServerConfig;
 use crate::handlers;
-use crate::protocol::{JsonRpcError, JsonRpcRequest, JsonRpcResponse};
+use crate::logging;
+use crate::notifications::Notifier;
+use crate::protocol::{
+    ClientInfo, InitializeParams, JsonRpcError, JsonRpcRequest, JsonRpcResponse, McpErrorCode, McpErrorResponse,
+};
+use crate::response_limit::{Overflow, SpilledResults};
 
 /// Maximum bytes per JSON-RPC message (1 MiB).
 const MAX_MESSAGE_BYTES: usize = 1024 * 1024;
 
-/// MCP server that communicates over stdio using newline-delimited JSON-RPC 2.0.
+/// MCP server session speaking newline-delimited JSON-RPC 2.0.
+///
+/// One `McpServer` holds the state of a single client connection (the
+/// initialization handshake, the client's identity, oversized results
+/// waiting to be read, and the log level it asked for). Stdio serves
+/// exactly one session; socket transports create one per accepted
+/// connection.
 pub struct McpServer {
     config: ServerConfig,
     initialized: bool,
+    client: ClientIdentity,
+    /// `clientInfo` from `initialize`, for the audit log.
+    client_info: Option<ClientInfo>,
+    /// Whether `clientInfo.name` identifies the client for access control.
+    trusts_client_name: bool,
+    /// Whether `initialize` must carry a known bearer token.
+    requires_token: bool,
+    /// Oversized tool results, readable through `resources/read`.
+    spilled: SpilledResults,
+    /// Session id in `request` spans, for routing log messages.
+    session: u64,
 }
 
 impl McpServer {
+    /// A session with the process that launched the server (stdio). The
+    /// client is identified by the `clientInfo.name` it sends in
+    /// `initialize`.
     pub fn new(config: ServerConfig) -> Self {
         Self {
             config,
             initialized: false,
+            client: ClientIdentity::default(),
+            client_info: None,
+            trusts_client_name: true,
+            requires_token: false,
+            spilled: SpilledResults::default(),
+            session: logging::next_session(),
         }
     }
 
+    /// A session with a socket peer identified by the transport. A name the
+    /// peer claims in `initialize` is not trusted.
+    pub fn with_client(config: ServerConfig, client: ClientIdentity) -> Self {
+        Self {
+            config,
+            initialized: false,
+            client,
+            client_info: None,
+            trusts_client_name: false,
+            requires_token: false,
+            spilled: SpilledResults::default(),
+            session: logging::next_session(),
+        }
+    }
+
+    /// A session with a TCP peer. When the config requires authentication
+    /// and TLS did not already identify the client by certificate, the
+    /// handshake only succeeds with a known bearer token.
+    pub fn network(config: ServerConfig, client: ClientIdentity) -> Self {
+        let requires_token = config.auth.is_required() && client.principal.is_none();
+        let mut server = Self::with_client(config, client);
+        server.requires_token = requires_token;
+        server
+    }
+
+    /// Serve a single session over the process's stdin/stdout.
     pub async fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let stdin = tokio::io::stdin();
-        let mut stdout = tokio::io::stdout();
-        let mut reader = BufReader::new(stdin);
+        self.serve(tokio::io::stdin(), tokio::io::stdout()).await?;
+        Ok(())
+    }
+
+    /// Serve a single session over an arbitrary byte stream until EOF.
+    pub async fn serve<R, W>(&mut self, input: R, mut output: W) -> std::io::Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut reader = BufReader::new(input);
         let mut raw = Vec::new();
+        let (notifier, mut notifications) = Notifier::channel();
 
         loop {
             raw.clear();
-            let n = reader.read_until(b'\n', &mut raw).await?;
+            // Never buffer more than one byte past the limit, so a peer
+            // cannot make the server allocate without bound.
+            let n = (&mut reader)
+                .take(MAX_MESSAGE_BYTES as u64 + 1)
+                .read_until(b'\n', &mut raw)
+                .await?;
             if n == 0 {
                 break;
             }
 
             if n > MAX_MESSAGE_BYTES {
-                eprintln!("Message too large: {n} bytes (limit {MAX_MESSAGE_BYTES})");
+                let rest = if raw.ends_with(b"\n") { 0 } else { discard_line(&mut reader).await? };
+                warn!(bytes = n + rest, limit = MAX_MESSAGE_BYTES, "Message too large");
                 write_response(
-                    &mut stdout,
+                    &mut output,
                     &JsonRpcResponse::error(None, JsonRpcError::parse_error()),
                 ).await?;
                 continue;
@@ -47,7 +126,7 @@ impl McpServer {
                 Ok(s) => s.trim(),
                 Err(_) => {
                     write_response(
-                        &mut stdout,
+                        &mut output,
                         &JsonRpcResponse::error(None, JsonRpcError::parse_error()),
                     ).await?;
                     continue;
@@ -61,9 +140,9 @@ impl McpServer {
             let req: JsonRpcRequest = match serde_json::from_str(trimmed) {
                 Ok(r) => r,
                 Err(e) => {
-                    eprintln!("Parse error: {e}");
+                    warn!(error = %e, "Parse error");
                     write_response(
-                        &mut stdout,
+                        &mut output,
                         &JsonRpcResponse::error(None, JsonRpcError::parse_error()),
                     ).await?;
                     continue;
@@ -73,7 +152,7 @@ impl McpServer {
             // Validate jsonrpc version
             if req.jsonrpc != "2.0" {
                 write_response(
-                    &mut stdout,
+                    &mut output,
                     &JsonRpcResponse::error(req.id.clone(), JsonRpcError::invalid_request()),
                 ).await?;
                 continue;
@@ -85,7 +164,7 @@ impl McpServer {
                     continue;
                 }
                 write_response(
-                    &mut stdout,
+                    &mut output,
                     &JsonRpcResponse::error(
                         req.id.clone(),
                         JsonRpcError::invalid_request_with("Server not initialized"),
@@ -94,8 +173,97 @@ impl McpServer {
                 continue;
             }
 
-            if let Some(resp) = handlers::dispatch(&req, &self.config).await {
-                write_response(&mut stdout, &resp).await?;
+            let span = tracing::info_span!(
+                "request",
+                session = self.session,
+                id = req.id.as_ref().map(tracing::field::display),
+                method = %req.method,
+                tool = Empty,
+                cache = Empty,
+                base_cache = Empty,
+                duration_ms = Empty,
+            );
+
+            // Write notifications (e.g. progress) as the handler emits them,
+            // then any it queued just before finishing, then the response.
+            if req.method == "initialize" {
+                let params = req
+                    .params
+                    .clone()
+                    .and_then(|p| serde_json::from_value::<InitializeParams>(p).ok());
+                if self.requires_token {
+                    let authorization = params
+                        .as_ref()
+                        .and_then(|p| p.meta.as_ref())
+                        .and_then(|meta| meta.authorization.as_deref());
+                    match self.config.auth.authenticate_token(authorization) {
+                        Some(name) => {
+                            self.client.principal = Some(Principal::Token(name));
+                            self.requires_token = false;
+                        }
+                        None => {
+                            span.in_scope(|| warn!("Rejected initialize without a known bearer token"));
+                            if req.id.is_some() {
+                                let error = McpErrorResponse::canonical(McpErrorCode::Forbidden);
+                                write_response(
+                                    &mut output,
+                                    &JsonRpcResponse::error(req.id.clone(), error.into()),
+                                ).await?;
+                            }
+                            continue;
+                        }
+                    }
+                }
+                self.client_info = params.and_then(|p| p.client_info);
+                if self.trusts_client_name {
+                    self.client.client_name = self.client_info.as_ref().and_then(|info| info.name.clone());
+                }
+            }
+
+            let started = Instant::now();
+            let mut record = handlers::CallRecord::default();
+            let response = match span.in_scope(|| self.session_request(&req, &notifier)) {
+                Some(response) => Some(response),
+                None => {
+                    let dispatch = handlers::dispatch_recorded(&req, &self.config, &notifier, &self.client, &mut record)
+                        .instrument(span.clone());
+                    tokio::pin!(dispatch);
+                    loop {
+                        tokio::select! {
+                            response = &mut dispatch => break response,
+                            Some(notification) = notifications.recv() => {
+                                write_response(&mut output, &notification).await?;
+                            }
+                        }
+                    }
+                }
+            };
+
+            if let (Some(audit), "tools/call") = (&self.config.audit, req.method.as_str()) {
+                audit
+                    .record_call(
+                        &self.client,
+                        self.client_info.as_ref(),
+                        &record,
+                        response.as_ref(),
+                        started.elapsed(),
+                    )
+                    .instrument(span.clone())
+                    .await;
+            }
+
+            let response = match (&self.config.responses, response) {
+                (Some(limit), Some(resp)) if req.method == "tools/call" => Some(self.spilled.limit(limit, resp)),
+                (_, response) => response,
+            };
+            span.record("duration_ms", started.elapsed().as_millis() as u64);
+            span.in_scope(|| debug!("Request completed"));
+
+            while let Ok(notification) = notifications.try_recv() {
+                write_response(&mut output, &notification).await?;
+            }
+            if let Some(resp) = response {
+                write_response(&mut output, &resp).await?;
             }
 
             if req.method == "initialize" {
@@ -107,13 +275,80 @@ impl McpServer {
     }
 }
 
-async fn write_response(
-    stdout: &mut tokio::io::Stdout,
-    resp: &JsonRpcResponse,
-) -> Result<(), Box<dyn std::error::Error>> {
+impl McpServer {
+    /// Answer the requests that concern this session rather than the caches:
+    /// `logging/setLevel`, and `resources/list` and `resources/read` of
+    /// spilled results when oversized results are spilled.
+    fn session_request(&self, req: &JsonRpcRequest, notifier: &Notifier) -> Option<JsonRpcResponse> {
+        let spills = self
+            .config
+            .responses
+            .as_ref()
+            .is_some_and(|limit| limit.overflow == Overflow::Resource);
+        match req.method.as_str() {
+            "logging/setLevel" => {
+                let level = req
+                    .params
+                    .as_ref()
+                    .and_then(|p| p.get("level"))
+                    .and_then(|level| level.as_str())
+                    .and_then(logging::parse_level);
+                Some(match level {
+                    Some(level) => {
+                        logging::forward_to_client(self.session, notifier.clone(), level);
+                        JsonRpcResponse::success(req.id.clone(), serde_json::json!({}))
+                    }
+                    None => JsonRpcResponse::error(
+                        req.id.clone(),
+                        JsonRpcError::invalid_params("Invalid level for logging/setLevel"),
+                    ),
+                })
+            }
+            "resources/list" if spills => Some(JsonRpcResponse::success(req.id.clone(), self.spilled.list())),
+            "resources/read" if spills => Some(match self.spilled.read(req.params.as_ref()) {
+                Ok(result) => JsonRpcResponse::success(req.id.clone(), result),
+                Err(error) => JsonRpcResponse::error(req.id.clone(), error),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for McpServer {
+    fn drop(&mut self) {
+        logging::forget_client(self.session);
+    }
+}
+
+async fn write_response<W, T>(output: &mut W, resp: &T) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
     let out = serde_json::to_string(resp)?;
-    stdout.write_all(out.as_bytes()).await?;
-    stdout.write_all(b"\n").await?;
-    stdout.flush().await?;
+    output.write_all(out.as_bytes()).await?;
+    output.write_all(b"\n").await?;
+    output.flush().await?;
     Ok(())
 }
+
+/// Skip input up to and including the next newline without buffering it.
+/// Returns the number of bytes skipped.
+async fn discard_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<usize> {
+    let mut skipped = 0;
+    loop {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() {
+            return Ok(skipped);
+        }
+        let (len, done) = match buf.iter().position(|&b| b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (buf.len(), false),
+        };
+        reader.consume(len);
+        skipped += len;
+        if done {
+            return Ok(skipped);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Query-focused snippets for `context.resolve`.
+//!
+//! In snippets mode each document contributes only its best windows around
+//! query-term matches instead of its full content, and the budget is charged
+//! only for those windows. Windows are whole lines, scored by the number of
+//! term matches they contain, and chosen by score then line number, so the
+//! output is as deterministic as full-content selection.
+
+use serde::Serialize;
+
+use crate::selection;
+
+/// Lines of context kept on each side of a matching line.
+pub const CONTEXT_LINES: usize = 1;
+
+/// Maximum snippets returned per document.
+pub const MAX_SNIPPETS: usize = 3;
+
+/// A window of whole lines from a document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Snippet {
+    /// 1-based, inclusive.
+    pub start_line: usize,
+    /// 1-based, inclusive.
+    pub end_line: usize,
+    pub term_matches: usize,
+    pub text: String,
+}
+
+/// Choose the best snippets of `content` for `terms`.
+///
+/// Every matching line yields a candidate window of up to
+/// `2 * CONTEXT_LINES + 1` lines. Windows are taken by match count
+/// (descending) then start line (ascending), skipping any that overlap a
+/// window already taken, until [`MAX_SNIPPETS`] are chosen. The result is
+/// in document order. Documents without matches yield no snippets.
+pub fn snippets(content: &str, terms: &[String]) -> Vec<Snippet> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut matches_per_line = vec![0usize; lines.len() + 1];
+    for word in selection::words(content) {
+        if terms.contains(&word.text) {
+            matches_per_line[word.line] += 1;
+        }
+    }
+
+    let mut windows: Vec<(usize, usize, usize)> = (1..=lines.len())
+        .filter(|&line| matches_per_line[line] > 0)
+        .map(|line| {
+            let start = line.saturating_sub(CONTEXT_LINES).max(1);
+            let end = (line + CONTEXT_LINES).min(lines.len());
+            let score = matches_per_line[start..=end].iter().sum();
+            (start, end, score)
+        })
+        .collect();
+    windows.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+
+    let mut chosen: Vec<(usize, usize, usize)> = Vec::new();
+    for window in windows {
+        if chosen.len() == MAX_SNIPPETS {
+            break;
+        }
+        if chosen.iter().all(|c| window.1 < c.0 || window.0 > c.1) {
+            chosen.push(window);
+        }
+    }
+    chosen.sort_by_key(|&(start, _, _)| start);
+
+    chosen
+        .into_iter()
+        .map(|(start, end, score)| Snippet {
+            start_line: start,
+            end_line: end,
+            term_matches: score,
+            text: lines[start - 1..end].join("\n"),
+        })
+        .collect()
+}
@@ -0,0 +1,217 @@
+//! Token counting for budgets.
+//!
+//! context-core charges roughly one token per four characters. Models count
+//! differently, so the server can count with a byte-pair-encoding tokenizer
+//! loaded from a local rank file instead. Tokenizers are declared by name in
+//! the config file and never fetched over the network.
+//!
+//! Rank files use the tiktoken format: one `<base64 token> <rank>` pair per
+//! line, as published for `cl100k_base`, `o200k_base`, and similar
+//! encodings.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use base64::Engine;
+use serde::Deserialize;
+
+use crate::selection;
+
+/// Name of the built-in tokenizer (context-core's estimate).
+pub const ESTIMATE: &str = "estimate";
+
+/// Pre-tokenization pattern of `cl100k_base`, used when a tokenizer does not
+/// declare its own.
+pub const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+/// A tokenizer declaration from the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TokenizerSpec {
+    /// A tiktoken rank file. Relative paths are resolved against the
+    /// directory of the config file.
+    Tiktoken {
+        path: PathBuf,
+        /// Pre-tokenization regex; defaults to [`CL100K_PATTERN`].
+        #[serde(default)]
+        pattern: Option<String>,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenizerError {
+    #[error("tokenizer name {0:?} is reserved for the built-in estimate")]
+    Reserved(String),
+    #[error("cannot read rank file for tokenizer {name:?}: {source}")]
+    Unreadable {
+        name: String,
+        source: std::io::Error,
+    },
+    #[error("invalid rank file for tokenizer {name:?} at line {line}")]
+    InvalidRanks { name: String, line: usize },
+    #[error("invalid pattern for tokenizer {name:?}: {source}")]
+    InvalidPattern {
+        name: String,
+        source: Box<fancy_regex::Error>,
+    },
+}
+
+/// Counts tokens in text.
+pub enum Tokenizer {
+    /// context-core's built-in estimate.
+    Estimate,
+    Bpe(BpeTokenizer),
+}
+
+impl Tokenizer {
+    /// Count the tokens in `text`.
+    pub fn count(&self, text: &str) -> usize {
+        match self {
+            Self::Estimate => selection::estimate_tokens(text),
+            Self::Bpe(bpe) => bpe.count(text),
+        }
+    }
+}
+
+/// Byte-pair-encoding tokenizer over a tiktoken rank table.
+pub struct BpeTokenizer {
+    ranks: HashMap<Vec<u8>, u32>,
+    pattern: fancy_regex::Regex,
+}
+
+impl BpeTokenizer {
+    /// Load a tokenizer from a tiktoken rank file.
+    pub fn load(name: &str, path: &Path, pattern: Option<&str>) -> Result<Self, TokenizerError> {
+        let text = std::fs::read_to_string(path).map_err(|source| TokenizerError::Unreadable {
+            name: name.to_string(),
+            source,
+        })?;
+
+        let mut ranks = HashMap::new();
+        for (idx, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = || TokenizerError::InvalidRanks {
+                name: name.to_string(),
+                line: idx + 1,
+            };
+            let (token, rank) = line.split_once(' ').ok_or_else(invalid)?;
+            let token = base64::engine::general_purpose::STANDARD
+                .decode(token)
+                .map_err(|_| invalid())?;
+            let rank: u32 = rank.trim().parse().map_err(|_| invalid())?;
+            ranks.insert(token, rank);
+        }
+
+        let pattern = fancy_regex::Regex::new(pattern.unwrap_or(CL100K_PATTERN)).map_err(|e| {
+            TokenizerError::InvalidPattern {
+                name: name.to_string(),
+                source: Box::new(e),
+            }
+        })?;
+
+        Ok(Self { ranks, pattern })
+    }
+
+    /// Count the tokens `text` encodes to.
+    ///
+    /// If the pattern fails mid-text (for example by exceeding the regex
+    /// backtrack limit), the unmatched remainder is charged one token per
+    /// byte, which over-counts rather than overflowing a budget.
+    pub fn count(&self, text: &str) -> usize {
+        let mut count = 0;
+        let mut consumed = 0;
+        for piece in self.pattern.find_iter(text) {
+            match piece {
+                Ok(piece) => {
+                    count += self.count_piece(piece.as_str().as_bytes());
+                    consumed = piece.end();
+                }
+                Err(_) => return count + text.len() - consumed,
+            }
+        }
+        count
+    }
+
+    /// Number of tokens in one pre-tokenized piece, merging the lowest-rank
+    /// adjacent pair until no pair is in the table.
+    fn count_piece(&self, piece: &[u8]) -> usize {
+        if piece.is_empty() {
+            return 0;
+        }
+        if self.ranks.contains_key(piece) {
+            return 1;
+        }
+
+        // Boundaries between parts; parts[i]..parts[i + 1] is one token.
+        let mut parts: Vec<usize> = (0..=piece.len()).collect();
+        loop {
+            let best = (0..parts.len().saturating_sub(2))
+                .filter_map(|i| {
+                    self.ranks
+                        .get(&piece[parts[i]..parts[i + 2]])
+                        .map(|&rank| (rank, i))
+                })
+                .min();
+            match best {
+                Some((_, i)) => {
+                    parts.remove(i + 1);
+                }
+                None => return parts.len() - 1,
+            }
+        }
+    }
+}
+
+/// Tokenizers declared in the config file, loaded once at startup.
+#[derive(Clone, Default)]
+pub struct TokenizerRegistry {
+    tokenizers: Arc<BTreeMap<String, Arc<Tokenizer>>>,
+}
+
+impl TokenizerRegistry {
+    /// Load every declared tokenizer. Relative paths resolve against `base_dir`.
+    pub fn load(
+        specs: &BTreeMap<String, TokenizerSpec>,
+        base_dir: &Path,
+    ) -> Result<Self, TokenizerError> {
+        let mut tokenizers = BTreeMap::new();
+        for (name, spec) in specs {
+            if name == ESTIMATE {
+                return Err(TokenizerError::Reserved(name.clone()));
+            }
+            let tokenizer = match spec {
+                TokenizerSpec::Tiktoken { path, pattern } => Tokenizer::Bpe(BpeTokenizer::load(
+                    name,
+                    &base_dir.join(path),
+                    pattern.as_deref(),
+                )?),
+            };
+            tokenizers.insert(name.clone(), Arc::new(tokenizer));
+        }
+        Ok(Self {
+            tokenizers: Arc::new(tokenizers),
+        })
+    }
+
+    /// Look up a tokenizer by name. [`ESTIMATE`] is always available.
+    pub fn get(&self, name: &str) -> Option<Arc<Tokenizer>> {
+        if name == ESTIMATE {
+            return Some(Arc::new(Tokenizer::Estimate));
+        }
+        self.tokenizers.get(name).cloned()
+    }
+
+    /// Whether `name` refers to a known tokenizer.
+    pub fn contains(&self, name: &str) -> bool {
+        name == ESTIMATE || self.tokenizers.contains_key(name)
+    }
+}
+
+impl std::fmt::Debug for TokenizerRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.tokenizers.keys()).finish()
+    }
+}
@@ -0,0 +1,175 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tracing::{error, info, warn};
+
+use crate::access::ClientIdentity;
+use crate::auth;
+use crate::config::ServerConfig;
+use crate::server::McpServer;
+
+/// Transport the server listens on.
+///
+/// Every transport carries the same newline-delimited JSON-RPC 2.0 stream.
+/// Socket transports accept any number of connections, each of which is an
+/// independent MCP session with its own initialization handshake. Unix
+/// socket peers are identified by their uid; TCP peers by a client
+/// certificate or bearer token when the config's `auth` section asks for
+/// one, and are anonymous otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    /// A single session over stdin/stdout (the default for MCP hosts).
+    Stdio,
+    /// TCP listener on the given address.
+    Tcp(SocketAddr),
+    /// Unix domain socket at the given path.
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+/// Pause after a failed `accept` before trying again, so a persistent
+/// failure such as running out of file descriptors does not spin.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// How long a TCP client has to complete the TLS handshake before the
+/// connection is dropped, so stalled peers do not hold tasks open.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run the server on the given transport until it shuts down.
+///
+/// Stdio returns when stdin reaches EOF. Socket transports return only if
+/// they cannot listen; after that they run until the process is terminated.
+/// Failures to accept a connection are logged and retried after a short
+/// pause, and per-connection I/O errors are logged; neither stops the
+/// listener.
+pub async fn serve(config: ServerConfig, transport: Transport) -> std::io::Result<()> {
+    match transport {
+        Transport::Stdio => {
+            McpServer::new(config)
+                .serve(tokio::io::stdin(), tokio::io::stdout())
+                .await
+        }
+
+        Transport::Tcp(addr) => {
+            config
+                .auth
+                .check_listen_address(addr)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            let tls = config.auth.tls_acceptor();
+            info!(address = %listener.local_addr()?, "Listening on tcp");
+            if !config.auth.is_required() {
+                warn!("TCP clients are not authenticated (see the config's auth section)");
+            } else if config.auth.tokens_in_clear() {
+                warn!("Bearer tokens are accepted without TLS; only loopback clients can connect");
+            }
+
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        accept_failed(e).await;
+                        continue;
+                    }
+                };
+                let config = config.clone();
+                let tls = tls.clone();
+                tokio::spawn(async move {
+                    let result = match tls {
+                        Some(acceptor) => match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                            Err(_) => {
+                                warn!(%peer, "TLS handshake timed out");
+                                return;
+                            }
+                            Ok(Ok(stream)) => {
+                                let client = ClientIdentity {
+                                    principal: auth::certificate_principal(stream.get_ref().1.peer_certificates()),
+                                    ..Default::default()
+                                };
+                                let (reader, writer) = tokio::io::split(stream);
+                                McpServer::network(config, client).serve(reader, writer).await
+                            }
+                            Ok(Err(e)) => Err(e),
+                        },
+                        None => {
+                            let (reader, writer) = stream.into_split();
+                            McpServer::network(config, ClientIdentity::default()).serve(reader, writer).await
+                        }
+                    };
+                    if let Err(e) = result {
+                        warn!(%peer, error = %e, "Connection failed");
+                    }
+                });
+            }
+        }
+
+        #[cfg(unix)]
+        Transport::Unix(path) => {
+            remove_stale_socket(&path)?;
+            let listener = tokio::net::UnixListener::bind(&path)?;
+            info!(path = %path.display(), "Listening on unix socket");
+
+            loop {
+                let (stream, _) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        accept_failed(e).await;
+                        continue;
+                    }
+                };
+                let config = config.clone();
+                tokio::spawn(async move {
+                    let client = match stream.peer_cred() {
+                        Ok(cred) => ClientIdentity {
+                            uid: Some(cred.uid()),
+                            ..Default::default()
+                        },
+                        Err(e) => {
+                            warn!(error = %e, "Cannot read peer credentials");
+                            ClientIdentity::default()
+                        }
+                    };
+                    let (reader, writer) = stream.into_split();
+                    if let Err(e) = McpServer::with_client(config, client).serve(reader, writer).await {
+                        warn!(error = %e, "Connection failed");
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Log a failed `accept` and wait before the next one.
+async fn accept_failed(e: std::io::Error) {
+    error!(error = %e, "Cannot accept connection");
+    tokio::time::sleep(ACCEPT_BACKOFF).await;
+}
+
+/// Remove a socket left at `path` by a server that is no longer running.
+///
+/// Anything else at `path` is left alone: a regular file, or a socket some
+/// process still listens on, makes binding fail instead.
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> std::io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AddrInUse,
+            format!("another server is listening on {}", path.display()),
+        ));
+    }
+    warn!(path = %path.display(), "Removing stale unix socket");
+    std::fs::remove_file(path)
+}
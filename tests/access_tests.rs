@@ -0,0 +1,207 @@
+//! Tests for per-client access control: policy evaluation, filtered
+//! `tools/list` and `context.list_caches`, `forbidden` denials, and how
+//! sessions identify their client.
+
+mod common;
+
+use std::path::Path;
+
+use mcp_context_server::access::{AccessConfig, AccessPolicy, ClientIdentity};
+use mcp_context_server::config::ServerConfig;
+use mcp_context_server::handlers;
+use mcp_context_server::notifications::Notifier;
+use mcp_context_server::protocol::{JsonRpcRequest, RpcId};
+use mcp_context_server::server::McpServer;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use common::{build_cache_with, config_file};
+
+const POLICY: &str = r#"{
+    "clients": [
+        {"uid": 1001, "caches": ["engineering"]},
+        {"uid": 1001, "caches": ["docs"], "tools": ["context.resolve"]},
+        {"client_name": "hr-assistant", "caches": ["hr"], "tools": ["context.resolve", "context.list_caches"]},
+        {"uid": 0, "caches": ["*"]}
+    ]
+}"#;
+
+/// A cache root holding `docs`, `engineering`, and `hr`, served with
+/// [`POLICY`].
+fn policy_config(root: &Path) -> ServerConfig {
+    for name in ["docs", "engineering", "hr"] {
+        build_cache_with(&root.join("caches").join(name), &[("guide.md", &format!("The {name} handbook."))]);
+    }
+    let policy: serde_json::Value = serde_json::from_str(POLICY).unwrap();
+    config_file(root, &root.join("caches"), serde_json::json!({"access": policy}))
+}
+
+fn uid(uid: u32) -> ClientIdentity {
+    ClientIdentity {
+        uid: Some(uid),
+        ..Default::default()
+    }
+}
+
+async fn request(config: &ServerConfig, client: &ClientIdentity, method: &str, params: serde_json::Value) -> serde_json::Value {
+    let req = JsonRpcRequest {
+        jsonrpc: "2.0".into(),
+        id: Some(RpcId::Number(1)),
+        method: method.into(),
+        params: Some(params),
+    };
+    handlers::dispatch_with(&req, config, &Notifier::default(), client)
+        .await
+        .unwrap()
+        .result
+        .unwrap()
+}
+
+async fn call_tool(config: &ServerConfig, client: &ClientIdentity, name: &str, arguments: serde_json::Value) -> serde_json::Value {
+    request(config, client, "tools/call", serde_json::json!({"name": name, "arguments": arguments})).await
+}
+
+fn is_forbidden(result: &serde_json::Value) -> bool {
+    let text = result["content"][0]["text"].as_str().unwrap();
+    result["isError"] == true
+        && serde_json::from_str::<serde_json::Value>(text).unwrap()
+            == serde_json::json!({"error": {"code": "forbidden", "message": "Access is forbidden"}})
+}
+
+fn listed_caches(result: &serde_json::Value) -> Vec<String> {
+    let value: serde_json::Value = serde_json::from_str(result["content"][0]["text"].as_str().unwrap()).unwrap();
+    value["caches"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["path"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn permissions_combine_matching_rules() {
+    let config: AccessConfig = serde_json::from_str(POLICY).unwrap();
+    let policy = AccessPolicy::new(config).unwrap();
+
+    let engineer = policy.permissions(&uid(1001));
+    assert!(engineer.allows_cache("engineering") && engineer.allows_cache("docs"));
+    assert!(!engineer.allows_cache("hr"));
+    assert!(engineer.allows_tool("context.inspect_cache"));
+    assert!(!engineer.allows_tool("context.doctor"), "root-wide tools need every cache");
+
+    let admin = policy.permissions(&uid(0));
+    assert!(admin.allows_cache("hr") && admin.allows_tool("context.doctor"));
+
+    let stranger = policy.permissions(&uid(2000));
+    assert!(!stranger.allows_cache("docs") && !stranger.allows_tool("context.resolve"));
+
+    let with_default: AccessConfig =
+        serde_json::from_str(r#"{"default": {"caches": ["docs"], "tools": ["context.resolve"]}}"#).unwrap();
+    let anyone = AccessPolicy::new(with_default).unwrap().permissions(&ClientIdentity::default());
+    assert!(anyone.allows_cache("docs") && !anyone.allows_cache("hr"));
+
+    let ambiguous: AccessConfig =
+        serde_json::from_str(r#"{"clients": [{"uid": 1, "client_name": "x", "caches": []}]}"#).unwrap();
+    assert!(AccessPolicy::new(ambiguous).is_err());
+}
+
+#[tokio::test]
+async fn denied_caches_are_hidden_and_forbidden() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = policy_config(tmp.path());
+    let engineer = uid(1001);
+
+    let listed = call_tool(&config, &engineer, "context.list_caches", serde_json::json!({})).await;
+    assert_eq!(listed_caches(&listed), ["docs", "engineering"]);
+
+    let allowed = call_tool(
+        &config,
+        &engineer,
+        "context.resolve",
+        serde_json::json!({"cache": "engineering", "query": "handbook", "budget": 100}),
+    )
+    .await;
+    assert!(allowed.get("isError").is_none(), "{allowed}");
+
+    let respelled = call_tool(
+        &config,
+        &engineer,
+        "context.resolve",
+        serde_json::json!({"cache": "./engineering/", "query": "handbook", "budget": 100}),
+    )
+    .await;
+    assert!(respelled.get("isError").is_none(), "{respelled}");
+
+    // Denied caches are forbidden whether or not they exist, however they
+    // are spelled.
+    for cache in ["hr", "hr/", "./hr", "missing"] {
+        let denied = call_tool(
+            &config,
+            &engineer,
+            "context.resolve",
+            serde_json::json!({"cache": cache, "query": "handbook", "budget": 100}),
+        )
+        .await;
+        assert!(is_forbidden(&denied), "{denied}");
+    }
+    let diff = call_tool(&config, &engineer, "context.diff_caches", serde_json::json!({"base": "docs", "target": "hr"})).await;
+    assert!(is_forbidden(&diff), "{diff}");
+    let doctor = call_tool(&config, &engineer, "context.doctor", serde_json::json!({})).await;
+    assert!(is_forbidden(&doctor), "{doctor}");
+
+    let tools = request(&config, &engineer, "tools/list", serde_json::json!({})).await;
+    let names: Vec<&str> = tools["tools"].as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap()).collect();
+    assert!(names.contains(&"context.resolve") && !names.contains(&"context.doctor"));
+
+    // Without an access section, everyone sees everything.
+    let open = ServerConfig {
+        cache_root: config.cache_root.clone(),
+        ..Default::default()
+    };
+    let listed = call_tool(&open, &ClientIdentity::default(), "context.list_caches", serde_json::json!({})).await;
+    assert_eq!(listed_caches(&listed), ["docs", "engineering", "hr"]);
+}
+
+/// Run `requests` through a session and return the responses.
+async fn session(server: McpServer, requests: &[serde_json::Value]) -> Vec<serde_json::Value> {
+    let mut server = server;
+    let (client, server_io) = tokio::io::duplex(64 * 1024);
+    let (server_read, server_write) = tokio::io::split(server_io);
+    let handle = tokio::spawn(async move { server.serve(server_read, server_write).await });
+
+    let (client_read, mut client_write) = tokio::io::split(client);
+    for request in requests {
+        client_write.write_all(format!("{request}\n").as_bytes()).await.unwrap();
+    }
+    client_write.shutdown().await.unwrap();
+
+    let mut lines = BufReader::new(client_read).lines();
+    let mut responses = Vec::new();
+    while let Some(line) = lines.next_line().await.unwrap() {
+        responses.push(serde_json::from_str(&line).unwrap());
+    }
+    handle.await.unwrap().unwrap();
+    responses
+}
+
+#[tokio::test]
+async fn stdio_sessions_are_identified_by_client_name() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = policy_config(tmp.path());
+    let requests = [
+        serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": {"protocolVersion": "2024-11-05", "clientInfo": {"name": "hr-assistant"}}
+        }),
+        serde_json::json!({
+            "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+            "params": {"name": "context.list_caches", "arguments": {}}
+        }),
+    ];
+
+    let stdio = session(McpServer::new(config.clone()), &requests).await;
+    assert_eq!(listed_caches(&stdio[1]["result"]), ["hr"]);
+
+    // Socket peers cannot claim a name.
+    let socket = session(McpServer::with_client(config, ClientIdentity::default()), &requests).await;
+    assert!(is_forbidden(&socket[1]["result"]), "{}", socket[1]);
+}
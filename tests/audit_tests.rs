@@ -0,0 +1,192 @@
+//! Tests for the audit log: entry contents, the hash chain, and size-based
+//! rotation.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use mcp_context_server::audit;
+use mcp_context_server::config::ServerConfig;
+use mcp_context_server::server::McpServer;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use common::{build_cache_with, config_file};
+
+/// A cache root with `docs`, served with the given `audit` section.
+fn audit_config(root: &Path, audit: serde_json::Value) -> ServerConfig {
+    build_cache_with(
+        &root.join("caches").join("docs"),
+        &[("deploy.md", "deploy.md: deployment notes."), ("security.md", "security.md: deployment notes.")],
+    );
+    config_file(root, &root.join("caches"), serde_json::json!({"audit": audit}))
+}
+
+/// Initialize a stdio session as `agent` and make each tool call.
+async fn session(config: ServerConfig, calls: &[serde_json::Value]) {
+    let (client, server_io) = tokio::io::duplex(64 * 1024);
+    let (server_read, server_write) = tokio::io::split(server_io);
+    let handle = tokio::spawn(async move { McpServer::new(config).serve(server_read, server_write).await });
+
+    let (client_read, mut client_write) = tokio::io::split(client);
+    let initialize = serde_json::json!({
+        "jsonrpc": "2.0", "id": 0, "method": "initialize",
+        "params": {"clientInfo": {"name": "agent", "version": "1.2.0"}}
+    });
+    client_write.write_all(format!("{initialize}\n").as_bytes()).await.unwrap();
+    for (i, call) in calls.iter().enumerate() {
+        let request = serde_json::json!({"jsonrpc": "2.0", "id": i + 1, "method": "tools/call", "params": call});
+        client_write.write_all(format!("{request}\n").as_bytes()).await.unwrap();
+    }
+    client_write.shutdown().await.unwrap();
+
+    let mut lines = BufReader::new(client_read).lines();
+    while lines.next_line().await.unwrap().is_some() {}
+    handle.await.unwrap().unwrap();
+}
+
+fn resolve(query: &str) -> serde_json::Value {
+    serde_json::json!({"name": "context.resolve", "arguments": {"cache": "docs", "query": query, "budget": 4000}})
+}
+
+fn entries(path: &Path) -> Vec<serde_json::Value> {
+    fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn tool_calls_are_recorded_in_a_hash_chain() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = audit_config(tmp.path(), serde_json::json!({"path": "logs/audit.jsonl"}));
+    let log = tmp.path().join("logs/audit.jsonl");
+    session(
+        config.clone(),
+        &[
+            resolve("deployment"),
+            serde_json::json!({"name": "context.resolve", "arguments": {"cache": "missing", "query": "x", "budget": 10}}),
+            serde_json::json!({"name": "context.list_caches", "arguments": {}}),
+            serde_json::json!({"name": "context.inspect_cache", "arguments": {"cache": "./docs/"}}),
+            serde_json::json!({"name": "context.unknown", "arguments": {}}),
+        ],
+    )
+    .await;
+
+    let entries = entries(&log);
+    assert_eq!(entries.len(), 5);
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(tmp.path().join("caches/docs/manifest.json")).unwrap()).unwrap();
+
+    let resolved = &entries[0];
+    assert_eq!(resolved["client"], serde_json::json!({"name": "agent", "version": "1.2.0"}));
+    assert_eq!(resolved["tool"], "context.resolve");
+    assert_eq!(resolved["cache"], "docs");
+    assert_eq!(resolved["cache_version"], manifest["cache_version"]);
+    assert_eq!(resolved["query_sha256"], format!("sha256:{}", sha256_hex(b"deployment")));
+    assert!(resolved.get("query").is_none(), "queries are hashed by default");
+    assert_eq!(resolved["budget"], 4000);
+    let ids: Vec<&str> = resolved["documents"].as_array().unwrap().iter().map(|d| d["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, ["deploy.md", "security.md"]);
+    assert!(resolved["documents"][0]["version"].as_str().unwrap().starts_with("sha256:"));
+    assert_eq!(resolved["outcome"], "ok");
+    assert!(resolved["duration_ms"].is_u64());
+    assert!(resolved["prev_hash"].is_null());
+
+    assert_eq!(entries[1]["outcome"], "cache_missing");
+    assert!(entries[1]["cache_version"].is_null() && entries[1]["documents"].is_null());
+    assert_eq!(entries[2]["tool"], "context.list_caches");
+    assert_eq!(entries[1]["prev_hash"], entries[0]["hash"]);
+    assert_eq!(entries[2]["prev_hash"], entries[1]["hash"]);
+    // Caches are recorded by the name they resolve to.
+    assert_eq!(entries[3]["cache"], "docs");
+    assert_eq!(entries[3]["cache_version"], manifest["cache_version"]);
+    // Errors without a code, such as an unknown tool, are plain errors.
+    assert_eq!(entries[4]["outcome"], "error");
+
+    // A restarted server continues the chain.
+    let restarted = ServerConfig {
+        cache_root: config.cache_root.clone(),
+        ..Default::default()
+    }
+    .with_config_file(&tmp.path().join("config.json"))
+    .unwrap();
+    session(restarted, &[resolve("security")]).await;
+    assert_eq!(audit::verify(std::slice::from_ref(&log)).unwrap(), 6);
+
+    // Editing any entry breaks it.
+    let text = fs::read_to_string(&log).unwrap();
+    fs::write(&log, text.replacen("\"outcome\":\"cache_missing\"", "\"outcome\":\"ok\"", 1)).unwrap();
+    let err = audit::verify(std::slice::from_ref(&log)).unwrap_err().to_string();
+    assert!(err.ends_with(":2: hash does not match the entry"), "{err}");
+
+    // So does dropping one.
+    let lines: Vec<&str> = text.lines().collect();
+    fs::write(&log, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+    let err = audit::verify(&[log]).unwrap_err().to_string();
+    assert!(err.ends_with(":2: prev_hash does not match the previous entry"), "{err}");
+}
+
+#[tokio::test]
+async fn diff_calls_record_both_caches() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = audit_config(tmp.path(), serde_json::json!({"path": "audit.jsonl"}));
+    build_cache_with(&tmp.path().join("caches").join("old"), &[("deploy.md", "deploy.md: old notes.")]);
+    session(
+        config,
+        &[serde_json::json!({"name": "context.diff_caches", "arguments": {"base": "./old", "target": "docs/"}})],
+    )
+    .await;
+
+    let entries = entries(&tmp.path().join("audit.jsonl"));
+    assert_eq!(entries[0]["cache"], "docs");
+    assert_eq!(entries[0]["base_cache"], "old");
+    assert_eq!(entries[0]["outcome"], "ok");
+}
+
+#[tokio::test]
+async fn logs_rotate_by_size_and_keep_the_chain() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = audit_config(
+        tmp.path(),
+        serde_json::json!({"path": "audit.jsonl", "query": "full", "max_bytes": 1500, "keep": 2}),
+    );
+    let calls: Vec<serde_json::Value> = (0..8).map(|i| resolve(&format!("deployment {i}"))).collect();
+    session(config.clone(), &calls).await;
+
+    let log = tmp.path().join("audit.jsonl");
+    let files = config.audit.as_ref().unwrap().files();
+    assert_eq!(files, [tmp.path().join("audit.jsonl.2"), tmp.path().join("audit.jsonl.1"), log.clone()]);
+    assert!(!tmp.path().join("audit.jsonl.3").exists(), "only `keep` rotated files remain");
+    for file in &files {
+        assert!(fs::metadata(file).unwrap().len() <= 1500);
+    }
+
+    let current = entries(&log);
+    assert_eq!(current.last().unwrap()["query"], "deployment 7");
+    assert!(current[0].get("query_sha256").is_none());
+    // The chain spans files, oldest first, but not the deleted ones.
+    let verified = audit::verify(&files).unwrap();
+    assert!(verified < 8 && verified >= current.len() + 2, "{verified}");
+    assert!(audit::verify(&[files[0].clone(), log]).is_err());
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(bytes).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[tokio::test]
+async fn a_restarted_server_continues_a_long_log() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = audit_config(tmp.path(), serde_json::json!({"path": "audit.jsonl", "query": "full"}));
+    let calls: Vec<serde_json::Value> = (0..40).map(|i| resolve(&format!("deployment {i}"))).collect();
+    session(config.clone(), &calls).await;
+    let log = tmp.path().join("audit.jsonl");
+    assert!(fs::metadata(&log).unwrap().len() > 8192, "the last entry is found without reading the whole file");
+
+    let restarted = audit_config(tmp.path(), serde_json::json!({"path": "audit.jsonl", "query": "full"}));
+    session(restarted, &[resolve("security")]).await;
+    assert_eq!(audit::verify(&[log]).unwrap(), 41);
+}
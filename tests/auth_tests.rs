@@ -0,0 +1,227 @@
+//! Tests for TCP authentication: bearer tokens checked in `initialize`,
+//! and mutual TLS with locally generated certificates identifying clients
+//! for access control.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use mcp_context_server::access::ClientIdentity;
+use mcp_context_server::config::ServerConfig;
+use mcp_context_server::server::McpServer;
+use mcp_context_server::transport::{self, Transport};
+use rcgen::{BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+
+use common::{build_cache_with, config_file};
+
+const TOKEN: &str = "s3cret-ci-token";
+
+/// A cache root with `docs` and `hr` and a config file whose `auth`
+/// section is `auth`. Token `ci-bot` may use `docs`; certificate
+/// `hr-gateway` may use `hr`.
+fn auth_config(root: &Path, auth: serde_json::Value) -> ServerConfig {
+    for name in ["docs", "hr"] {
+        build_cache_with(&root.join("caches").join(name), &[("guide.md", "The handbook.")]);
+    }
+    let config = serde_json::json!({
+        "auth": auth,
+        "access": {
+            "clients": [
+                {"token": "ci-bot", "caches": ["docs"]},
+                {"certificate": "hr-gateway", "caches": ["hr"]}
+            ]
+        }
+    });
+    config_file(root, &root.join("caches"), config)
+}
+
+fn token_hash(token: &str) -> String {
+    let hex: String = Sha256::digest(token.as_bytes()).iter().map(|b| format!("{b:02x}")).collect();
+    format!("sha256:{hex}")
+}
+
+fn initialize(authorization: Option<&str>) -> serde_json::Value {
+    let mut request = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}});
+    if let Some(authorization) = authorization {
+        request["params"]["_meta"] = serde_json::json!({"authorization": authorization});
+    }
+    request
+}
+
+fn list_caches() -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+        "params": {"name": "context.list_caches", "arguments": {}}
+    })
+}
+
+/// Send `requests` over `stream`, close the write side, and collect the
+/// responses.
+async fn exchange<S: AsyncRead + AsyncWrite>(stream: S, requests: &[serde_json::Value]) -> Vec<serde_json::Value> {
+    let (read, mut write) = tokio::io::split(stream);
+    for request in requests {
+        write.write_all(format!("{request}\n").as_bytes()).await.unwrap();
+    }
+    write.shutdown().await.unwrap();
+
+    let mut lines = BufReader::new(read).lines();
+    let mut responses = Vec::new();
+    while let Ok(Some(line)) = lines.next_line().await {
+        responses.push(serde_json::from_str(&line).unwrap());
+    }
+    responses
+}
+
+fn listed_caches(response: &serde_json::Value) -> Vec<String> {
+    let text = response["result"]["content"][0]["text"].as_str().unwrap();
+    let value: serde_json::Value = serde_json::from_str(text).unwrap();
+    value["caches"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["path"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn bearer_tokens_authenticate_tcp_sessions() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = auth_config(tmp.path(), serde_json::json!({"tokens": {"ci-bot": token_hash(TOKEN)}}));
+    let bearer = format!("Bearer {TOKEN}");
+
+    for (authorization, accepted) in [(None, false), (Some("Bearer wrong"), false), (Some(bearer.as_str()), true)] {
+        let (client, server_io) = tokio::io::duplex(64 * 1024);
+        let (server_read, server_write) = tokio::io::split(server_io);
+        let session = McpServer::network(config.clone(), ClientIdentity::default());
+        let server = tokio::spawn(async move {
+            let mut session = session;
+            session.serve(server_read, server_write).await
+        });
+        let responses = exchange(client, &[initialize(authorization), list_caches()]).await;
+        server.await.unwrap().unwrap();
+
+        if accepted {
+            assert!(responses[0].get("error").is_none(), "{}", responses[0]);
+            assert_eq!(listed_caches(&responses[1]), ["docs"]);
+        } else {
+            assert_eq!(responses[0]["error"]["data"]["error"]["code"], "forbidden");
+            assert_eq!(responses[1]["error"]["message"], "Server not initialized");
+        }
+    }
+
+    // Stdio sessions trust the process boundary and need no token.
+    let (client, server_io) = tokio::io::duplex(64 * 1024);
+    let (server_read, server_write) = tokio::io::split(server_io);
+    let server = tokio::spawn(async move { McpServer::new(config).serve(server_read, server_write).await });
+    let responses = exchange(client, &[initialize(None)]).await;
+    server.await.unwrap().unwrap();
+    assert!(responses[0].get("error").is_none());
+}
+
+#[tokio::test]
+async fn tokens_without_tls_only_listen_on_loopback() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = auth_config(tmp.path(), serde_json::json!({"tokens": {"ci-bot": token_hash(TOKEN)}}));
+
+    let err = transport::serve(config.clone(), Transport::Tcp("0.0.0.0:0".parse().unwrap())).await.unwrap_err();
+    assert!(err.to_string().contains("auth.tokens require auth.tls"), "{err}");
+    assert!(config.auth.check_listen_address("[::]:7400".parse().unwrap()).is_err());
+    assert!(config.auth.check_listen_address("127.0.0.1:7400".parse().unwrap()).is_ok());
+    assert!(config.auth.check_listen_address("[::1]:7400".parse().unwrap()).is_ok());
+}
+
+#[test]
+fn malformed_token_hashes_are_rejected() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config_path = tmp.path().join("config.json");
+    fs::write(&config_path, r#"{"auth": {"tokens": {"ci-bot": "plaintext"}}}"#).unwrap();
+    let err = ServerConfig::default().with_config_file(&config_path).unwrap_err();
+    assert!(err.contains("\"ci-bot\""), "{err}");
+}
+
+/// A CA and a certificate it signs for `name` with `usage`, as PEM.
+fn issue(issuer: &Issuer<'_, KeyPair>, name: &str, usage: ExtendedKeyUsagePurpose) -> (String, String) {
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+    params.distinguished_name.push(DnType::CommonName, name);
+    params.extended_key_usages = vec![usage];
+    let cert = params.signed_by(&key, issuer).unwrap();
+    (cert.pem(), key.serialize_pem())
+}
+
+#[tokio::test]
+async fn mutual_tls_identifies_clients_by_certificate() {
+    let tmp = tempfile::tempdir().unwrap();
+    let certs = tmp.path().join("certs");
+    fs::create_dir_all(&certs).unwrap();
+
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.distinguished_name.push(DnType::CommonName, "Test CA");
+    let ca_pem = ca_params.self_signed(&ca_key).unwrap().pem();
+    let ca = Issuer::new(ca_params, ca_key);
+    let (server_cert, server_key) = issue(&ca, "localhost", ExtendedKeyUsagePurpose::ServerAuth);
+    let (client_cert, client_key) = issue(&ca, "hr-gateway", ExtendedKeyUsagePurpose::ClientAuth);
+    fs::write(certs.join("ca.pem"), &ca_pem).unwrap();
+    fs::write(certs.join("server.pem"), server_cert).unwrap();
+    fs::write(certs.join("server.key"), server_key).unwrap();
+
+    let config = auth_config(
+        tmp.path(),
+        serde_json::json!({"tls": {"cert": "certs/server.pem", "key": "certs/server.key", "client_ca": "certs/ca.pem"}}),
+    );
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    tokio::spawn(transport::serve(config, Transport::Tcp(addr)));
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_slice(ca_pem.as_bytes()).unwrap()).unwrap();
+    let builder = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    let with_cert = builder
+        .clone()
+        .with_client_auth_cert(
+            vec![CertificateDer::from_pem_slice(client_cert.as_bytes()).unwrap()],
+            PrivateKeyDer::from_pem_slice(client_key.as_bytes()).unwrap(),
+        )
+        .unwrap();
+    let without_cert = builder.with_no_client_auth();
+
+    let connect = |client_config: rustls::ClientConfig| async move {
+        let mut attempts = 0;
+        let tcp = loop {
+            match tokio::net::TcpStream::connect(addr).await {
+                Ok(tcp) => break tcp,
+                Err(_) if attempts < 100 => {
+                    attempts += 1;
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                }
+                Err(e) => panic!("server did not start: {e}"),
+            }
+        };
+        tokio_rustls::TlsConnector::from(Arc::new(client_config))
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+    };
+
+    let stream = connect(with_cert).await.unwrap();
+    let responses = exchange(stream, &[initialize(None), list_caches()]).await;
+    assert!(responses[0].get("error").is_none(), "{}", responses[0]);
+    assert_eq!(listed_caches(&responses[1]), ["hr"]);
+
+    // Without a client certificate the server ends the handshake; no
+    // request is ever answered.
+    if let Ok(stream) = connect(without_cert).await {
+        assert!(exchange(stream, &[initialize(None)]).await.is_empty());
+    }
+}
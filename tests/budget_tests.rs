@@ -0,0 +1,115 @@
+//! Tests for `budget` given as a context-window share or a model profile.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use mcp_context_server::config::ServerConfig;
+use mcp_context_server::handlers;
+use mcp_context_server::protocol::{Budget, ResolveContextParams};
+
+use common::{build_cache_with, config_file};
+
+fn config_with_profiles(root: &Path) -> ServerConfig {
+    let cache_root = root.join("caches");
+    build_cache_with(&cache_root.join("docs"), &[("guide.md", "Deployment guide")]);
+    config_file(
+        root,
+        &cache_root,
+        serde_json::json!({
+            "profiles": {
+                "large": { "model_window": 200000, "fraction": 0.25, "reserve": 4000 },
+                "small": { "model_window": 8192 }
+            }
+        }),
+    )
+}
+
+async fn resolve(config: &ServerConfig, budget: serde_json::Value) -> serde_json::Value {
+    let params = ResolveContextParams {
+        cache: "docs".to_string(),
+        query: "deployment".to_string(),
+        budget: serde_json::from_value(budget).unwrap(),
+        ..Default::default()
+    };
+    let result = handlers::resolve_context::handle(params, config).await;
+    serde_json::from_str(&result.content[0].text).unwrap()
+}
+
+#[tokio::test]
+async fn window_budget_is_computed_and_echoed() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = config_with_profiles(tmp.path());
+
+    let value = resolve(
+        &config,
+        serde_json::json!({ "model_window": 200000, "fraction": 0.25, "reserve": 4000 }),
+    )
+    .await;
+    // floor((200000 - 4000) * 0.25)
+    assert_eq!(value["selection"]["budget"].as_u64().unwrap(), 49000);
+
+    let value = resolve(&config, serde_json::json!({ "model_window": 1000 })).await;
+    assert_eq!(value["selection"]["budget"].as_u64().unwrap(), 1000);
+}
+
+#[tokio::test]
+async fn profile_budget_uses_config() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = config_with_profiles(tmp.path());
+
+    let value = resolve(&config, serde_json::json!("large")).await;
+    assert_eq!(value["selection"]["budget"].as_u64().unwrap(), 49000);
+
+    let value = resolve(&config, serde_json::json!("small")).await;
+    assert_eq!(value["selection"]["budget"].as_u64().unwrap(), 8192);
+}
+
+#[tokio::test]
+async fn nonsensical_budgets_are_invalid_budget() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = config_with_profiles(tmp.path());
+
+    let cases = [
+        serde_json::json!(-1),
+        serde_json::json!(1.5),
+        serde_json::json!(true),
+        serde_json::json!("no-such-profile"),
+        serde_json::json!({ "model_window": 0 }),
+        serde_json::json!({ "model_window": -100 }),
+        serde_json::json!({ "model_window": 1000, "fraction": 0 }),
+        serde_json::json!({ "model_window": 1000, "fraction": 1.5 }),
+        serde_json::json!({ "model_window": 1000, "reserve": -1 }),
+        serde_json::json!({ "model_window": 1000, "reserve": 1000 }),
+        serde_json::json!({ "model_window": 1000, "tokens": 5 }),
+        serde_json::json!({ "fraction": 0.5 }),
+    ];
+
+    for budget in cases {
+        let value = resolve(&config, budget.clone()).await;
+        assert_eq!(
+            value["error"]["code"], "invalid_budget",
+            "budget {budget} should be invalid_budget"
+        );
+    }
+}
+
+#[test]
+fn config_rejects_unusable_profile() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config_path = tmp.path().join("config.json");
+    fs::write(
+        &config_path,
+        r#"{ "profiles": { "broken": { "model_window": 100, "reserve": 200 } } }"#,
+    )
+    .unwrap();
+
+    assert!(ServerConfig::default().with_config_file(&config_path).is_err());
+}
+
+#[test]
+fn integer_budget_still_deserializes_as_tokens() {
+    let budget: Budget = serde_json::from_str("4000").unwrap();
+    assert_eq!(budget, Budget::Tokens(4000));
+}
@@ -0,0 +1,225 @@
+//! Tests for command-line parsing and the one-shot tool subcommands.
+
+mod common;
+
+use std::path::Path;
+
+use clap::Parser;
+use mcp_context_server::cli::{self, Cli, Command, TransportKind};
+use mcp_context_server::config::ServerConfig;
+use mcp_context_server::handlers;
+use mcp_context_server::transport::{self, Transport};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use common::build_test_cache;
+
+#[test]
+fn no_subcommand_serves_stdio() {
+    let cli = Cli::try_parse_from(["mcp-context-server", "--cache-root", "/tmp/caches"]).unwrap();
+    assert!(cli.command.is_none(), "No subcommand should fall back to serving stdio");
+
+    let config = cli.config.to_config().unwrap();
+    assert_eq!(config.cache_root, Path::new("/tmp/caches"));
+}
+
+#[test]
+fn flags_mirror_environment_variables() {
+    let cli = Cli::try_parse_from([
+        "mcp-context-server",
+        "list",
+        "--cache-root",
+        "/srv/caches",
+        "--tool-timeout-secs",
+        "7",
+    ])
+    .unwrap();
+
+    let config = cli.config.to_config().unwrap();
+    assert_eq!(config.cache_root, Path::new("/srv/caches"));
+    assert_eq!(config.tool_timeout.as_secs(), 7);
+}
+
+#[test]
+fn serve_transport_selection() {
+    let cli = Cli::try_parse_from([
+        "mcp-context-server",
+        "serve",
+        "--transport",
+        "tcp",
+        "--listen",
+        "127.0.0.1:7400",
+    ])
+    .unwrap();
+
+    let Some(Command::Serve(args)) = cli.command else {
+        panic!("expected serve subcommand");
+    };
+    assert_eq!(args.transport, TransportKind::Tcp);
+    assert_eq!(
+        args.to_transport().unwrap(),
+        Transport::Tcp("127.0.0.1:7400".parse().unwrap())
+    );
+
+    let cli = Cli::try_parse_from(["mcp-context-server", "serve", "--transport", "tcp"]).unwrap();
+    let Some(Command::Serve(args)) = cli.command else {
+        panic!("expected serve subcommand");
+    };
+    assert!(args.to_transport().is_err(), "Socket transports require --listen");
+}
+
+#[tokio::test]
+async fn one_shot_matches_handler_output() {
+    let tmp = tempfile::tempdir().unwrap();
+    build_test_cache(&tmp.path().join("cli-cache"));
+
+    let cli = Cli::try_parse_from([
+        "mcp-context-server",
+        "--cache-root",
+        tmp.path().to_str().unwrap(),
+        "inspect",
+        "--cache",
+        "cli-cache",
+    ])
+    .unwrap();
+    let config = cli.config.to_config().unwrap();
+
+    let command = cli.command.unwrap();
+    let from_cli = cli::one_shot(&command, &config).await;
+    let from_handler = handlers::inspect_cache::handle(
+        mcp_context_server::protocol::InspectCacheParams {
+            cache: "cli-cache".to_string(),
+        },
+        &config,
+    )
+    .await;
+
+    assert!(!from_cli.is_error);
+    assert_eq!(from_cli.content[0].text, from_handler.content[0].text);
+}
+
+#[tokio::test]
+async fn one_shot_negative_budget_is_invalid_budget() {
+    let tmp = tempfile::tempdir().unwrap();
+    build_test_cache(&tmp.path().join("cli-cache"));
+
+    let cli = Cli::try_parse_from([
+        "mcp-context-server",
+        "--cache-root",
+        tmp.path().to_str().unwrap(),
+        "resolve",
+        "--cache",
+        "cli-cache",
+        "--query",
+        "alpha",
+        "--budget",
+        "-1",
+    ])
+    .unwrap();
+    let config = cli.config.to_config().unwrap();
+
+    let result = cli::one_shot(&cli.command.unwrap(), &config).await;
+    assert!(result.is_error);
+
+    let err: serde_json::Value = serde_json::from_str(&result.content[0].text).unwrap();
+    assert_eq!(err["error"]["code"].as_str().unwrap(), "invalid_budget");
+}
+
+#[tokio::test]
+async fn one_shot_accepts_window_budget() {
+    let tmp = tempfile::tempdir().unwrap();
+    build_test_cache(&tmp.path().join("cli-cache"));
+
+    let cli = Cli::try_parse_from([
+        "mcp-context-server",
+        "--cache-root",
+        tmp.path().to_str().unwrap(),
+        "resolve",
+        "--cache",
+        "cli-cache",
+        "--query",
+        "alpha",
+        "--budget",
+        r#"{"model_window": 8000, "fraction": 0.5}"#,
+    ])
+    .unwrap();
+    let config = cli.config.to_config().unwrap();
+
+    let result = cli::one_shot(&cli.command.unwrap(), &config).await;
+    assert!(!result.is_error, "{}", result.content[0].text);
+
+    let value: serde_json::Value = serde_json::from_str(&result.content[0].text).unwrap();
+    assert_eq!(value["selection"]["budget"].as_u64().unwrap(), 4000);
+}
+
+#[test]
+fn check_config_rejects_missing_root() {
+    let tmp = tempfile::tempdir().unwrap();
+    let missing = tmp.path().join("missing");
+
+    let cli = Cli::try_parse_from([
+        "mcp-context-server",
+        "--cache-root",
+        missing.to_str().unwrap(),
+        "check-config",
+    ])
+    .unwrap();
+
+    let config = cli.config.to_config().unwrap();
+    assert!(config.validate().is_err());
+
+    let config = cli::Cli::try_parse_from([
+        "mcp-context-server",
+        "--cache-root",
+        tmp.path().to_str().unwrap(),
+    ])
+    .unwrap()
+    .config
+    .to_config()
+    .unwrap();
+    assert!(config.validate().is_ok());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_socket_replaces_only_a_stale_socket() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = ServerConfig {
+        cache_root: tmp.path().to_path_buf(),
+        ..Default::default()
+    };
+
+    // Anything but a socket is left alone.
+    let file = tmp.path().join("notes.sock");
+    std::fs::write(&file, "notes").unwrap();
+    let err = transport::serve(config.clone(), Transport::Unix(file.clone())).await.unwrap_err();
+    assert!(err.to_string().contains("is not a socket"), "{err}");
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "notes");
+
+    // A socket left behind by a server that exited is replaced.
+    let path = tmp.path().join("server.sock");
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    tokio::spawn(transport::serve(config.clone(), Transport::Unix(path.clone())));
+    let mut attempts = 0;
+    let stream = loop {
+        match tokio::net::UnixStream::connect(&path).await {
+            Ok(stream) => break stream,
+            Err(_) if attempts < 100 => {
+                attempts += 1;
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            Err(e) => panic!("cannot connect: {e}"),
+        }
+    };
+    let (read, mut write) = stream.into_split();
+    write
+        .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"initialize\",\"params\":{}}\n")
+        .await
+        .unwrap();
+    let line = BufReader::new(read).lines().next_line().await.unwrap().unwrap();
+    let response: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(response["result"]["serverInfo"]["name"], "mcp-context-server");
+
+    // A socket a server still listens on is not.
+    let err = transport::serve(config, Transport::Unix(path)).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse, "{err}");
+}
@@ -0,0 +1,72 @@
+//! Fixtures shared by the integration tests: caches built from in-memory
+//! files, and server configs loaded from a written config file.
+
+// Each test binary uses only some of these.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use context_core::cache::{CacheBuildConfig, CacheBuilder};
+use context_core::document::{Document, DocumentId, Metadata};
+use mcp_context_server::config::ServerConfig;
+
+/// Build a cache at `cache_dir` from `(path, content)` pairs.
+pub fn build_cache_with(cache_dir: &Path, files: &[(&str, &str)]) {
+    let root = Path::new("/test");
+    let docs = files
+        .iter()
+        .map(|(path, content)| {
+            Document::ingest(
+                DocumentId::from_path(root, &root.join(path)).unwrap(),
+                path.to_string(),
+                content.as_bytes().to_vec(),
+                Metadata::default(),
+            )
+            .unwrap()
+        })
+        .collect();
+
+    let builder = CacheBuilder::new(CacheBuildConfig::v0());
+    builder.build(docs, cache_dir).unwrap();
+}
+
+/// Build a cache holding `docs/alpha.md` and `docs/beta.md`.
+pub fn build_test_cache(cache_dir: &Path) {
+    build_cache_with(
+        cache_dir,
+        &[
+            ("docs/alpha.md", "Alpha document content for testing"),
+            ("docs/beta.md", "Beta document content for testing"),
+        ],
+    );
+}
+
+/// A config serving the caches under `cache_root`, without a config file.
+pub fn test_config(cache_root: &Path) -> ServerConfig {
+    ServerConfig {
+        cache_root: cache_root.to_path_buf(),
+        tool_timeout: Duration::from_secs(30),
+        ..Default::default()
+    }
+}
+
+/// Write `config` to `dir/config.json` and return its path.
+pub fn write_config(dir: &Path, config: serde_json::Value) -> PathBuf {
+    let path = dir.join("config.json");
+    fs::write(&path, serde_json::to_string(&config).unwrap()).unwrap();
+    path
+}
+
+/// A config serving the caches under `cache_root` with `config` loaded as
+/// `dir/config.json`.
+pub fn config_file(dir: &Path, cache_root: &Path, config: serde_json::Value) -> ServerConfig {
+    let path = write_config(dir, config);
+    ServerConfig {
+        cache_root: cache_root.to_path_buf(),
+        ..Default::default()
+    }
+    .with_config_file(&path)
+    .unwrap()
+}
@@ -0,0 +1,73 @@
+//! Tests for near-duplicate suppression over a ranking.
+
+use mcp_context_server::dedup::{self, MatchKind};
+use mcp_context_server::selection::Candidate;
+
+const RUNBOOK: &str = "Deploy the cache service by running the deploy script on the primary host, \
+then verify the health endpoint reports ready before routing traffic to the new release";
+
+/// Ids of the documents `dedup` keeps, in rank order.
+fn kept<'a>(ranked: &'a [Candidate], duplicates: &[dedup::Duplicate]) -> Vec<&'a str> {
+    ranked
+        .iter()
+        .map(|c| c.id.as_str())
+        .filter(|id| !duplicates.iter().any(|d| d.id == *id))
+        .collect()
+}
+
+fn candidate(id: &str, version: &str, content: &str) -> Candidate {
+    Candidate {
+        id: id.to_string(),
+        version: version.to_string(),
+        content: content.to_string(),
+        score: 0.1,
+        tokens: content.chars().count().div_ceil(4),
+        why: serde_json::json!({"query_terms": ["deploy"]}),
+    }
+}
+
+#[test]
+fn identical_hash_keeps_higher_ranked_copy() {
+    let ranked = vec![
+        candidate("confluence/runbook.md", "sha256:aa", RUNBOOK),
+        candidate("other.md", "sha256:bb", "Unrelated notes about deploy windows."),
+        candidate("notion/runbook.md", "sha256:aa", RUNBOOK),
+    ];
+
+    let duplicates = dedup::dedup(&ranked);
+    assert_eq!(kept(&ranked, &duplicates), ["confluence/runbook.md", "other.md"]);
+
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].id, "notion/runbook.md");
+    assert_eq!(duplicates[0].duplicate_of, "confluence/runbook.md");
+    assert_eq!(duplicates[0].match_kind, MatchKind::IdenticalHash);
+    assert_eq!(duplicates[0].similarity, 1.0);
+}
+
+#[test]
+fn similar_shingles_are_duplicates() {
+    let edited = format!("{RUNBOOK}.");
+    let ranked = vec![
+        candidate("a.md", "sha256:aa", RUNBOOK),
+        candidate("b.md", "sha256:bb", &edited),
+    ];
+
+    let duplicates = dedup::dedup(&ranked);
+    assert_eq!(kept(&ranked, &duplicates), ["a.md"]);
+    assert_eq!(duplicates[0].match_kind, MatchKind::Similar);
+    assert!(duplicates[0].similarity >= dedup::SIMILARITY_THRESHOLD);
+}
+
+#[test]
+fn distinct_and_empty_documents_are_kept() {
+    let ranked = vec![
+        candidate("a.md", "sha256:aa", RUNBOOK),
+        candidate("b.md", "sha256:bb", "Rotate the signing keys every quarter."),
+        candidate("empty-1.md", "sha256:cc", ""),
+        candidate("empty-2.md", "sha256:dd", ""),
+    ];
+
+    let duplicates = dedup::dedup(&ranked);
+    assert_eq!(kept(&ranked, &duplicates).len(), 4);
+    assert!(duplicates.is_empty());
+}
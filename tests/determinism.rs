@@ -7,6 +7,7 @@
 //! from context selection MUST be byte-identical across:
 //! - multiple runs
 //! - cache rebuilds
+//! - resolving through the inverted index instead of a full scan
 
 use std::path::Path;
 
@@ -14,6 +15,10 @@ use context_core::cache::{CacheBuildConfig, CacheBuilder, ContextCache, CacheMan
 use context_core::document::{Document, DocumentId, Metadata};
 use context_core::selection::ContextSelector;
 use context_core::types::Query;
+use mcp_context_server::config::ServerConfig;
+use mcp_context_server::handlers;
+use mcp_context_server::index::INDEX_FILE;
+use mcp_context_server::protocol::{InspectCacheParams, ResolveContextParams};
 
 /// Build fixture documents with known, stable content.
 fn fixture_documents(root: &Path) -> Vec<Document> {
@@ -214,3 +219,131 @@ fn zero_budget_returns_empty_documents_deterministically() {
     let docs = value.get("documents").unwrap().as_array().unwrap();
     assert!(docs.is_empty(), "Zero budget should select no documents");
 }
+
+#[tokio::test]
+async fn plain_queries_resolve_like_context_core() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("source");
+    std::fs::create_dir_all(&root).unwrap();
+    let cache_root = tmp.path().join("caches");
+    let cache = build_cache(&cache_root.join("cache"), &root);
+
+    // Signs and quotes that do not form operators or phrases are left to
+    // context-core.
+    for query in ["-", "+", "- foo", "foo\"bar", "--verbose", "deployment \"guide"] {
+        let resolved = resolve_via_handler(&cache_root, false, query, 4096).await;
+        assert_eq!(resolved, format!("{}\n", select_to_json(&cache, query, 4096)), "Query {query:?}");
+    }
+}
+
+// -----------------------------------------------------------------------
+// Inverted index
+// -----------------------------------------------------------------------
+
+/// Resolve through the MCP handler, with the inverted index enabled for
+/// every cache (`index_min_documents: 0`) or disabled.
+async fn resolve_via_handler(cache_root: &Path, indexed: bool, query: &str, budget: i64) -> String {
+    let config = ServerConfig {
+        cache_root: cache_root.to_path_buf(),
+        index_min_documents: if indexed { 0 } else { usize::MAX },
+        ..Default::default()
+    };
+    let params = ResolveContextParams {
+        cache: "cache".to_string(),
+        query: query.to_string(),
+        budget: budget.into(),
+        ..Default::default()
+    };
+    let result = handlers::resolve_context::handle(params, &config).await;
+    assert!(!result.is_error, "Resolve should succeed: {}", result.content[0].text);
+    result.content[0].text.clone()
+}
+
+async fn inspect_via_handler(cache_root: &Path) -> String {
+    let config = ServerConfig {
+        cache_root: cache_root.to_path_buf(),
+        ..Default::default()
+    };
+    let params = InspectCacheParams {
+        cache: "cache".to_string(),
+    };
+    let result = handlers::inspect_cache::handle(params, &config).await;
+    assert!(!result.is_error, "Inspect should succeed: {}", result.content[0].text);
+    result.content[0].text.clone()
+}
+
+const INDEX_CASES: &[(&str, i64)] = &[
+    ("deployment", 4096),
+    ("deployment", 20),
+    ("api authentication", 2048),
+    ("API Cache", 40),
+    ("architecture pipeline", 1024),
+    ("quickstart", 30),
+    ("nonexistent topic", 4096),
+    ("nonexistent topic", 25),
+    ("", 100),
+    ("deployment", 0),
+];
+
+#[tokio::test]
+async fn indexed_resolve_matches_full_scan() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("source");
+    std::fs::create_dir_all(&root).unwrap();
+    let cache_root = tmp.path().join("caches");
+    build_cache(&cache_root.join("cache"), &root);
+    let inspected = inspect_via_handler(&cache_root).await;
+
+    for (query, budget) in INDEX_CASES {
+        let full = resolve_via_handler(&cache_root, false, query, *budget).await;
+        // First indexed call builds the index; the second loads it.
+        let built = resolve_via_handler(&cache_root, true, query, *budget).await;
+        let loaded = resolve_via_handler(&cache_root, true, query, *budget).await;
+        assert_eq!(full, built, "Query {query:?} budget {budget}: index build changed output");
+        assert_eq!(full, loaded, "Query {query:?} budget {budget}: loaded index changed output");
+    }
+    assert!(cache_root.join("cache").join(INDEX_FILE).is_file());
+    // The index and leftover temporary files are not part of the cache as
+    // inspect reports it.
+    std::fs::write(cache_root.join("cache").join(format!("{INDEX_FILE}.1.0.tmp")), b"{").unwrap();
+    assert_eq!(inspect_via_handler(&cache_root).await, inspected);
+}
+
+#[tokio::test]
+async fn stale_or_corrupt_index_is_rebuilt() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("source");
+    std::fs::create_dir_all(&root).unwrap();
+    let cache_root = tmp.path().join("caches");
+    let cache_dir = cache_root.join("cache");
+    build_cache(&cache_dir, &root);
+    resolve_via_handler(&cache_root, true, "deployment", 4096).await;
+
+    // Rebuild the cache in place with different documents, keeping the
+    // old index.
+    let stale = std::fs::read(cache_dir.join(INDEX_FILE)).unwrap();
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+    let docs = vec![Document::ingest(
+        DocumentId::from_path(&root, &root.join("docs/new.md")).unwrap(),
+        "docs/new.md".to_string(),
+        b"Deployment notes for the new release".to_vec(),
+        Metadata::new(),
+    )
+    .unwrap()];
+    CacheBuilder::new(CacheBuildConfig::v0()).build(docs, &cache_dir).unwrap();
+    std::fs::write(cache_dir.join(INDEX_FILE), &stale).unwrap();
+
+    let full = resolve_via_handler(&cache_root, false, "deployment", 4096).await;
+    assert_eq!(full, resolve_via_handler(&cache_root, true, "deployment", 4096).await);
+
+    // The stale index was replaced by one for the rebuilt cache.
+    let rebuilt = std::fs::read(cache_dir.join(INDEX_FILE)).unwrap();
+    assert_ne!(stale, rebuilt);
+    let index: serde_json::Value = serde_json::from_slice(&rebuilt).unwrap();
+    assert_eq!(index["cache_version"], load_cache(&cache_dir).manifest.cache_version);
+
+    // A corrupt index is rebuilt too.
+    std::fs::write(cache_dir.join(INDEX_FILE), b"{\"format_version\": 1").unwrap();
+    assert_eq!(full, resolve_via_handler(&cache_root, true, "deployment", 4096).await);
+    assert_eq!(rebuilt, std::fs::read(cache_dir.join(INDEX_FILE)).unwrap());
+}
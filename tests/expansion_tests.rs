@@ -0,0 +1,79 @@
+//! Tests for query expansion: dictionary loading, expansion order, and the
+//! `query_terms` reported for expanded queries.
+
+use std::fs;
+use std::path::Path;
+
+use mcp_context_server::expansion::{self, Dictionary};
+use mcp_context_server::query::{self, Operator};
+
+fn dictionary(dir: &Path, json: serde_json::Value) -> Dictionary {
+    let path = dir.join("dictionary.json");
+    fs::write(&path, json.to_string()).unwrap();
+    Dictionary::load(&path).unwrap()
+}
+
+fn clauses(query: &str, dictionary: &Dictionary) -> Vec<(String, Operator, Option<String>)> {
+    expansion::expand(&query::parse(query).unwrap(), dictionary)
+        .clauses
+        .into_iter()
+        .map(|c| (c.words.join(" "), c.operator, c.expanded_from))
+        .collect()
+}
+
+#[test]
+fn synonyms_and_acronyms_expand_in_dictionary_order() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dict = dictionary(
+        tmp.path(),
+        serde_json::json!({
+            "synonyms": [["K8s", "Kubernetes", "kube"]],
+            "acronyms": {"SLA": ["Service Level Agreement", "service-level agreement"]}
+        }),
+    );
+
+    let expanded = |from: &str, to: &str| (to.to_string(), Operator::Optional, Some(from.to_string()));
+    assert_eq!(
+        clauses("+sla k8s -kube", &dict),
+        [
+            ("sla".to_string(), Operator::Required, None),
+            ("k8s".to_string(), Operator::Optional, None),
+            ("kube".to_string(), Operator::Excluded, None),
+            expanded("sla", "service level agreement"),
+            expanded("sla", "service-level agreement"),
+            expanded("k8s", "kubernetes"),
+        ]
+    );
+
+    // Acronyms expand one way only.
+    assert_eq!(clauses("\"service level agreement\"", &dict).len(), 1);
+}
+
+#[test]
+fn query_terms_mark_original_and_expanded_words() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dict = dictionary(tmp.path(), serde_json::json!({"acronyms": {"sla": ["service level"]}}));
+
+    let plain = query::parse("uptime").unwrap();
+    assert_eq!(expansion::query_terms(&expansion::expand(&plain, &dict)), serde_json::json!(["uptime"]));
+
+    let expanded = expansion::expand(&query::parse("sla uptime").unwrap(), &dict);
+    assert!(expanded.is_expanded());
+    assert_eq!(
+        expansion::query_terms(&expanded),
+        serde_json::json!([
+            {"term": "sla", "kind": "original"},
+            {"term": "uptime", "kind": "original"},
+            {"term": "service", "kind": "expanded", "expanded_from": "sla"},
+            {"term": "level", "kind": "expanded", "expanded_from": "sla"},
+        ])
+    );
+}
+
+#[test]
+fn malformed_dictionary_is_rejected() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("dictionary.json");
+    fs::write(&path, r#"{"synonym": [["a", "b"]]}"#).unwrap();
+    assert!(Dictionary::load(&path).is_err());
+}
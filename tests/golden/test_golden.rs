@@ -8,7 +8,7 @@ use context_core::selection::ContextSelector;
 use context_core::types::Query;
 use mcp_context_server::config::ServerConfig;
 use mcp_context_server::handlers;
-use mcp_context_server::protocol::{JsonRpcRequest, RpcId};
+use mcp_context_server::protocol::{JsonRpcRequest, ResolveContextParams, RpcId};
 use serde_json::json;
 use sha2::{Digest, Sha256};
 use tempfile::tempdir;
@@ -211,11 +211,41 @@ fn golden_ordering_output() {
     assert_eq!(json, expected, "ordering output does not match golden");
 }
 
+#[tokio::test]
+async fn golden_explain_output() {
+    let config = ServerConfig {
+        cache_root: fixtures_root(),
+        tool_timeout: std::time::Duration::from_secs(5),
+        ..Default::default()
+    };
+
+    // A budget of 8 drops deployment.md (10 tokens) so the explain block
+    // has an excluded document.
+    let params = ResolveContextParams {
+        cache: "cache_valid".into(),
+        query: "deployment".into(),
+        budget: 8.into(),
+        explain: true,
+        ..Default::default()
+    };
+
+    let result = handlers::resolve_context::handle(params, &config).await;
+    assert!(!result.is_error, "explain resolve failed: {}", result.content[0].text);
+
+    let expected = read_expected("resolve_explain.json");
+    assert_eq!(
+        result.content[0].text.trim_end(),
+        expected,
+        "explain output does not match golden"
+    );
+}
+
 #[tokio::test]
 async fn golden_mcp_success_response() {
     let config = ServerConfig {
         cache_root: fixtures_root(),
         tool_timeout: std::time::Duration::from_secs(5),
+        ..Default::default()
     };
 
     let req = JsonRpcRequest {
@@ -244,6 +274,7 @@ async fn golden_mcp_error_cache_missing() {
     let config = ServerConfig {
         cache_root: fixtures_root(),
         tool_timeout: std::time::Duration::from_secs(5),
+        ..Default::default()
     };
 
     let req = JsonRpcRequest {
@@ -270,6 +301,7 @@ async fn golden_mcp_error_cache_invalid() {
     let config = ServerConfig {
         cache_root: fixtures_root(),
         tool_timeout: std::time::Duration::from_secs(5),
+        ..Default::default()
     };
 
     let req = JsonRpcRequest {
@@ -298,6 +330,7 @@ async fn golden_list_caches_output() {
     let config = ServerConfig {
         cache_root: fixtures_root().join("cache_root"),
         tool_timeout: std::time::Duration::from_secs(5),
+        ..Default::default()
     };
 
     let result = handlers::list_caches::handle(&config).await;
@@ -312,6 +345,7 @@ async fn golden_inspect_cache_outputs() {
     let config = ServerConfig {
         cache_root: fixtures_root(),
         tool_timeout: std::time::Duration::from_secs(5),
+        ..Default::default()
     };
 
     let valid_result = handlers::inspect_cache::handle(
@@ -377,11 +411,13 @@ async fn golden_end_to_end_determinism() {
     let config1 = ServerConfig {
         cache_root: cache_root1.to_path_buf(),
         tool_timeout: std::time::Duration::from_secs(5),
+        ..Default::default()
     };
 
     let config2 = ServerConfig {
         cache_root: cache_root2.to_path_buf(),
         tool_timeout: std::time::Duration::from_secs(5),
+        ..Default::default()
     };
 
     let resp1 = handlers::dispatch(&req, &config1).await.expect("missing response");
@@ -411,6 +447,7 @@ async fn golden_permission_denied_io_error() {
     let config = ServerConfig {
         cache_root: dir.path().to_path_buf(),
         tool_timeout: std::time::Duration::from_secs(5),
+        ..Default::default()
     };
 
     let req = JsonRpcRequest {
@@ -0,0 +1,196 @@
+//! Tests for hybrid scoring: embedder config, embeddings files, and the
+//! fused ranking of `context.resolve` with `"scoring": "hybrid"`.
+//!
+//! The embedder is a shell command that ignores its input and prints a fixed
+//! query vector, so results depend only on the fixtures.
+#![cfg(feature = "hybrid")]
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use mcp_context_server::config::ServerConfig;
+use mcp_context_server::handlers;
+use mcp_context_server::protocol::{JsonRpcRequest, RpcId};
+
+use common::{build_cache_with, write_config};
+
+const QUERY_VECTOR: &str = "[1, 0, 0]";
+
+/// The `docs` cache: one document per embedding axis.
+const DOCS: &[(&str, &str)] = &[
+    ("alpha.md", "Redis keeps rate limit counters."),
+    ("beta.md", "Throttling protects the service from bursts."),
+    ("gamma.md", "Sessions are cached for an hour."),
+];
+
+/// Write `embeddings.json` with a vector per document id, tagged with the
+/// document's current version unless `stale` lists the id.
+fn write_embeddings(cache_dir: &Path, model: &str, vectors: &[(&str, [f32; 3])], stale: &[&str]) {
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(cache_dir.join("manifest.json")).unwrap()).unwrap();
+    let mut documents = serde_json::Map::new();
+    for doc in manifest["documents"].as_array().unwrap() {
+        let id = doc["id"].as_str().unwrap();
+        let Some((_, vector)) = vectors.iter().find(|(v, _)| *v == id) else {
+            continue;
+        };
+        let version = if stale.contains(&id) {
+            "sha256:0000".to_string()
+        } else {
+            doc["version"].as_str().unwrap().to_string()
+        };
+        documents.insert(id.to_string(), serde_json::json!({"version": version, "vector": vector}));
+    }
+    let embeddings = serde_json::json!({"model": model, "dimensions": 3, "documents": documents});
+    fs::write(cache_dir.join("embeddings.json"), embeddings.to_string()).unwrap();
+}
+
+fn hybrid_config(root: &Path, embedder_model: &str) -> ServerConfig {
+    let script = format!("cat > /dev/null; echo '{QUERY_VECTOR}'");
+    let config_path = write_config(
+        root,
+        serde_json::json!({
+            "embedders": {
+                "fixed": { "format": "command", "command": ["sh", "-c", script], "model": embedder_model }
+            },
+            "caches": { "docs": { "embedder": "fixed" } }
+        }),
+    );
+    ServerConfig {
+        cache_root: root.to_path_buf(),
+        ..Default::default()
+    }
+    .with_config_file(&config_path)
+    .unwrap()
+}
+
+async fn resolve(config: &ServerConfig, arguments: serde_json::Value) -> serde_json::Value {
+    let req = JsonRpcRequest {
+        jsonrpc: "2.0".into(),
+        id: Some(RpcId::Number(1)),
+        method: "tools/call".into(),
+        params: Some(serde_json::json!({"name": "context.resolve", "arguments": arguments})),
+    };
+    let response = handlers::dispatch(&req, config).await.unwrap();
+    response.result.unwrap()
+}
+
+fn text_json(result: &serde_json::Value) -> serde_json::Value {
+    serde_json::from_str(result["content"][0]["text"].as_str().unwrap()).unwrap()
+}
+
+fn hybrid_arguments() -> serde_json::Value {
+    serde_json::json!({
+        "cache": "docs", "query": "rate limit", "budget": 1000, "scoring": "hybrid", "explain": true
+    })
+}
+
+#[tokio::test]
+async fn hybrid_fuses_lexical_and_semantic_scores() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let cache_dir = root.join("docs");
+    build_cache_with(&cache_dir, DOCS);
+    write_embeddings(
+        &cache_dir,
+        "test-model",
+        &[
+            ("alpha.md", [0.6, 0.8, 0.0]),
+            ("beta.md", [1.0, 0.0, 0.0]),
+            ("gamma.md", [-1.0, 0.0, 0.0]),
+        ],
+        &[],
+    );
+    let config = hybrid_config(root, "test-model");
+
+    let first = resolve(&config, hybrid_arguments()).await;
+    assert!(first.get("isError").is_none(), "Resolve should succeed: {first}");
+    let second = resolve(&config, hybrid_arguments()).await;
+    assert_eq!(first, second);
+
+    let value = text_json(&first);
+    assert_eq!(value["selection"]["scoring"], "hybrid");
+    assert!(value["explain"]["score_formula"].as_str().unwrap().contains("cosine"));
+
+    // beta.md has no query words but the closest vector; gamma.md points
+    // away from the query and is clamped to zero.
+    let documents = value["documents"].as_array().unwrap();
+    let ranking: Vec<(&str, f64)> = documents
+        .iter()
+        .map(|d| (d["id"].as_str().unwrap(), d["score"].as_f64().unwrap()))
+        .collect();
+    assert_eq!(ranking, [("alpha.md", 0.8), ("beta.md", 0.5), ("gamma.md", 0.0)]);
+    assert_eq!(documents[0]["why"]["lexical_score"].as_f64().unwrap(), 1.0);
+    assert_eq!(documents[0]["why"]["semantic_score"].as_f64().unwrap(), 0.6);
+    assert_eq!(documents[2]["why"]["semantic_score"].as_f64().unwrap(), 0.0);
+}
+
+#[tokio::test]
+async fn hybrid_ignores_vectors_of_other_versions() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let cache_dir = root.join("docs");
+    build_cache_with(&cache_dir, DOCS);
+    write_embeddings(
+        &cache_dir,
+        "test-model",
+        &[("alpha.md", [0.0, 1.0, 0.0]), ("beta.md", [1.0, 0.0, 0.0])],
+        &["beta.md"],
+    );
+    let config = hybrid_config(root, "test-model");
+
+    let value = text_json(&resolve(&config, hybrid_arguments()).await);
+    let documents = value["documents"].as_array().unwrap();
+    assert_eq!(documents[0]["id"], "alpha.md");
+    assert_eq!(documents[0]["score"].as_f64().unwrap(), 0.5);
+    for doc in &documents[1..] {
+        assert_eq!(doc["score"].as_f64().unwrap(), 0.0);
+    }
+}
+
+#[tokio::test]
+async fn hybrid_rejects_embeddings_from_another_model() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let cache_dir = root.join("docs");
+    build_cache_with(&cache_dir, DOCS);
+    write_embeddings(&cache_dir, "other-model", &[("alpha.md", [1.0, 0.0, 0.0])], &[]);
+    let config = hybrid_config(root, "test-model");
+
+    let result = resolve(&config, hybrid_arguments()).await;
+    assert_eq!(result["isError"], true);
+    let error = text_json(&result);
+    assert_eq!(error["error"]["code"], "cache_invalid");
+    assert!(!error["error"]["message"].as_str().unwrap().contains("model"));
+}
+
+#[tokio::test]
+async fn hybrid_without_embedder_is_rejected() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    build_cache_with(&root.join("docs"), DOCS);
+    let config = ServerConfig {
+        cache_root: root.to_path_buf(),
+        ..Default::default()
+    };
+
+    let result = resolve(&config, hybrid_arguments()).await;
+    assert_eq!(result["isError"], true);
+    let text = result["content"][0]["text"].as_str().unwrap();
+    assert!(text.contains("No embedder configured"));
+}
+
+#[test]
+fn config_rejects_unknown_embedder() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config_path = write_config(
+        tmp.path(),
+        serde_json::json!({"caches": {"docs": {"embedder": "missing"}}}),
+    );
+    let err = ServerConfig::default()
+        .with_config_file(&config_path)
+        .unwrap_err();
+    assert!(err.contains("unknown embedder"));
+}
@@ -0,0 +1,161 @@
+//! Tests for rate limits and the cap on concurrent selections: token
+//! buckets per client and per cache, `rate_limited` errors with a retry
+//! hint, waiting for a selection slot, and throttle counts in `health`.
+
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+
+use mcp_context_server::access::ClientIdentity;
+use mcp_context_server::config::ServerConfig;
+use mcp_context_server::handlers;
+use mcp_context_server::limits::{Limiter, LimitsConfig, ThrottleStats};
+use mcp_context_server::notifications::Notifier;
+use mcp_context_server::protocol::{JsonRpcRequest, McpErrorResponse, RpcId};
+
+use common::{build_cache_with, config_file};
+
+fn uid(uid: u32) -> ClientIdentity {
+    ClientIdentity {
+        uid: Some(uid),
+        ..Default::default()
+    }
+}
+
+fn limiter(config: serde_json::Value) -> Limiter {
+    let config: LimitsConfig = serde_json::from_value(config).unwrap();
+    Limiter::new(&config).unwrap()
+}
+
+#[test]
+fn buckets_limit_clients_and_caches() {
+    let limits = limiter(serde_json::json!({
+        "per_client": {"rate": 0.5, "burst": 2},
+        "per_cache": {"rate": 1, "burst": 3}
+    }));
+
+    // Each client gets its own burst.
+    assert!(limits.admit(&uid(1), &["docs"]).is_ok());
+    assert!(limits.admit(&uid(1), &["docs"]).is_ok());
+    let retry = limits.admit(&uid(1), &["hr"]).unwrap_err();
+    assert!(retry > Duration::from_millis(1500) && retry <= Duration::from_secs(2), "{retry:?}");
+
+    // The cache bucket is shared: client 2 takes the last `docs` token.
+    assert!(limits.admit(&uid(2), &["docs"]).is_ok());
+    let retry = limits.admit(&uid(3), &["docs", "hr"]).unwrap_err();
+    assert!(retry <= Duration::from_secs(1), "{retry:?}");
+    // A refused call takes nothing, so client 3 still has its burst.
+    assert!(limits.admit(&uid(3), &["hr"]).is_ok());
+    assert!(limits.admit(&uid(3), &[]).is_ok());
+
+    assert_eq!(
+        limits.stats(),
+        ThrottleStats {
+            client: 1,
+            cache: 1,
+            selections: 0
+        }
+    );
+
+    let invalid: LimitsConfig = serde_json::from_value(serde_json::json!({"per_cache": {"rate": 0, "burst": 1}})).unwrap();
+    assert!(Limiter::new(&invalid).is_err());
+}
+
+#[test]
+fn tiny_rates_saturate_the_retry_hint() {
+    let limits = limiter(serde_json::json!({"per_client": {"rate": 1e-300, "burst": 1}}));
+    assert!(limits.admit(&uid(1), &[]).is_ok());
+    let retry = limits.admit(&uid(1), &[]).unwrap_err();
+    assert_eq!(retry, Duration::MAX);
+    assert_eq!(McpErrorResponse::rate_limited(retry).error.retry_after_ms, Some(u64::MAX));
+}
+
+fn limits_config(root: &Path, limits: serde_json::Value) -> ServerConfig {
+    build_cache_with(&root.join("caches").join("docs"), &[("guide.md", "The deployment guide.")]);
+    config_file(root, &root.join("caches"), serde_json::json!({"limits": limits}))
+}
+
+async fn call_tool(config: &ServerConfig, name: &str, arguments: serde_json::Value) -> serde_json::Value {
+    let req = JsonRpcRequest {
+        jsonrpc: "2.0".into(),
+        id: Some(RpcId::Number(1)),
+        method: "tools/call".into(),
+        params: Some(serde_json::json!({"name": name, "arguments": arguments})),
+    };
+    let result = handlers::dispatch_with(&req, config, &Notifier::default(), &uid(1000))
+        .await
+        .unwrap()
+        .result
+        .unwrap();
+    let text = result["content"][0]["text"].as_str().unwrap();
+    serde_json::from_str(text).unwrap()
+}
+
+fn resolve_args() -> serde_json::Value {
+    serde_json::json!({"cache": "docs", "query": "deployment", "budget": 100})
+}
+
+#[tokio::test]
+async fn throttled_calls_fail_with_rate_limited() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = limits_config(tmp.path(), serde_json::json!({"per_client": {"rate": 0.01, "burst": 1}}));
+
+    let first = call_tool(&config, "context.resolve", resolve_args()).await;
+    assert!(first.get("error").is_none(), "{first}");
+    let second = call_tool(&config, "context.resolve", resolve_args()).await;
+    assert_eq!(second["error"]["code"], "rate_limited");
+    assert_eq!(second["error"]["message"], "Rate limit exceeded");
+    let retry_after = second["error"]["retry_after_ms"].as_u64().unwrap();
+    assert!(retry_after > 90_000 && retry_after <= 100_000, "{retry_after}");
+
+    // `health` is not charged, so a throttled client can still read the counts.
+    let health = call_tool(&config, "health", serde_json::json!({})).await;
+    assert_eq!(health["throttled"]["client"], 1);
+}
+
+#[tokio::test]
+async fn cache_buckets_are_charged_by_resolved_name() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = limits_config(tmp.path(), serde_json::json!({"per_cache": {"rate": 0.01, "burst": 1}}));
+
+    // Caches that do not exist are not charged.
+    for _ in 0..3 {
+        let missing = call_tool(&config, "context.resolve", serde_json::json!({"cache": "missing", "query": "x", "budget": 10})).await;
+        assert_eq!(missing["error"]["code"], "cache_missing");
+    }
+
+    let first = call_tool(&config, "context.resolve", resolve_args()).await;
+    assert!(first.get("error").is_none(), "{first}");
+    let respelled = call_tool(
+        &config,
+        "context.resolve",
+        serde_json::json!({"cache": "./docs/", "query": "deployment", "budget": 100}),
+    )
+    .await;
+    assert_eq!(respelled["error"]["code"], "rate_limited");
+}
+
+#[tokio::test]
+async fn concurrent_selections_wait_for_a_slot() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = limits_config(tmp.path(), serde_json::json!({"max_concurrent_selections": 1}));
+
+    let busy = config.limits.selection_permit().await.unwrap();
+    let waiting = tokio::spawn({
+        let config = config.clone();
+        async move { call_tool(&config, "context.resolve", resolve_args()).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!waiting.is_finished(), "the call waits while the slot is taken");
+
+    drop(busy);
+    let resolved = waiting.await.unwrap();
+    assert!(resolved.get("error").is_none(), "{resolved}");
+
+    let health = call_tool(&config, "health", serde_json::json!({})).await;
+    assert_eq!(
+        health,
+        serde_json::json!({"status": "ok", "throttled": {"client": 0, "cache": 0, "selections": 1}})
+    );
+}
@@ -0,0 +1,115 @@
+//! Tests for forwarding log events to MCP clients: `logging/setLevel` and
+//! `notifications/message`.
+
+mod common;
+
+use mcp_context_server::config::ServerConfig;
+use mcp_context_server::logging::{self, LogFormat};
+use mcp_context_server::server::McpServer;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines, ReadHalf, WriteHalf};
+
+use common::build_cache_with;
+
+/// An initialized stdio session driven one request at a time.
+struct Session {
+    lines: Lines<BufReader<ReadHalf<DuplexStream>>>,
+    write: WriteHalf<DuplexStream>,
+    next_id: u64,
+}
+
+impl Session {
+    async fn start(config: ServerConfig) -> (Self, serde_json::Value) {
+        let (client, server_io) = tokio::io::duplex(64 * 1024);
+        let (server_read, server_write) = tokio::io::split(server_io);
+        tokio::spawn(async move { McpServer::new(config).serve(server_read, server_write).await });
+        let (client_read, write) = tokio::io::split(client);
+        let mut session = Self {
+            lines: BufReader::new(client_read).lines(),
+            write,
+            next_id: 0,
+        };
+        let mut initialized = session.request("initialize", serde_json::json!({})).await;
+        let response = initialized.pop().unwrap();
+        (session, response)
+    }
+
+    /// Send a request; returns the notifications written before the
+    /// response, then the response.
+    async fn request(&mut self, method: &str, params: serde_json::Value) -> Vec<serde_json::Value> {
+        self.next_id += 1;
+        let request = serde_json::json!({"jsonrpc": "2.0", "id": self.next_id, "method": method, "params": params});
+        self.write.write_all(format!("{request}\n").as_bytes()).await.unwrap();
+        let mut messages = Vec::new();
+        loop {
+            let line = self.lines.next_line().await.unwrap().unwrap();
+            let message: serde_json::Value = serde_json::from_str(&line).unwrap();
+            let done = message["id"] == self.next_id;
+            messages.push(message);
+            if done {
+                return messages;
+            }
+        }
+    }
+
+    /// A `context.resolve` whose query fails to parse, which the server
+    /// logs as a warning.
+    async fn resolve_invalid_query(&mut self) -> Vec<serde_json::Value> {
+        self.request(
+            "tools/call",
+            serde_json::json!({
+                "name": "context.resolve",
+                "arguments": {"cache": "docs", "query": "\"\" deploy", "budget": 100}
+            }),
+        )
+        .await
+    }
+}
+
+#[tokio::test]
+async fn clients_receive_the_log_messages_they_ask_for() {
+    logging::init(LogFormat::Json).unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    build_cache_with(&tmp.path().join("docs"), &[("guide.md", "The deployment guide.")]);
+    let config = ServerConfig {
+        cache_root: tmp.path().to_path_buf(),
+        ..Default::default()
+    };
+
+    let (mut listening, initialized) = Session::start(config.clone()).await;
+    assert_eq!(initialized["result"]["capabilities"]["logging"], serde_json::json!({}));
+    let (mut quiet, _) = Session::start(config).await;
+
+    let set = listening.request("logging/setLevel", serde_json::json!({"level": "warning"})).await;
+    assert_eq!(set, [serde_json::json!({"jsonrpc": "2.0", "id": 2, "result": {}})]);
+
+    let messages = listening.resolve_invalid_query().await;
+    assert_eq!(messages.len(), 2, "{messages:?}");
+    let notification = &messages[0];
+    assert_eq!(notification["method"], "notifications/message");
+    assert_eq!(notification["params"]["level"], "warning");
+    assert_eq!(notification["params"]["logger"], "mcp_context_server::handlers::resolve_context");
+    assert_eq!(notification["params"]["data"]["message"], "Invalid query");
+    assert!(notification["params"]["data"]["error"].as_str().unwrap().contains("contains no words"));
+    assert!(messages[1]["result"]["isError"].as_bool().unwrap());
+
+    // Other sessions only get their own messages, and only after asking.
+    let messages = quiet.resolve_invalid_query().await;
+    assert_eq!(messages.len(), 1, "{messages:?}");
+
+    // Raising the level filters warnings out.
+    listening.request("logging/setLevel", serde_json::json!({"level": "critical"})).await;
+    assert_eq!(listening.resolve_invalid_query().await.len(), 1);
+
+    // Only the server's own events are forwarded, whatever the level.
+    listening.request("logging/setLevel", serde_json::json!({"level": "debug"})).await;
+    let messages = listening.resolve_invalid_query().await;
+    let (response, notifications) = messages.split_last().unwrap();
+    assert!(response["result"]["isError"].as_bool().unwrap());
+    assert!(!notifications.is_empty());
+    for message in notifications {
+        assert!(message["params"]["logger"].as_str().unwrap().starts_with("mcp_context_server::"), "{message}");
+    }
+
+    let invalid = listening.request("logging/setLevel", serde_json::json!({"level": "verbose"})).await;
+    assert_eq!(invalid.last().unwrap()["error"]["code"], -32602);
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use jsonschema::validator_for;
 use serde_json::Value;
 
@@ -35,13 +37,20 @@ fn golden_mcp_error_schema_validation() {
             "cache_invalid",
             "invalid_query",
             "invalid_budget",
+            "invalid_params",
             "io_error",
-            "internal_error"
+            "internal_error",
+            "forbidden",
+            "rate_limited"
           ]
         },
         "message": {
           "type": "string",
           "minLength": 1
+        },
+        "retry_after_ms": {
+          "type": "integer",
+          "minimum": 0
         }
       }
     }
@@ -63,4 +72,9 @@ fn golden_mcp_error_schema_validation() {
 }"#;
 
     assert_eq!(json_str.trim(), expected.trim(), "MCP error JSON snapshot mismatch");
+
+    // 5. Rate-limit errors carry a retry hint
+    let limited = serde_json::to_value(McpErrorResponse::rate_limited(Duration::from_micros(1500))).unwrap();
+    assert!(validator.is_valid(&limited), "rate_limited JSON must satisfy v0 schema");
+    assert_eq!(limited["error"]["retry_after_ms"], 2);
 }
@@ -0,0 +1,60 @@
+//! Tests for `query` parsing: phrases and `+`/`-` operators.
+
+use mcp_context_server::query::{self, Operator, QueryError};
+
+fn clauses(q: &str) -> Vec<(String, Operator, bool)> {
+    query::parse(q)
+        .unwrap()
+        .clauses
+        .into_iter()
+        .map(|c| (c.words.join(" "), c.operator, c.quoted))
+        .collect()
+}
+
+#[test]
+fn plain_terms_have_no_operators() {
+    let parsed = query::parse("Deploy  the-cache, now").unwrap();
+    assert!(!parsed.has_operators());
+    assert_eq!(parsed.positive_terms(), ["deploy", "the-cache", "now"]);
+}
+
+#[test]
+fn phrases_and_operators_parse() {
+    assert_eq!(
+        clauses(r#""Rate Limit" -deprecated +redis -"old api""#),
+        [
+            ("rate limit".to_string(), Operator::Optional, true),
+            ("deprecated".to_string(), Operator::Excluded, false),
+            ("redis".to_string(), Operator::Required, false),
+            ("old api".to_string(), Operator::Excluded, true),
+        ]
+    );
+
+    let parsed = query::parse(r#""rate limit" -deprecated +redis"#).unwrap();
+    assert!(parsed.has_operators());
+    assert_eq!(parsed.positive_terms(), ["rate", "limit", "redis"]);
+}
+
+#[test]
+fn empty_phrases_are_rejected() {
+    assert_eq!(query::parse(r#"cache "" x"#), Err(QueryError::EmptyPhrase(6)));
+    assert_eq!(query::parse(r#"cache -"  ""#), Err(QueryError::EmptyPhrase(7)));
+}
+
+#[test]
+fn stray_signs_and_quotes_are_part_of_terms() {
+    for q in ["-", "+", "- foo", "cache +", r#"foo"bar"#, "--verbose", r#"cache "rate limit"#, "+-x"] {
+        let parsed = query::parse(q).unwrap();
+        assert!(!parsed.has_operators(), "{q:?}");
+    }
+    assert_eq!(
+        clauses(r#"--verbose foo"bar "rate limit"#),
+        [
+            ("verbose".to_string(), Operator::Optional, false),
+            ("foo\"bar".to_string(), Operator::Optional, false),
+            ("rate".to_string(), Operator::Optional, false),
+            ("limit".to_string(), Operator::Optional, false),
+        ]
+    );
+    assert_eq!(query::parse("- foo").unwrap().positive_terms(), ["foo"]);
+}
@@ -0,0 +1,227 @@
+//! Tests for the response size cap: deterministic truncation that drops
+//! trailing documents, and spilled results paged back through
+//! `resources/read`.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use mcp_context_server::config::ServerConfig;
+use mcp_context_server::server::McpServer;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines, ReadHalf, WriteHalf};
+
+use common::{build_cache_with, config_file};
+
+/// Four long guides whose text needs escaping in JSON.
+fn build_guides(cache_dir: &Path) {
+    let guides: Vec<(String, String)> = (0..4)
+        .map(|i| {
+            let body = format!("Deployment step {i}: \"check\" the ünïcode\tlogs.\n").repeat(120);
+            (format!("guide-{i}.md"), body)
+        })
+        .collect();
+    let files: Vec<(&str, &str)> = guides.iter().map(|(name, body)| (name.as_str(), body.as_str())).collect();
+    build_cache_with(cache_dir, &files);
+}
+
+/// A cache root with `docs`, served with the given `responses` section.
+fn responses_config(root: &Path, responses: Option<serde_json::Value>) -> ServerConfig {
+    let caches = root.join("caches");
+    if !caches.join("docs").exists() {
+        build_guides(&caches.join("docs"));
+    }
+    match responses {
+        Some(responses) => config_file(root, &caches, serde_json::json!({"responses": responses})),
+        None => ServerConfig {
+            cache_root: caches,
+            ..Default::default()
+        },
+    }
+}
+
+/// An initialized stdio session driven one request at a time.
+struct Session {
+    lines: Lines<BufReader<ReadHalf<DuplexStream>>>,
+    write: WriteHalf<DuplexStream>,
+    next_id: u64,
+    capabilities: serde_json::Value,
+}
+
+impl Session {
+    async fn start(config: ServerConfig) -> Self {
+        let (client, server_io) = tokio::io::duplex(1024 * 1024);
+        let (server_read, server_write) = tokio::io::split(server_io);
+        tokio::spawn(async move { McpServer::new(config).serve(server_read, server_write).await });
+        let (client_read, write) = tokio::io::split(client);
+        let mut session = Self {
+            lines: BufReader::new(client_read).lines(),
+            write,
+            next_id: 0,
+            capabilities: serde_json::Value::Null,
+        };
+        let (_, initialized) = session.request("initialize", serde_json::json!({})).await;
+        session.capabilities = initialized["result"]["capabilities"].clone();
+        session
+    }
+
+    /// Send a request; returns the raw response line and its parsed form.
+    async fn request(&mut self, method: &str, params: serde_json::Value) -> (String, serde_json::Value) {
+        self.next_id += 1;
+        let request = serde_json::json!({"jsonrpc": "2.0", "id": self.next_id, "method": method, "params": params});
+        self.write.write_all(format!("{request}\n").as_bytes()).await.unwrap();
+        let line = self.lines.next_line().await.unwrap().unwrap();
+        let response = serde_json::from_str(&line).unwrap();
+        (line, response)
+    }
+
+    /// Resolve everything in `docs`; returns the response line and the
+    /// result's first content item.
+    async fn resolve_content(&mut self) -> (String, serde_json::Value) {
+        let (line, response) = self
+            .request(
+                "tools/call",
+                serde_json::json!({
+                    "name": "context.resolve",
+                    "arguments": {"cache": "docs", "query": "deployment", "budget": 100000}
+                }),
+            )
+            .await;
+        assert_eq!(response["result"]["content"].as_array().unwrap().len(), 1);
+        (line, response["result"]["content"][0].clone())
+    }
+
+    /// Like `resolve_content`, for a result returned as text.
+    async fn resolve(&mut self) -> (String, String) {
+        let (line, content) = self.resolve_content().await;
+        (line, content["text"].as_str().unwrap().to_string())
+    }
+}
+
+/// The resource a spilled result's text content item names.
+fn spilled_resource(content: &serde_json::Value) -> serde_json::Value {
+    let text: serde_json::Value = serde_json::from_str(content["text"].as_str().unwrap()).unwrap();
+    text["resource"].clone()
+}
+
+#[tokio::test]
+async fn oversized_results_drop_trailing_documents() {
+    let tmp = tempfile::tempdir().unwrap();
+    let (_, full) = Session::start(responses_config(tmp.path(), None)).await.resolve().await;
+    assert!(full.len() > 20_000, "{}", full.len());
+    let full: serde_json::Value = serde_json::from_str(&full).unwrap();
+    let documents = full["documents"].as_array().unwrap();
+
+    let config = responses_config(tmp.path(), Some(serde_json::json!({"max_bytes": 8192})));
+    let mut session = Session::start(config).await;
+    assert!(session.capabilities.get("resources").is_none());
+    let (line, text) = session.resolve().await;
+    assert!(line.len() < 8192, "{}", line.len());
+
+    // The result stays valid JSON: a prefix of the documents, marked.
+    let truncated: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let kept = truncated["documents"].as_array().unwrap();
+    assert!(!kept.is_empty() && kept.len() < documents.len(), "{}", kept.len());
+    assert_eq!(kept[..], documents[..kept.len()]);
+    assert_eq!(truncated["truncated"], true);
+    assert_eq!(truncated["documents_omitted"], documents.len() - kept.len());
+    let omitted_tokens: u64 = documents[kept.len()..].iter().map(|doc| doc["tokens"].as_u64().unwrap()).sum();
+    assert_eq!(truncated["tokens_omitted"], omitted_tokens);
+    assert_eq!(truncated["selection"], full["selection"]);
+    // Keeping one more document would not have fit.
+    assert!(line.len() + serde_json::to_string(&documents[kept.len()]).unwrap().len() > 8192);
+
+    // The same result always truncates to the same bytes.
+    let (_, again) = session.resolve().await;
+    assert_eq!(again, text);
+
+    // Small results pass through untouched.
+    let (_, list) = session.request("tools/call", serde_json::json!({"name": "context.list_caches"})).await;
+    let list: serde_json::Value = serde_json::from_str(list["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+    assert!(list.get("truncated").is_none());
+}
+
+#[tokio::test]
+async fn spilled_results_are_paged_through_resources_read() {
+    let tmp = tempfile::tempdir().unwrap();
+    let (_, full) = Session::start(responses_config(tmp.path(), None)).await.resolve().await;
+
+    let config = responses_config(tmp.path(), Some(serde_json::json!({"max_bytes": 4096, "overflow": "resource"})));
+    let mut session = Session::start(config).await;
+    assert_eq!(session.capabilities["resources"], serde_json::json!({}));
+    let (_, content) = session.resolve_content().await;
+    assert_eq!(content["type"], "text");
+    let link = spilled_resource(&content);
+    assert_eq!(link["size"], full.len());
+    assert_eq!(link["mimeType"], "text/plain");
+
+    let mut uri = link["uri"].as_str().unwrap().to_string();
+    let mut pages = Vec::new();
+    loop {
+        let (line, response) = session.request("resources/read", serde_json::json!({"uri": uri})).await;
+        assert!(line.len() < 4096, "{}", line.len());
+        pages.push(response["result"]["contents"][0]["text"].as_str().unwrap().to_string());
+        match response["result"]["_meta"]["nextPage"].as_str() {
+            Some(next) => uri = next.to_string(),
+            None => break,
+        }
+    }
+    assert!(pages.len() > 1);
+    assert_eq!(link["pages"], pages.len());
+    assert_eq!(pages.concat(), full);
+
+    let (_, listed) = session.request("resources/list", serde_json::json!({})).await;
+    assert_eq!(listed["result"]["resources"][0]["uri"], link["uri"]);
+
+    for unknown in ["context://responses/99", "context://responses/1?page=0", "file:///etc/passwd"] {
+        let (_, response) = session.request("resources/read", serde_json::json!({"uri": unknown})).await;
+        assert_eq!(response["error"]["code"], -32002, "{unknown}");
+    }
+}
+
+#[tokio::test]
+async fn spilled_bytes_are_bounded_per_session() {
+    let tmp = tempfile::tempdir().unwrap();
+    let (_, full) = Session::start(responses_config(tmp.path(), None)).await.resolve().await;
+
+    // Room for two results: each new one drops the oldest.
+    let max_spilled_bytes = full.len() * 2 + 1;
+    let config = responses_config(
+        tmp.path(),
+        Some(serde_json::json!({"max_bytes": 4096, "overflow": "resource", "max_spilled_bytes": max_spilled_bytes})),
+    );
+    let mut session = Session::start(config).await;
+    let mut uris = Vec::new();
+    for _ in 0..3 {
+        let (_, content) = session.resolve_content().await;
+        uris.push(spilled_resource(&content)["uri"].clone());
+    }
+    let (_, listed) = session.request("resources/list", serde_json::json!({})).await;
+    let listed: Vec<&serde_json::Value> = listed["result"]["resources"].as_array().unwrap().iter().map(|r| &r["uri"]).collect();
+    assert_eq!(listed, [&uris[1], &uris[2]]);
+    let (_, dropped) = session.request("resources/read", serde_json::json!({"uri": uris[0]})).await;
+    assert_eq!(dropped["error"]["code"], -32002);
+
+    // A result larger than the whole allowance is truncated instead.
+    let config = responses_config(
+        tmp.path(),
+        Some(serde_json::json!({"max_bytes": 4096, "overflow": "resource", "max_spilled_bytes": 8192})),
+    );
+    let (line, text) = Session::start(config).await.resolve().await;
+    assert!(line.len() < 4096, "{}", line.len());
+    let truncated: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(truncated["truncated"], true);
+}
+
+#[test]
+fn tiny_caps_are_rejected() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config_path = tmp.path().join("config.json");
+    fs::write(&config_path, r#"{"responses": {"max_bytes": 100}}"#).unwrap();
+    let err = ServerConfig::default().with_config_file(&config_path).unwrap_err();
+    assert!(err.contains("responses.max_bytes must be at least 4096"), "{err}");
+
+    fs::write(&config_path, r#"{"responses": {"max_bytes": 8192, "max_spilled_bytes": 4096}}"#).unwrap();
+    let err = ServerConfig::default().with_config_file(&config_path).unwrap_err();
+    assert!(err.contains("responses.max_spilled_bytes must be at least max_bytes"), "{err}");
+}
@@ -0,0 +1,183 @@
+//! Tests for configurable tokenizers: rank-file loading, BPE counting, the
+//! config file, and `context.resolve` charging with a chosen tokenizer.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use base64::Engine;
+use mcp_context_server::config::ServerConfig;
+use mcp_context_server::handlers;
+use mcp_context_server::protocol::ResolveContextParams;
+use mcp_context_server::tokenizer::BpeTokenizer;
+
+use common::{build_cache_with, write_config};
+
+/// Write a tiktoken rank file with every single byte plus merges that build
+/// the word "deploy".
+fn write_ranks(path: &Path) {
+    let mut tokens: Vec<Vec<u8>> = (0..=255u8).map(|b| vec![b]).collect();
+    for merge in ["de", "pl", "oy", "ploy", "deploy"] {
+        tokens.push(merge.as_bytes().to_vec());
+    }
+
+    let engine = base64::engine::general_purpose::STANDARD;
+    let lines: Vec<String> = tokens
+        .iter()
+        .enumerate()
+        .map(|(rank, token)| format!("{} {rank}", engine.encode(token)))
+        .collect();
+    fs::write(path, lines.join("\n")).unwrap();
+}
+
+/// The `docs` cache: one document whose words BPE-encode differently from
+/// the estimate.
+const DOCS: &[(&str, &str)] = &[("deploy.md", "deploy deploy xyz")];
+
+fn resolve_params(tokenizer: Option<&str>) -> ResolveContextParams {
+    ResolveContextParams {
+        cache: "docs".to_string(),
+        query: "deploy".to_string(),
+        budget: 1000.into(),
+        tokenizer: tokenizer.map(str::to_string),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn bpe_counts_merged_tokens() {
+    let tmp = tempfile::tempdir().unwrap();
+    let ranks = tmp.path().join("tiny.tiktoken");
+    write_ranks(&ranks);
+
+    let bpe = BpeTokenizer::load("tiny", &ranks, None).unwrap();
+    assert_eq!(bpe.count(""), 0);
+    assert_eq!(bpe.count("deploy"), 1);
+    // " deploy" is one piece; the leading space has no merge.
+    assert_eq!(bpe.count(" deploy"), 2);
+    assert_eq!(bpe.count("xyz"), 3);
+    // "deploy" + " deploy" + " xyz"
+    assert_eq!(bpe.count("deploy deploy xyz"), 1 + 2 + 4);
+}
+
+#[test]
+fn bpe_rejects_malformed_rank_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    let ranks = tmp.path().join("bad.tiktoken");
+    fs::write(&ranks, "ZGU= 0\nnot-a-rank-line\n").unwrap();
+
+    let err = BpeTokenizer::load("bad", &ranks, None).err().unwrap();
+    assert!(err.to_string().contains("line 2"), "unexpected error: {err}");
+}
+
+#[test]
+fn config_file_rejects_unknown_and_reserved_names() {
+    let tmp = tempfile::tempdir().unwrap();
+    write_ranks(&tmp.path().join("tiny.tiktoken"));
+
+    let unknown = write_config(
+        tmp.path(),
+        serde_json::json!({ "caches": { "docs": { "tokenizer": "missing" } } }),
+    );
+    assert!(ServerConfig::default().with_config_file(&unknown).is_err());
+
+    let reserved = write_config(
+        tmp.path(),
+        serde_json::json!({
+            "tokenizers": { "estimate": { "format": "tiktoken", "path": "tiny.tiktoken" } }
+        }),
+    );
+    assert!(ServerConfig::default().with_config_file(&reserved).is_err());
+}
+
+#[tokio::test]
+async fn resolve_charges_with_requested_tokenizer() {
+    let tmp = tempfile::tempdir().unwrap();
+    write_ranks(&tmp.path().join("tiny.tiktoken"));
+    let config_path = write_config(
+        tmp.path(),
+        serde_json::json!({
+            "tokenizers": { "tiny": { "format": "tiktoken", "path": "tiny.tiktoken" } }
+        }),
+    );
+    let cache_root = tmp.path().join("caches");
+    build_cache_with(&cache_root.join("docs"), DOCS);
+
+    let config = ServerConfig {
+        cache_root,
+        ..ServerConfig::default()
+    }
+    .with_config_file(&config_path)
+    .unwrap();
+
+    let result = handlers::resolve_context::handle(resolve_params(Some("tiny")), &config).await;
+    assert!(!result.is_error, "Resolve should succeed: {}", result.content[0].text);
+    let value: serde_json::Value = serde_json::from_str(&result.content[0].text).unwrap();
+
+    assert_eq!(value["selection"]["tokenizer"], "tiny");
+    assert_eq!(value["documents"][0]["tokens"].as_u64().unwrap(), 7);
+    assert_eq!(value["selection"]["tokens_used"].as_u64().unwrap(), 7);
+
+    // The built-in estimate can be requested explicitly and is reported too.
+    let result = handlers::resolve_context::handle(resolve_params(Some("estimate")), &config).await;
+    let value: serde_json::Value = serde_json::from_str(&result.content[0].text).unwrap();
+    assert_eq!(value["selection"]["tokenizer"], "estimate");
+    assert_eq!(value["documents"][0]["tokens"].as_u64().unwrap(), 5);
+}
+
+#[tokio::test]
+async fn resolve_uses_cache_default_tokenizer() {
+    let tmp = tempfile::tempdir().unwrap();
+    write_ranks(&tmp.path().join("tiny.tiktoken"));
+    let config_path = write_config(
+        tmp.path(),
+        serde_json::json!({
+            "tokenizers": { "tiny": { "format": "tiktoken", "path": "tiny.tiktoken" } },
+            "caches": { "docs": { "tokenizer": "tiny" } }
+        }),
+    );
+    let cache_root = tmp.path().join("caches");
+    build_cache_with(&cache_root.join("docs"), DOCS);
+
+    let config = ServerConfig {
+        cache_root,
+        ..ServerConfig::default()
+    }
+    .with_config_file(&config_path)
+    .unwrap();
+
+    let result = handlers::resolve_context::handle(resolve_params(None), &config).await;
+    let value: serde_json::Value = serde_json::from_str(&result.content[0].text).unwrap();
+    assert_eq!(value["selection"]["tokenizer"], "tiny");
+    assert_eq!(value["documents"][0]["tokens"].as_u64().unwrap(), 7);
+}
+
+#[tokio::test]
+async fn resolve_rejects_unknown_tokenizer() {
+    let tmp = tempfile::tempdir().unwrap();
+    build_cache_with(&tmp.path().join("docs"), DOCS);
+    let config = ServerConfig {
+        cache_root: tmp.path().to_path_buf(),
+        ..ServerConfig::default()
+    };
+
+    let result = handlers::resolve_context::handle(resolve_params(Some("gpt-9")), &config).await;
+    assert!(result.is_error);
+    let err: serde_json::Value = serde_json::from_str(&result.content[0].text).unwrap();
+    assert_eq!(err, serde_json::json!({"error": {"code": "invalid_params", "message": "Parameters are invalid"}}));
+}
+
+#[tokio::test]
+async fn resolve_without_tokenizer_is_unchanged() {
+    let tmp = tempfile::tempdir().unwrap();
+    build_cache_with(&tmp.path().join("docs"), DOCS);
+    let config = ServerConfig {
+        cache_root: tmp.path().to_path_buf(),
+        ..ServerConfig::default()
+    };
+
+    let result = handlers::resolve_context::handle(resolve_params(None), &config).await;
+    let value: serde_json::Value = serde_json::from_str(&result.content[0].text).unwrap();
+    assert!(value["selection"].get("tokenizer").is_none());
+}